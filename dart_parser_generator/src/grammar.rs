@@ -5,7 +5,7 @@ pub const START_SYMBOL: &'static str = "LibraryDeclaration";
 pub const EPSILON: &'static str = "[EMPTY]";
 pub const END: &'static str = "[END]";
 
-const DART_GRAMMARS: [&'static str; 136] = [
+const DART_GRAMMARS: [&'static str; 153] = [
 // Variables
 "InitializedVariableDeclaration ::= DeclaredIdentifier
     |/ DeclaredIdentifier '=' Expression
@@ -57,13 +57,30 @@ const DART_GRAMMARS: [&'static str; 136] = [
     |/ 'late' 'final' Identifier
     |/ 'late' 'final' Type Identifier",
 // Classes
-"ClassDeclaration ::= 'class' Identifier '{' '}'
-    |/ 'class' Identifier '{' ClassDeclarationInternal '}'",
+"ClassDeclaration ::= 'class' Identifier ClassBody
+    |/ 'class' Identifier Superclass ClassBody
+    |/ 'abstract' 'class' Identifier ClassBody
+    |/ 'abstract' 'class' Identifier Superclass ClassBody",
+"Superclass ::= 'extends' TypeNotVoid",
+"ClassBody ::= '{' '}'
+    |/ '{' ClassDeclarationInternal '}'",
 "ClassDeclarationInternal ::= ClassMemberDeclaration
     |/ ClassDeclarationInternal ClassMemberDeclaration",
 "ClassMemberDeclaration ::= Declaration ';'
-    |/ MemberImpl",
-"MemberImpl ::= FunctionSignature FunctionBody",
+    |/ MemberImpl
+    |/ MetadataList MemberImpl
+    |/ FunctionSignature ';'
+    |/ MetadataList FunctionSignature ';'",
+"MemberImpl ::= FunctionSignature FunctionBody
+    |/ FunctionSignature Initializers FunctionBody
+    |/ FunctionSignature Initializers ';'
+    |/ GetterSignature FunctionBody
+    |/ OperatorSignature FunctionBody",
+"Initializers ::= ':' 'super' Arguments",
+"GetterSignature ::= 'get' Identifier
+    |/ Type 'get' Identifier",
+"OperatorSignature ::= 'operator' '==' FormalParameterList
+    |/ Type 'operator' '==' FormalParameterList",
 "Declaration ::= 'var' InitializedIdentifierList
     |/ Type InitializedIdentifierList
     |/ 'late' 'var' InitializedIdentifierList
@@ -79,7 +96,7 @@ const DART_GRAMMARS: [&'static str; 136] = [
 "ExpressionNotBrace ::= SelectorExpressionNotBrace AssignmentOperator Expression
     |/ ConditionalExpressionNotBrace
     |/ ThrowExpression",
-"AssignmentOperator ::= '=' |/ '*=' |/ '/=' |/ '~/=' |/ '%=' |/ '+=' |/ '-=' |/ '<<=' |/ '>>=' |/ '&=' |/ '^=' |/ '|=' |/ '??='",
+"AssignmentOperator ::= '=' |/ '*=' |/ '/=' |/ '~/=' |/ '%=' |/ '+=' |/ '-=' |/ '<<=' |/ '>>=' |/ '>>>=' |/ '&=' |/ '^=' |/ '|=' |/ '??='",
 "ExpressionOpt ::= [EMPTY]
     |/ Expression",
 "ExpressionList ::= Expression
@@ -183,7 +200,7 @@ const DART_GRAMMARS: [&'static str; 136] = [
     |/ ShiftExpression ShiftOperator AdditiveExpression",
 "ShiftExpressionNotBrace ::= AdditiveExpressionNotBrace
     |/ ShiftExpressionNotBrace ShiftOperator AdditiveExpression",
-"ShiftOperator ::= '<<' |/ '>>'",
+"ShiftOperator ::= '<<' |/ '>>' |/ '>>>'",
 "AdditiveExpression ::= AdditiveExpression '+' MultiplicativeExpression
     |/ AdditiveExpression '-' MultiplicativeExpression
     |/ MultiplicativeExpression",
@@ -289,26 +306,56 @@ const DART_GRAMMARS: [&'static str; 136] = [
 "ContinueStatement ::= 'continue' ';'
     |/ 'continue' Identifier ';'",
 // Libraries and Scripts
-"LibraryDeclaration ::= LibraryImportList TopLevelDeclarationList",
+"LibraryDeclaration ::= LibraryDirectiveList TopLevelDeclarationList",
 "TopLevelDeclarationList ::= [EMPTY]
     |/ TopLevelDeclarationList TopLevelDeclaration",
 "TopLevelDeclaration ::= ClassDeclaration
     |/ TopFunctionDeclaration
-    |/ TopVariableDeclaration",
-"LibraryImportList ::= [EMPTY]
-    |/ LibraryImportList LibraryImport",
-"LibraryImport ::= 'import' Uri ';'
-    |/ 'import' Uri 'as' Identifier ';'
-    |/ 'import' Uri CombinatorList ';'
-    |/ 'import' Uri 'as' Identifier CombinatorList ';'",
+    |/ TopVariableDeclaration
+    |/ TopExternalDeclaration
+    |/ MetadataList ClassDeclaration
+    |/ MetadataList TopFunctionDeclaration",
+"MetadataList ::= Metadata
+    |/ MetadataList Metadata",
+"Metadata ::= '@' DottedIdentifierList
+    |/ '@' DottedIdentifierList Arguments",
+"LibraryDirectiveList ::= [EMPTY]
+    |/ LibraryDirectiveList LibraryDirective",
+"LibraryDirective ::= LibraryName
+    |/ LibraryImport
+    |/ LibraryExport
+    |/ PartDirective
+    |/ PartHeader",
+"LibraryName ::= 'library' ';'
+    |/ 'library' DottedIdentifierList ';'",
+"LibraryImport ::= 'import' ConfigurableUri ';'
+    |/ 'import' ConfigurableUri 'as' Identifier ';'
+    |/ 'import' ConfigurableUri CombinatorList ';'
+    |/ 'import' ConfigurableUri 'as' Identifier CombinatorList ';'
+    |/ 'import' ConfigurableUri 'deferred' 'as' Identifier ';'
+    |/ 'import' ConfigurableUri 'deferred' 'as' Identifier CombinatorList ';'",
+"LibraryExport ::= 'export' ConfigurableUri ';'
+    |/ 'export' ConfigurableUri CombinatorList ';'",
+"PartDirective ::= 'part' Uri ';'",
+"PartHeader ::= 'part' 'of' Uri ';'
+    |/ 'part' 'of' DottedIdentifierList ';'",
 "Uri ::= 'STRING_BEGIN_END'",
+"ConfigurableUri ::= Uri
+    |/ Uri ConfigurationUriList",
+"ConfigurationUriList ::= ConfigurationUri
+    |/ ConfigurationUriList ConfigurationUri",
+"ConfigurationUri ::= 'if' '(' DottedIdentifierList ')' Uri",
 "CombinatorList ::= Combinator
     |/ CombinatorList Combinator",
 "Combinator ::= 'show' IdentifierList
     |/ 'hide' IdentifierList",
 "IdentifierList ::= Identifier
     |/ IdentifierList ',' Identifier",
+"DottedIdentifierList ::= Identifier
+    |/ DottedIdentifierList '.' Identifier",
 "TopFunctionDeclaration ::= FunctionSignature FunctionBody",
+"TopExternalDeclaration ::= 'external' FunctionSignature ';'
+    |/ 'external' Type Identifier ';'",
 "TopVariableDeclaration ::= 'var' InitializedIdentifierList ';'
     |/ Type InitializedIdentifierList ';'
     |/ 'late' 'var' InitializedIdentifierList ';'
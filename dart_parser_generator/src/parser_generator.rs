@@ -277,6 +277,9 @@ fn calc_transition_map(
                             // priortize shift by default
                             if *token == "else" { continue }
                             if *token == "import" { continue }
+                            if *token == "export" { continue }
+                            if *token == "part" { continue }
+                            if *token == "library" { continue }
                             if *token == "on" { continue }
                             if *token == "(" && rule.left == "Selector" { continue }
 
@@ -1,12 +1,17 @@
 use std::{cmp, collections::HashMap};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 
+/// 次の命令の引数の上位バイト
+pub const EXTENDED_ARG: u8 = 144;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ByteCode {
     operation: u8,
     operand: u8,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     PopTop,
     RotTwo,
@@ -48,44 +53,45 @@ pub enum OpCode {
     ImportStar,
     PopBlock,
     PopExcept,
-    StoreName(u8),
+    StoreName(u32),
     ForIter(u32),
-    StoreAttr(u8),
-    StoreGlobal(u8),
-    LoadConst(u8),
-    LoadName(u8),
-    BuildTuple(u8),
-    BuildList(u8),
-    BuildSet(u8),
-    BuildMap(u8),
-    LoadAttr(u8),
-    CompareOp(u8),
-    ImportName(u8),
-    ImportFrom(u8),
+    StoreAttr(u32),
+    StoreGlobal(u32),
+    LoadConst(u32),
+    LoadName(u32),
+    BuildTuple(u32),
+    BuildList(u32),
+    BuildSet(u32),
+    BuildMap(u32),
+    LoadAttr(u32),
+    CompareOp(u32),
+    ImportName(u32),
+    ImportFrom(u32),
     // JumpForward(u32),
-    // JumpIfFalseOrPop(u32),
-    // JumpIfTrueOrPop(u32),
+    JumpIfFalseOrPop(u32),
+    JumpIfTrueOrPop(u32),
     JumpAbsolute(u32),
     PopJumpIfFalse(u32),
-    LoadGlobal(u8),
+    LoadGlobal(u32),
+    ContainsOp(u32),
     JumpIfNotExcMatch(u32),
     SetupFinally(u32),
-    LoadFast(u8),
-    StoreFast(u8),
-    RaiseVarargs(u8),
+    LoadFast(u32),
+    StoreFast(u32),
+    RaiseVarargs(u32),
     PopJumpIfTrue(u32),
-    CallFunction(u8),
-    MakeFunction(u8),
-    BuildSlice(u8),
-    CallFunctionKw(u8),
-    BuildConstKeyMap(u8),
-    LoadMethod(u8),
-    CallMethod(u8),
+    CallFunction(u32),
+    MakeFunction(u32),
+    BuildSlice(u32),
+    CallFunctionKw(u32),
+    BuildConstKeyMap(u32),
+    LoadMethod(u32),
+    CallMethod(u32),
 }
 
 impl OpCode {
     pub fn compare_op_from_str(op: &str) -> Result<OpCode> {
-        let operand: u8 = match op {
+        let operand: u32 = match op {
             "<" => 0,
             "<=" => 1,
             "==" => 2,
@@ -156,12 +162,13 @@ impl OpCode {
             OpCode::ImportName(_) => 108,
             OpCode::ImportFrom(_) => 109,
             // OpCode::JumpForward(_) => 110,
-            // OpCode::JumpIfFalseOrPop(_) => 111,
-            // OpCode::JumpIfTrueOrPop(_) => 112,
+            OpCode::JumpIfFalseOrPop(_) => 111,
+            OpCode::JumpIfTrueOrPop(_) => 112,
             OpCode::JumpAbsolute(_) => 113,
             OpCode::PopJumpIfFalse(_) => 114,
             OpCode::PopJumpIfTrue(_) => 115,
             OpCode::LoadGlobal(_) => 116,
+            OpCode::ContainsOp(_) => 118,
             OpCode::JumpIfNotExcMatch(_) => 121,
             OpCode::SetupFinally(_) => 122,
             OpCode::LoadFast(_) => 124,
@@ -177,24 +184,179 @@ impl OpCode {
         }
     }
 
-    pub fn resolve(&self, label_table: &HashMap<u32, u8>) -> ByteCode {
+    /// 命令の値と引数から命令を復元する
+    pub fn decode(operation: u8, arg: u32) -> Option<OpCode> {
+        let op = match operation {
+            1 => OpCode::PopTop,
+            2 => OpCode::RotTwo,
+            3 => OpCode::RotThree,
+            4 => OpCode::DupTop,
+            5 => OpCode::DupTopTwo,
+            6 => OpCode::RotFour,
+            11 => OpCode::UnaryNegative,
+            12 => OpCode::UnaryNot,
+            15 => OpCode::UnaryInvert,
+            20 => OpCode::BinaryMultiply,
+            22 => OpCode::BinaryModulo,
+            23 => OpCode::BinaryAdd,
+            24 => OpCode::BinarySubtract,
+            25 => OpCode::BinarySubScr,
+            26 => OpCode::BinaryFloorDivide,
+            27 => OpCode::BinaryTrueDivide,
+            28 => OpCode::InplaceFloorDivide,
+            29 => OpCode::InplaceTrueDivide,
+            48 => OpCode::Reraise,
+            55 => OpCode::InplaceAdd,
+            56 => OpCode::InplaceSubtract,
+            57 => OpCode::InplaceMultiply,
+            59 => OpCode::InplaceModulo,
+            60 => OpCode::StoreSubScr,
+            62 => OpCode::BinaryLShift,
+            63 => OpCode::BinaryRShift,
+            64 => OpCode::BinaryAnd,
+            65 => OpCode::BinaryXor,
+            66 => OpCode::BinaryOr,
+            68 => OpCode::GetIter,
+            71 => OpCode::LoadBuildClass,
+            75 => OpCode::InplaceLShift,
+            76 => OpCode::InplaceRShift,
+            77 => OpCode::InplaceAnd,
+            78 => OpCode::InplaceXor,
+            79 => OpCode::InplaceOr,
+            83 => OpCode::ReturnValue,
+            84 => OpCode::ImportStar,
+            87 => OpCode::PopBlock,
+            89 => OpCode::PopExcept,
+            90 => OpCode::StoreName(arg),
+            93 => OpCode::ForIter(arg),
+            95 => OpCode::StoreAttr(arg),
+            97 => OpCode::StoreGlobal(arg),
+            100 => OpCode::LoadConst(arg),
+            101 => OpCode::LoadName(arg),
+            102 => OpCode::BuildTuple(arg),
+            103 => OpCode::BuildList(arg),
+            104 => OpCode::BuildSet(arg),
+            105 => OpCode::BuildMap(arg),
+            106 => OpCode::LoadAttr(arg),
+            107 => OpCode::CompareOp(arg),
+            108 => OpCode::ImportName(arg),
+            109 => OpCode::ImportFrom(arg),
+            111 => OpCode::JumpIfFalseOrPop(arg),
+            112 => OpCode::JumpIfTrueOrPop(arg),
+            113 => OpCode::JumpAbsolute(arg),
+            114 => OpCode::PopJumpIfFalse(arg),
+            115 => OpCode::PopJumpIfTrue(arg),
+            116 => OpCode::LoadGlobal(arg),
+            118 => OpCode::ContainsOp(arg),
+            121 => OpCode::JumpIfNotExcMatch(arg),
+            122 => OpCode::SetupFinally(arg),
+            124 => OpCode::LoadFast(arg),
+            125 => OpCode::StoreFast(arg),
+            130 => OpCode::RaiseVarargs(arg),
+            131 => OpCode::CallFunction(arg),
+            132 => OpCode::MakeFunction(arg),
+            133 => OpCode::BuildSlice(arg),
+            141 => OpCode::CallFunctionKw(arg),
+            156 => OpCode::BuildConstKeyMap(arg),
+            160 => OpCode::LoadMethod(arg),
+            161 => OpCode::CallMethod(arg),
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// CPythonのdisモジュールと同じ命令名
+    pub fn name(&self) -> &'static str {
+        match *self {
+            OpCode::PopTop => "POP_TOP",
+            OpCode::RotTwo => "ROT_TWO",
+            OpCode::RotThree => "ROT_THREE",
+            OpCode::DupTop => "DUP_TOP",
+            OpCode::DupTopTwo => "DUP_TOP_TWO",
+            OpCode::RotFour => "ROT_FOUR",
+            OpCode::UnaryNegative => "UNARY_NEGATIVE",
+            OpCode::UnaryNot => "UNARY_NOT",
+            OpCode::UnaryInvert => "UNARY_INVERT",
+            OpCode::BinaryMultiply => "BINARY_MULTIPLY",
+            OpCode::BinaryModulo => "BINARY_MODULO",
+            OpCode::BinaryAdd => "BINARY_ADD",
+            OpCode::BinarySubtract => "BINARY_SUBTRACT",
+            OpCode::BinarySubScr => "BINARY_SUBSCR",
+            OpCode::BinaryFloorDivide => "BINARY_FLOOR_DIVIDE",
+            OpCode::BinaryTrueDivide => "BINARY_TRUE_DIVIDE",
+            OpCode::InplaceFloorDivide => "INPLACE_FLOOR_DIVIDE",
+            OpCode::InplaceTrueDivide => "INPLACE_TRUE_DIVIDE",
+            OpCode::Reraise => "RERAISE",
+            OpCode::InplaceAdd => "INPLACE_ADD",
+            OpCode::InplaceSubtract => "INPLACE_SUBTRACT",
+            OpCode::InplaceMultiply => "INPLACE_MULTIPLY",
+            OpCode::InplaceModulo => "INPLACE_MODULO",
+            OpCode::StoreSubScr => "STORE_SUBSCR",
+            OpCode::BinaryLShift => "BINARY_LSHIFT",
+            OpCode::BinaryRShift => "BINARY_RSHIFT",
+            OpCode::BinaryAnd => "BINARY_AND",
+            OpCode::BinaryXor => "BINARY_XOR",
+            OpCode::BinaryOr => "BINARY_OR",
+            OpCode::GetIter => "GET_ITER",
+            OpCode::LoadBuildClass => "LOAD_BUILD_CLASS",
+            OpCode::InplaceLShift => "INPLACE_LSHIFT",
+            OpCode::InplaceRShift => "INPLACE_RSHIFT",
+            OpCode::InplaceAnd => "INPLACE_AND",
+            OpCode::InplaceXor => "INPLACE_XOR",
+            OpCode::InplaceOr => "INPLACE_OR",
+            OpCode::ReturnValue => "RETURN_VALUE",
+            OpCode::ImportStar => "IMPORT_STAR",
+            OpCode::PopBlock => "POP_BLOCK",
+            OpCode::PopExcept => "POP_EXCEPT",
+            OpCode::StoreName(_) => "STORE_NAME",
+            OpCode::ForIter(_) => "FOR_ITER",
+            OpCode::StoreAttr(_) => "STORE_ATTR",
+            OpCode::StoreGlobal(_) => "STORE_GLOBAL",
+            OpCode::LoadConst(_) => "LOAD_CONST",
+            OpCode::LoadName(_) => "LOAD_NAME",
+            OpCode::BuildTuple(_) => "BUILD_TUPLE",
+            OpCode::BuildList(_) => "BUILD_LIST",
+            OpCode::BuildSet(_) => "BUILD_SET",
+            OpCode::BuildMap(_) => "BUILD_MAP",
+            OpCode::LoadAttr(_) => "LOAD_ATTR",
+            OpCode::CompareOp(_) => "COMPARE_OP",
+            OpCode::ImportName(_) => "IMPORT_NAME",
+            OpCode::ImportFrom(_) => "IMPORT_FROM",
+            OpCode::JumpIfFalseOrPop(_) => "JUMP_IF_FALSE_OR_POP",
+            OpCode::JumpIfTrueOrPop(_) => "JUMP_IF_TRUE_OR_POP",
+            OpCode::JumpAbsolute(_) => "JUMP_ABSOLUTE",
+            OpCode::PopJumpIfFalse(_) => "POP_JUMP_IF_FALSE",
+            OpCode::PopJumpIfTrue(_) => "POP_JUMP_IF_TRUE",
+            OpCode::LoadGlobal(_) => "LOAD_GLOBAL",
+            OpCode::ContainsOp(_) => "CONTAINS_OP",
+            OpCode::JumpIfNotExcMatch(_) => "JUMP_IF_NOT_EXC_MATCH",
+            OpCode::SetupFinally(_) => "SETUP_FINALLY",
+            OpCode::LoadFast(_) => "LOAD_FAST",
+            OpCode::StoreFast(_) => "STORE_FAST",
+            OpCode::RaiseVarargs(_) => "RAISE_VARARGS",
+            OpCode::CallFunction(_) => "CALL_FUNCTION",
+            OpCode::MakeFunction(_) => "MAKE_FUNCTION",
+            OpCode::BuildSlice(_) => "BUILD_SLICE",
+            OpCode::CallFunctionKw(_) => "CALL_FUNCTION_KW",
+            OpCode::BuildConstKeyMap(_) => "BUILD_CONST_KEY_MAP",
+            OpCode::LoadMethod(_) => "LOAD_METHOD",
+            OpCode::CallMethod(_) => "CALL_METHOD",
+        }
+    }
+
+    /// 命令の引数。分岐する命令ではラベルを返す
+    pub fn argument(&self) -> Option<u32> {
         match *self {
             // OpCode::JumpForward(v) |
-            // OpCode::JumpIfFalseOrPop(v) |
-            // OpCode::JumpIfTrueOrPop(v) |
-            OpCode::JumpAbsolute(v)
+            OpCode::JumpIfFalseOrPop(v)
+            | OpCode::JumpIfTrueOrPop(v)
+            | OpCode::JumpAbsolute(v)
             | OpCode::PopJumpIfFalse(v)
             | OpCode::PopJumpIfTrue(v)
             | OpCode::JumpIfNotExcMatch(v)
             | OpCode::SetupFinally(v)
-            | OpCode::ForIter(v) => {
-                let operand = *label_table.get(&v).unwrap();
-                ByteCode {
-                    operation: self.get_value(),
-                    operand: operand,
-                }
-            }
-            OpCode::StoreName(v)
+            | OpCode::ForIter(v)
+            | OpCode::StoreName(v)
             | OpCode::StoreAttr(v)
             | OpCode::LoadConst(v)
             | OpCode::LoadName(v)
@@ -208,6 +370,7 @@ impl OpCode {
             | OpCode::CallFunction(v)
             | OpCode::LoadAttr(v)
             | OpCode::CompareOp(v)
+            | OpCode::ContainsOp(v)
             | OpCode::ImportName(v)
             | OpCode::ImportFrom(v)
             | OpCode::LoadMethod(v)
@@ -217,14 +380,8 @@ impl OpCode {
             | OpCode::BuildSlice(v)
             | OpCode::BuildConstKeyMap(v)
             | OpCode::BuildTuple(v)
-            | OpCode::CallFunctionKw(v) => ByteCode {
-                operation: self.get_value(),
-                operand: v,
-            },
-            _ => ByteCode {
-                operation: self.get_value(),
-                operand: 0,
-            },
+            | OpCode::CallFunctionKw(v) => Some(v),
+            _ => None,
         }
     }
 
@@ -265,7 +422,8 @@ impl OpCode {
             | OpCode::InplaceAnd
             | OpCode::InplaceXor
             | OpCode::InplaceOr
-            | OpCode::CompareOp(_) => -1,
+            | OpCode::CompareOp(_)
+            | OpCode::ContainsOp(_) => -1,
 
             OpCode::BinarySubScr => -1,
             OpCode::StoreSubScr => -3,
@@ -290,37 +448,43 @@ impl OpCode {
 
             OpCode::ReturnValue => -1,
             OpCode::PopBlock => 0,
-            OpCode::PopExcept => -1,
+            // 例外ハンドラに入るときに保存された例外情報の3つを取り除く
+            OpCode::PopExcept => -3,
 
             OpCode::LoadConst(_)
             | OpCode::LoadName(_)
             | OpCode::LoadFast(_)
             | OpCode::LoadGlobal(_) => 1,
 
-            OpCode::CallMethod(n) | OpCode::CallFunction(n) | OpCode::CallFunctionKw(n) => {
-                -(n as i32)
-            }
+            OpCode::CallFunction(n) => -(n as i32),
+            OpCode::CallMethod(n) | OpCode::CallFunctionKw(n) => -(n as i32) - 1,
 
             OpCode::SetupFinally(_) => {
+                // 例外発生時にはハンドラの先頭で例外情報が6つ積まれる
                 if jump {
-                    1
+                    6
                 } else {
                     0
                 }
             }
 
-            OpCode::Reraise => -1,
+            OpCode::Reraise => -3,
             OpCode::RaiseVarargs(v) => -(v as i32),
 
             // OpCode::JumpForward(_) |
             OpCode::JumpAbsolute(_) => 0,
-            // OpCode::JumpIfFalseOrPop(_) |
-            // OpCode::JumpIfTrueOrPop(_) => if jump { 0 } else { -1 },
+            OpCode::JumpIfFalseOrPop(_) | OpCode::JumpIfTrueOrPop(_) => {
+                if jump {
+                    0
+                } else {
+                    -1
+                }
+            }
             OpCode::PopJumpIfFalse(_) | OpCode::PopJumpIfTrue(_) => -1,
-            OpCode::JumpIfNotExcMatch(_) => 0,
+            OpCode::JumpIfNotExcMatch(_) => -2,
 
             OpCode::MakeFunction(v) => {
-                0 - ((v & 0x01) != 0) as i32
+                -1 - ((v & 0x01) != 0) as i32
                     - ((v & 0x02) != 0) as i32
                     - ((v & 0x04) != 0) as i32
                     - ((v & 0x08) != 0) as i32
@@ -328,17 +492,35 @@ impl OpCode {
 
             OpCode::BuildConstKeyMap(v) => -(v as i32),
 
-            OpCode::LoadAttr(_) | OpCode::LoadMethod(_) => 0,
+            OpCode::LoadAttr(_) => 0,
+            OpCode::LoadMethod(_) => 1,
             OpCode::StoreAttr(_) => -2,
 
             OpCode::LoadBuildClass => 1,
 
             OpCode::GetIter => 0,
-            OpCode::ForIter(_) => 1,
+            // イテレータが尽きるとイテレータを取り除いて分岐する
+            OpCode::ForIter(_) => {
+                if jump {
+                    -1
+                } else {
+                    1
+                }
+            }
         }
     }
 }
 
+impl ByteCode {
+    pub fn operation(&self) -> u8 {
+        self.operation
+    }
+
+    pub fn operand(&self) -> u8 {
+        self.operand
+    }
+}
+
 pub fn compile_code(operation_list: &[ByteCode]) -> Vec<u8> {
     let code_size = operation_list.len() * 2;
     let mut result = vec![0u8; code_size];
@@ -351,12 +533,362 @@ pub fn compile_code(operation_list: &[ByteCode]) -> Vec<u8> {
     result
 }
 
-pub fn calc_stack_size(operation_list: &[OpCode]) -> i32 {
-    let mut max_size = 0;
-    let mut current_size = 0;
-    for op in operation_list {
-        current_size += op.stack_effect(true);
-        max_size = cmp::max(max_size, current_size);
+/// compile_codeの逆変換
+pub fn decode_code(code: &[u8]) -> Result<Vec<ByteCode>> {
+    ensure!(
+        code.len().is_multiple_of(2),
+        "odd length of bytecode: {}",
+        code.len()
+    );
+    Ok(code
+        .chunks(2)
+        .map(|v| ByteCode {
+            operation: v[0],
+            operand: v[1],
+        })
+        .collect())
+}
+
+/// 分岐先のラベル(命令列を解決した後は分岐先の位置)
+pub fn jump_label(op: &OpCode) -> Option<u32> {
+    match *op {
+        OpCode::JumpIfFalseOrPop(v)
+        | OpCode::JumpIfTrueOrPop(v)
+        | OpCode::JumpAbsolute(v)
+        | OpCode::PopJumpIfFalse(v)
+        | OpCode::PopJumpIfTrue(v)
+        | OpCode::JumpIfNotExcMatch(v)
+        | OpCode::SetupFinally(v)
+        | OpCode::ForIter(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// 次の命令からの相対位置で分岐する命令
+pub fn is_relative_jump(op: &OpCode) -> bool {
+    matches!(op, OpCode::SetupFinally(_) | OpCode::ForIter(_))
+}
+
+/// 次の命令に進まない命令
+pub fn is_terminator(op: &OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::JumpAbsolute(_) | OpCode::ReturnValue | OpCode::RaiseVarargs(_) | OpCode::Reraise
+    )
+}
+
+/// i番目の命令が分岐する先の命令の位置
+/// ラベルの表は分岐先の命令の位置を持つ。範囲外や後ろへの相対分岐であればエラーにする
+pub fn jump_target(
+    operation_list: &[OpCode],
+    i: usize,
+    label_table: &HashMap<u32, u32>,
+) -> Result<Option<usize>> {
+    let op = &operation_list[i];
+    let Some(label) = jump_label(op) else {
+        return Ok(None);
+    };
+    let Some(&target) = label_table.get(&label) else {
+        bail!("jump to undefined label {} at instruction {}", label, i);
+    };
+    let target = target as usize;
+    ensure!(
+        target < operation_list.len(),
+        "jump to out of range instruction {} at instruction {}",
+        target,
+        i
+    );
+    ensure!(
+        !is_relative_jump(op) || target > i,
+        "relative jump must go forward at instruction {}",
+        i
+    );
+    Ok(Some(target))
+}
+
+// 引数を表すのに必要な命令の数(EXTENDED_ARGを含む)
+fn instruction_count(argument: u32) -> u32 {
+    match argument {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xff_ffff => 3,
+        _ => 4,
+    }
+}
+
+/// 分岐先を解決し、引数が1バイトに収まらない命令の前にEXTENDED_ARGを置く
+/// EXTENDED_ARGで命令が伸びると分岐先の位置も変わるので、長さが変わらなくなるまで繰り返す
+pub fn assemble(
+    operation_list: &[OpCode],
+    label_table: &HashMap<u32, u32>,
+) -> Result<Vec<ByteCode>> {
+    let mut targets = vec![];
+    for i in 0..operation_list.len() {
+        targets.push(jump_target(operation_list, i, label_table)?);
+    }
+
+    // 各命令のEXTENDED_ARGを含めた命令の数
+    let mut counts = vec![1u32; operation_list.len()];
+    let arguments = loop {
+        let mut offsets = vec![0u32];
+        for count in &counts {
+            offsets.push(offsets.last().unwrap() + count);
+        }
+        let arguments: Vec<u32> = operation_list
+            .iter()
+            .zip(&targets)
+            .enumerate()
+            .map(|(i, (op, target))| match *target {
+                // 相対分岐は命令の次の位置から数える
+                Some(target) if is_relative_jump(op) => (offsets[target] - offsets[i + 1]) * 2,
+                Some(target) => offsets[target] * 2,
+                None => op.argument().unwrap_or(0),
+            })
+            .collect();
+        // 縮めると繰り返しが止まらないことがあるので、伸ばすだけにする
+        let grown: Vec<u32> = counts
+            .iter()
+            .zip(&arguments)
+            .map(|(&count, &argument)| cmp::max(count, instruction_count(argument)))
+            .collect();
+        if grown == counts {
+            break arguments;
+        }
+        counts = grown;
+    };
+
+    let mut result = vec![];
+    for ((op, count), argument) in operation_list.iter().zip(counts).zip(arguments) {
+        for i in (1..count).rev() {
+            result.push(ByteCode {
+                operation: EXTENDED_ARG,
+                operand: (argument >> (8 * i)) as u8,
+            });
+        }
+        result.push(ByteCode {
+            operation: op.get_value(),
+            operand: argument as u8,
+        });
+    }
+    Ok(result)
+}
+
+/// 基本ブロック
+/// operation_list[start..end]の命令を分岐せずに順に実行する
+struct BasicBlock {
+    start: usize,
+    end: usize,
+}
+
+/// 解決済みのジャンプラベルから作る制御フローグラフ
+struct ControlFlowGraph<'a> {
+    operation_list: &'a [OpCode],
+    blocks: Vec<BasicBlock>,
+    // 命令の位置 → その命令から始まる基本ブロック
+    block_index: HashMap<usize, usize>,
+    // ジャンプ命令の位置 → 分岐先の命令の位置
+    jump_targets: HashMap<usize, usize>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    fn new(operation_list: &'a [OpCode], label_table: &HashMap<u32, u32>) -> Result<Self> {
+        let mut jump_targets = HashMap::new();
+        let mut leaders = vec![false; operation_list.len() + 1];
+        leaders[0] = true;
+        for (i, op) in operation_list.iter().enumerate() {
+            if let Some(target) = jump_target(operation_list, i, label_table)? {
+                jump_targets.insert(i, target);
+                leaders[target] = true;
+                leaders[i + 1] = true;
+            } else if is_terminator(op) {
+                leaders[i + 1] = true;
+            }
+        }
+
+        let mut blocks = vec![];
+        let mut block_index = HashMap::new();
+        let mut start = 0;
+        leaders[operation_list.len()] = true;
+        for (end, &is_leader) in leaders.iter().enumerate().skip(1) {
+            if is_leader {
+                block_index.insert(start, blocks.len());
+                blocks.push(BasicBlock { start, end });
+                start = end;
+            }
+        }
+
+        Ok(ControlFlowGraph {
+            operation_list,
+            blocks,
+            block_index,
+            jump_targets,
+        })
+    }
+}
+
+/// 分岐と例外ハンドラを考慮してco_stacksizeを求める
+/// CPythonのcompile.cのstackdepth()と同じく、各基本ブロックの開始時の深さを伝播させる
+/// 合流点で深さが食い違う、深さが負になる、範囲外に分岐する、
+/// 末尾から実行が抜け出すといった不正なバイトコードはエラーにする
+pub fn calc_stack_size(operation_list: &[OpCode], label_table: &HashMap<u32, u32>) -> Result<u32> {
+    if operation_list.is_empty() {
+        return Ok(0);
+    }
+    let graph = ControlFlowGraph::new(operation_list, label_table)?;
+
+    let mut start_depth: Vec<Option<i32>> = vec![None; graph.blocks.len()];
+    let mut worklist = vec![0];
+    start_depth[0] = Some(0);
+    let mut max_depth = 0;
+
+    // 基本ブロックの開始時の深さを記録し、未訪問なら作業リストに加える
+    let visit = |block: usize,
+                 depth: i32,
+                 start_depth: &mut Vec<Option<i32>>,
+                 worklist: &mut Vec<usize>|
+     -> Result<()> {
+        match start_depth[block] {
+            Some(v) => ensure!(
+                v == depth,
+                "inconsistent stack depth at instruction {}: {} and {}",
+                graph.blocks[block].start,
+                v,
+                depth
+            ),
+            None => {
+                start_depth[block] = Some(depth);
+                worklist.push(block);
+            }
+        }
+        Ok(())
+    };
+
+    while let Some(block) = worklist.pop() {
+        let BasicBlock { start, end } = graph.blocks[block];
+        let mut depth = start_depth[block].unwrap();
+        for i in start..end {
+            let op = &graph.operation_list[i];
+            if let Some(&target) = graph.jump_targets.get(&i) {
+                let target_depth = depth + op.stack_effect(true);
+                ensure!(
+                    target_depth >= 0,
+                    "negative stack depth at instruction {}",
+                    i
+                );
+                max_depth = cmp::max(max_depth, target_depth);
+                visit(
+                    graph.block_index[&target],
+                    target_depth,
+                    &mut start_depth,
+                    &mut worklist,
+                )?;
+            }
+            depth += op.stack_effect(false);
+            ensure!(depth >= 0, "negative stack depth at instruction {}", i);
+            max_depth = cmp::max(max_depth, depth);
+        }
+
+        if is_terminator(&graph.operation_list[end - 1]) {
+            continue;
+        }
+        ensure!(
+            end < graph.operation_list.len(),
+            "execution falls off the end of the code"
+        );
+        visit(
+            graph.block_index[&end],
+            depth,
+            &mut start_depth,
+            &mut worklist,
+        )?;
+    }
+
+    Ok(max_depth as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{assemble, calc_stack_size, OpCode, EXTENDED_ARG};
+
+    #[test]
+    fn decode_opcode() {
+        // decodeはget_valueの逆変換になっている
+        for operation in 0..=255u8 {
+            if let Some(op) = OpCode::decode(operation, 1) {
+                assert_eq!(op.get_value(), operation, "{}", op.name());
+            }
+        }
+        assert_eq!(OpCode::decode(100, 3), Some(OpCode::LoadConst(3)));
+        assert_eq!(OpCode::decode(100, 256), Some(OpCode::LoadConst(256)));
+        assert_eq!(OpCode::decode(113, 300), Some(OpCode::JumpAbsolute(300)));
+        assert_eq!(OpCode::BinarySubScr.name(), "BINARY_SUBSCR");
+    }
+
+    #[test]
+    fn stack_size_follows_branches() {
+        // x = a if c else (b, b, b)
+        let operation_list = vec![
+            OpCode::LoadName(0),
+            OpCode::PopJumpIfFalse(0),
+            OpCode::LoadName(1),
+            OpCode::JumpAbsolute(1),
+            OpCode::LoadName(2),
+            OpCode::LoadName(2),
+            OpCode::LoadName(2),
+            OpCode::BuildTuple(3),
+            OpCode::StoreName(3),
+            OpCode::LoadConst(0),
+            OpCode::ReturnValue,
+        ];
+        let label_table = HashMap::from([(0, 4), (1, 8)]);
+        assert_eq!(calc_stack_size(&operation_list, &label_table).unwrap(), 3);
+    }
+
+    #[test]
+    fn reject_invalid_code() {
+        // 合流点で深さが食い違う
+        let operation_list = vec![
+            OpCode::LoadName(0),
+            OpCode::PopJumpIfFalse(0),
+            OpCode::LoadName(1),
+            OpCode::LoadConst(0),
+            OpCode::ReturnValue,
+        ];
+        let label_table = HashMap::from([(0, 3)]);
+        assert!(calc_stack_size(&operation_list, &label_table).is_err());
+
+        // 範囲外に分岐する
+        let label_table = HashMap::from([(0, 5)]);
+        assert!(calc_stack_size(&operation_list, &label_table).is_err());
+
+        // 空のスタックから取り出す
+        let operation_list = vec![OpCode::PopTop, OpCode::LoadConst(0), OpCode::ReturnValue];
+        assert!(calc_stack_size(&operation_list, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn assemble_extended_arg() {
+        // 256番目以降の定数はEXTENDED_ARGで上位バイトを渡す
+        let operation_list = vec![OpCode::LoadConst(0x1234), OpCode::ReturnValue];
+        let code: Vec<(u8, u8)> = assemble(&operation_list, &HashMap::new())
+            .unwrap()
+            .iter()
+            .map(|v| (v.operation(), v.operand()))
+            .collect();
+        assert_eq!(code, vec![(EXTENDED_ARG, 0x12), (100, 0x34), (83, 0)]);
+
+        // 伸びた命令の分だけ分岐先の位置もずれる
+        let mut operation_list = vec![OpCode::SetupFinally(0), OpCode::JumpAbsolute(1)];
+        operation_list.extend((0..130).map(|_| OpCode::LoadConst(300)));
+        operation_list.push(OpCode::ReturnValue);
+        let label_table = HashMap::from([(0, 132), (1, 132)]);
+        let code = assemble(&operation_list, &label_table).unwrap();
+        // RETURN_VALUEは4 + 4 + 130 * 4 = 528バイト目。相対分岐はSETUP_FINALLYの次から数える
+        assert_eq!((code[0].operation(), code[0].operand()), (EXTENDED_ARG, 2));
+        assert_eq!((code[1].operation(), code[1].operand()), (122, 12));
+        assert_eq!((code[2].operation(), code[2].operand()), (EXTENDED_ARG, 2));
+        assert_eq!((code[3].operation(), code[3].operand()), (113, 16));
     }
-    max_size
 }
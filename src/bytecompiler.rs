@@ -4,49 +4,207 @@ use std::rc::Rc;
 use std::time::SystemTime;
 use std::{cell::RefCell, collections::HashMap};
 
-use anyhow::{bail, ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 
-use crate::build_from_file;
-use crate::bytecode::ByteCode;
+use crate::bytecode::{self, ByteCode};
+use crate::cache;
+use crate::disasm;
 use crate::executioncontext::{BlockContext, ExecutionContext, VariableScope};
+use crate::library::scan_library_names;
+use crate::optimizer;
 use crate::parser::node::{
-    CollectionElement, DartType, FunctionParamSignature, LibraryImport, NodeExpression,
-    NodeStatement, Selector,
+    CallParameter, CollectionElement, Combinator, DartType, FunctionParamSignature, Identifier,
+    LibraryImport, NodeExpression, NodeStatement, Selector,
 };
+use crate::resolver::Resolver;
+use crate::runtime;
 use crate::{bytecode::OpCode, pyobject::PyObject};
+use crate::{BuildOptions, NumericSemantics, OptLevel};
 
 use self::runclass::run_class;
 use self::runfunction::run_function;
+use self::statictype::DeclaredTypes;
 
 pub mod runclass;
 pub mod runfunction;
 pub mod runroot;
+pub mod statictype;
+
+// show/hideで絞り込まれた、インポートする名前
+enum ImportFilter<'a> {
+    All,
+    Show(Vec<&'a str>),
+    Hide(Vec<&'a str>),
+}
+
+impl<'a> ImportFilter<'a> {
+    // コンビネータを順に適用する
+    fn from_combinators(combinator_list: &'a [Combinator]) -> Self {
+        let mut filter = ImportFilter::All;
+        for combinator in combinator_list {
+            let targets: Vec<&str> = combinator.target_list.iter().map(|v| v.value).collect();
+            filter = match (filter, combinator.is_show) {
+                (ImportFilter::All, true) => ImportFilter::Show(targets),
+                (ImportFilter::All, false) => ImportFilter::Hide(targets),
+                (ImportFilter::Show(names), true) => {
+                    ImportFilter::Show(names.into_iter().filter(|v| targets.contains(v)).collect())
+                }
+                (ImportFilter::Show(names), false) => {
+                    ImportFilter::Show(names.into_iter().filter(|v| !targets.contains(v)).collect())
+                }
+                (ImportFilter::Hide(names), true) => {
+                    ImportFilter::Show(targets.into_iter().filter(|v| !names.contains(v)).collect())
+                }
+                (ImportFilter::Hide(mut names), false) => {
+                    names.extend(targets);
+                    ImportFilter::Hide(names)
+                }
+            };
+        }
+        filter
+    }
+}
 
 struct DefaultScope {
     break_label: u32,
     continue_label: Option<u32>,
+    // ジャンプ先で有効なblock_stackの深さ
+    break_depth: usize,
+    continue_depth: usize,
+}
+
+// 制御フローの途中で抜けるときに後始末が必要なブロック
+// CPython 3.9のcompile.cのfblockに相当する
+#[derive(Clone, Copy)]
+enum FrameBlock<'value> {
+    // スタックにイテレータやswitchの値が積まれている
+    StackValue,
+    // try-onのtry節
+    TryExcept,
+    // try-finallyのtry節
+    FinallyTry(&'value NodeStatement<'value>),
+    // 例外発生時に実行されるfinally節
+    FinallyEnd,
+    // on節、catch節
+    HandlerCleanup,
 }
 
 pub struct ByteCompiler<'ctx, 'value> {
     pub byte_operations: RefCell<Vec<OpCode>>,
     context_stack: Vec<Rc<RefCell<dyn ExecutionContext + 'ctx>>>,
-    jump_label_table: RefCell<HashMap<u32, u8>>,
+    jump_label_table: RefCell<HashMap<u32, u32>>,
     jump_label_key_index: RefCell<u32>,
     default_scope_stack: Vec<DefaultScope>,
-    break_label_table: HashMap<String, u32>,
-    continue_label_table: HashMap<String, u32>,
+    break_label_table: HashMap<String, (u32, usize)>,
+    continue_label_table: HashMap<String, (u32, usize)>,
+    block_stack: Vec<FrameBlock<'value>>,
     source: &'value str,
+    options: &'value BuildOptions,
+    resolver: Rc<Resolver>,
+    declared_types: Rc<DeclaredTypes>,
+    // --emit=bytecodeで出力する命令列
+    listing: Option<&'value RefCell<String>>,
 }
 
 impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
-    fn compile_import(&mut self, node: &LibraryImport, time_start_build: SystemTime) -> Result<()> {
-        let uri = &node.uri;
-
+    fn compile_import(
+        &mut self,
+        node: &'value LibraryImport,
+        time_start_build: SystemTime,
+    ) -> Result<()> {
         let identifier = match &node.identifier {
             Some(v) => Some(v.value),
             None => None,
         };
+        let filter = ImportFilter::from_combinators(&node.combinator_list);
+
+        // 条件付きインポートでは、どの候補の名前も使えるものとする
+        for uri in std::iter::once(node.uri).chain(node.configuration_list.iter().map(|v| v.uri)) {
+            self.declare_imported_names(uri, identifier, &filter);
+        }
+
+        if node.configuration_list.is_empty() {
+            if let Some(path_splitted) =
+                self.resolve_import_uri(node, node.uri, time_start_build)?
+            {
+                self.push_library_import(node, &path_splitted, identifier, &filter);
+            }
+            return Ok(());
+        }
+
+        // 条件付きインポート
+        // import "A.dart" if (python.cupy) "B.dart";
+        // → try:
+        //       import cupy
+        //   except ImportError:
+        //       from A import *
+        //   else:
+        //       from B import *
+        let label_end = self.gen_jump_label();
+        for configuration in &node.configuration_list {
+            let test: Vec<&str> = configuration.test.iter().map(|v| v.value).collect();
+            let path_splitted =
+                self.resolve_import_uri(node, configuration.uri, time_start_build)?;
+            match test[..] {
+                ["dart", "library", library] => {
+                    // dart.library.xxxはコンパイル時に決まる
+                    if library == "core" || runtime::find_dart_library(library).is_some() {
+                        if let Some(path_splitted) = path_splitted {
+                            self.push_library_import(node, &path_splitted, identifier, &filter);
+                        }
+                        self.set_jump_label_value(label_end);
+                        return Ok(());
+                    }
+                }
+                ["python", ..] if test.len() > 1 => {
+                    let label_except = self.gen_jump_label();
+                    let label_reraise = self.gen_jump_label();
+                    let label_next = self.gen_jump_label();
 
+                    self.push_op(OpCode::SetupFinally(label_except));
+                    self.push_import_module(&test[1..]);
+                    self.push_op(OpCode::PopTop);
+                    self.push_op(OpCode::PopBlock);
+                    if let Some(path_splitted) = path_splitted {
+                        self.push_library_import(node, &path_splitted, identifier, &filter);
+                    }
+                    self.push_op(OpCode::JumpAbsolute(label_end));
+
+                    // ImportError以外はそのまま送出する
+                    self.set_jump_label_value(label_except);
+                    self.push_op(OpCode::DupTop);
+                    self.push_load_var(&"ImportError".to_string());
+                    self.push_op(OpCode::JumpIfNotExcMatch(label_reraise));
+                    self.push_op(OpCode::PopTop);
+                    self.push_op(OpCode::PopTop);
+                    self.push_op(OpCode::PopTop);
+                    self.push_op(OpCode::PopExcept);
+                    self.push_op(OpCode::JumpAbsolute(label_next));
+                    self.set_jump_label_value(label_reraise);
+                    self.push_op(OpCode::Reraise);
+                    self.set_jump_label_value(label_next);
+                }
+                _ => bail!("unsupported import condition: {}", test.join(".")),
+            }
+        }
+
+        // どの条件も満たさない場合
+        if let Some(path_splitted) = self.resolve_import_uri(node, node.uri, time_start_build)? {
+            self.push_library_import(node, &path_splitted, identifier, &filter);
+        }
+        self.set_jump_label_value(label_end);
+        Ok(())
+    }
+
+    // uriをインポートするモジュールのパスに変換する
+    // Dartのファイルであれば合わせてビルドする
+    // 何もインポートしなくてよい場合はNoneを返す
+    fn resolve_import_uri(
+        &self,
+        node: &LibraryImport,
+        uri: &'value str,
+        time_start_build: SystemTime,
+    ) -> Result<Option<Vec<&'value str>>> {
         // uri形式
         // import "elaphe/A/B.d.dart";
         // → from A.B import *
@@ -58,6 +216,18 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
         // → import A as B
         // 相対インポートは禁止
         // Schemeは禁止
+        if let Some(library) = runtime::dart_library_name(uri) {
+            // import "dart:math";
+            // → from elaphe_dart_math import *
+            if library == "core" {
+                // dart:coreは常に読み込まれている
+                return Ok(None);
+            }
+            let (module_name, source) = runtime::find_dart_library(library)
+                .with_context(|| format!("unsupported dart library: {}", uri))?;
+            check_shown_names(node, &runtime::dart_library_exports(source))?;
+            return Ok(Some(vec![module_name]));
+        }
         ensure!(!uri.contains(":"), "invalid import uri: {}", uri);
 
         // ignore relative representations
@@ -75,7 +245,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
         path_splitted.drain(0..relative_count);
 
         if path_splitted.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
 
         if path_splitted[0] == "elaphe" {
@@ -84,7 +254,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             assert!(last.ends_with(".d.dart"));
             if last == "core.d.dart" {
                 // ignore core.d.dart
-                return Ok(());
+                return Ok(None);
             }
 
             // remove "elaphe"
@@ -95,17 +265,9 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             path_splitted.push(last);
         } else {
             // import Dart modules
+            // 変更されたモジュールとその依存元だけをビルドし直す
             let output = Path::new(&uri).with_extension("pyc");
-            if !output.exists() {
-                build_from_file(output.to_str().unwrap(), &uri, time_start_build, false)?;
-            } else {
-                let metadata = fs::metadata(&output).unwrap();
-                let modified = metadata.modified().unwrap();
-                if time_start_build > modified {
-                    // if the file is not modified, rebuild it
-                    build_from_file(output.to_str().unwrap(), &uri, time_start_build, false)?;
-                }
-            }
+            cache::build_module(uri, &output, time_start_build, self.options)?;
 
             let last = path_splitted.pop().unwrap();
             assert!(last.ends_with(".dart"));
@@ -115,8 +277,101 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             path_splitted.push(last);
         }
 
-        match identifier {
-            None => {
+        // スタブやソースがあれば、showする名前が宣言されているか確かめる
+        if let Ok(source) = fs::read_to_string(uri) {
+            if let Some(declared_names) = scan_library_names(&source) {
+                let declared_names: Vec<&str> = declared_names.iter().map(|v| v.as_str()).collect();
+                check_shown_names(node, &declared_names)?;
+            }
+        }
+
+        Ok(Some(path_splitted))
+    }
+
+    // インポートで使えるようになる名前を名前解決に登録する
+    fn declare_imported_names(&self, uri: &str, identifier: Option<&str>, filter: &ImportFilter) {
+        if let Some(identifier) = identifier {
+            // ライブラリの名前はプレフィックスを通して参照する
+            self.resolver.declare(identifier);
+            return;
+        }
+        if let ImportFilter::Show(names) = filter {
+            self.resolver.declare_all(names);
+            return;
+        }
+        let names: Option<Vec<String>> = match runtime::dart_library_name(uri) {
+            Some("core") => Some(vec![]),
+            Some(library) => runtime::find_dart_library(library).map(|(_, source)| {
+                runtime::dart_library_exports(source)
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect()
+            }),
+            None if uri.ends_with("elaphe/core.d.dart") => Some(vec![]),
+            None => fs::read_to_string(uri)
+                .ok()
+                .and_then(|source| scan_library_names(&source)),
+        };
+        match (names, filter) {
+            (Some(names), ImportFilter::Hide(hidden)) => self
+                .resolver
+                .declare_all(names.iter().filter(|v| !hidden.contains(&v.as_str()))),
+            (Some(names), _) => self.resolver.declare_all(names),
+            // スタブが見つからないなど、公開している名前が分からない
+            (None, _) => self.resolver.open(),
+        }
+    }
+
+    fn push_library_import(
+        &self,
+        node: &LibraryImport,
+        path_splitted: &[&str],
+        identifier: Option<&str>,
+        filter: &ImportFilter,
+    ) {
+        if node.deferred {
+            self.push_deferred_import(path_splitted, identifier.unwrap(), filter);
+        } else {
+            self.push_import(path_splitted, identifier, filter);
+        }
+    }
+
+    // import "A/B.dart" deferred as C;
+    // → C = deferred_library('A.B', None, ())
+    // モジュールはC.loadLibrary()が呼ばれた時点で読み込む
+    fn push_deferred_import(
+        &self,
+        path_splitted: &[&str],
+        identifier: &str,
+        filter: &ImportFilter,
+    ) {
+        self.push_load_runtime("deferred_library");
+        self.push_load_const(PyObject::new_string(path_splitted.join("."), false));
+        match filter {
+            ImportFilter::All => {
+                self.push_load_const(PyObject::None(false));
+                self.push_load_const(new_name_tuple(&[]));
+            }
+            ImportFilter::Show(names) => {
+                self.push_load_const(new_name_tuple(names));
+                self.push_load_const(new_name_tuple(&[]));
+            }
+            ImportFilter::Hide(names) => {
+                self.push_load_const(PyObject::None(false));
+                self.push_load_const(new_name_tuple(names));
+            }
+        }
+        self.push_op(OpCode::CallFunction(3));
+        let p = (**self.context_stack.last().unwrap())
+            .borrow_mut()
+            .declare_variable(&identifier.to_string());
+        self.push_op(OpCode::StoreName(p));
+    }
+
+    // path_splittedで表されるモジュールをインポートする
+    fn push_import(&self, path_splitted: &[&str], identifier: Option<&str>, filter: &ImportFilter) {
+        match (identifier, filter) {
+            (None, ImportFilter::All) => {
                 // from A.B import *
 
                 // 0を積む
@@ -137,7 +392,62 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
 
                 self.push_op(OpCode::ImportStar);
             }
-            Some(v) => {
+            (None, ImportFilter::Show(names)) => {
+                // from A.B import a, b
+
+                // 0を積む
+                self.push_load_const(PyObject::Int(0, false));
+
+                // ('a', 'b')を積む
+                self.push_load_const(new_name_tuple(names));
+
+                // 名前でインポート
+                let import_name = path_splitted.join(".");
+                let import_name_p = (**self.context_stack.last().unwrap())
+                    .borrow_mut()
+                    .register_or_get_name(&import_name);
+                self.push_op(OpCode::ImportName(import_name_p));
+
+                for name in names {
+                    let p = (**self.context_stack.last().unwrap())
+                        .borrow_mut()
+                        .register_or_get_name(&name.to_string());
+                    self.push_op(OpCode::ImportFrom(p));
+                    let p = (**self.context_stack.last().unwrap())
+                        .borrow_mut()
+                        .declare_variable(&name.to_string());
+                    self.push_op(OpCode::StoreName(p));
+                }
+                self.push_op(OpCode::PopTop);
+            }
+            (None, ImportFilter::Hide(names)) => {
+                // import_hiding(globals(), A.B, ('a', 'b'))
+                self.push_load_runtime("import_hiding");
+                self.push_load_var(&"globals".to_string());
+                self.push_op(OpCode::CallFunction(0));
+                self.push_import_module(path_splitted);
+                self.push_load_const(new_name_tuple(names));
+                self.push_op(OpCode::CallFunction(3));
+                self.push_op(OpCode::PopTop);
+            }
+            (Some(v), ImportFilter::Show(_) | ImportFilter::Hide(_)) => {
+                // C = import_prefixed(A.B, ('a', ), ()) or import_prefixed(A.B, None, ('b', ))
+                self.push_load_runtime("import_prefixed");
+                self.push_import_module(path_splitted);
+                if let ImportFilter::Show(names) = filter {
+                    self.push_load_const(new_name_tuple(names));
+                    self.push_load_const(new_name_tuple(&[]));
+                } else if let ImportFilter::Hide(names) = filter {
+                    self.push_load_const(PyObject::None(false));
+                    self.push_load_const(new_name_tuple(names));
+                }
+                self.push_op(OpCode::CallFunction(3));
+                let p = (**self.context_stack.last().unwrap())
+                    .borrow_mut()
+                    .declare_variable(&v.to_string());
+                self.push_op(OpCode::StoreName(p));
+            }
+            (Some(v), ImportFilter::All) => {
                 // 0を積む
                 self.push_load_const(PyObject::Int(0, false));
 
@@ -180,7 +490,18 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 }
             }
         }
-        Ok(())
+    }
+
+    // A.Bのモジュール自体を積む
+    fn push_import_module(&self, path_splitted: &[&str]) {
+        // 空でないfromlistを渡すと、パッケージではなくA.Bが返される
+        self.push_load_const(PyObject::Int(0, false));
+        self.push_load_const(new_name_tuple(&["*"]));
+        let import_name = path_splitted.join(".");
+        let import_name_p = (**self.context_stack.last().unwrap())
+            .borrow_mut()
+            .register_or_get_name(&import_name);
+        self.push_op(OpCode::ImportName(import_name_p));
     }
 
     fn compile_expr(&mut self, node: &'value NodeExpression) -> Result<()> {
@@ -223,18 +544,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                         "==" | "!=" | ">=" | ">" | "<=" | "<" => {
                             self.push_op(OpCode::compare_op_from_str(operator)?)
                         }
-                        "<<" => self.push_op(OpCode::BinaryLShift),
-                        ">>" => self.push_op(OpCode::BinaryRShift),
-                        "&" => self.push_op(OpCode::BinaryAnd),
-                        "^" => self.push_op(OpCode::BinaryXor),
-                        "|" => self.push_op(OpCode::BinaryOr),
-                        "+" => self.push_op(OpCode::BinaryAdd),
-                        "-" => self.push_op(OpCode::BinarySubtract),
-                        "*" => self.push_op(OpCode::BinaryMultiply),
-                        "/" => self.push_op(OpCode::BinaryTrueDivide),
-                        "%" => self.push_op(OpCode::BinaryModulo),
-                        "~/" => self.push_op(OpCode::BinaryFloorDivide),
-                        _ => bail!("unknown binary operator: {}", *operator),
+                        _ => self.push_arithmetic_op(operator, right, false)?,
                     }
                 }
             }
@@ -268,6 +578,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 child,
             } => {
                 if let NodeExpression::Identifier { identifier } = &**child {
+                    self.resolve_identifier(identifier);
                     let value = identifier.value.to_string();
                     if *is_prefix {
                         // 前置
@@ -300,20 +611,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 // isinstance(child, type_test.)
                 self.push_load_var(&"isinstance".to_string());
                 self.compile_expr(child)?;
-                if let DartType::Named {
-                    type_name,
-                    type_arguments: _,
-                    is_nullable: _,
-                } = &type_test.dart_type
-                {
-                    let name = type_name.identifier.value;
-                    let p = (**self.context_stack.last().unwrap())
-                        .borrow_mut()
-                        .register_or_get_name(&name.to_string());
-                    self.push_op(OpCode::LoadName(p));
-                } else {
-                    bail!("Invalid Test Expression");
-                }
+                self.push_load_type(&type_test.dart_type)?;
                 self.push_op(OpCode::CallFunction(2));
                 if !type_test.check_matching {
                     self.push_op(OpCode::UnaryNot);
@@ -339,6 +637,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
 
                         match &**left {
                             NodeExpression::Identifier { identifier } => {
+                                self.resolve_identifier(identifier);
                                 let value = identifier.value.to_string();
                                 self.push_store_var(&value);
                             }
@@ -369,27 +668,15 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                             _ => bail!("Invalid lhs value."),
                         }
                     }
-                    "*=" | "/=" | "~/=" | "%=" | "+=" | "-=" | "<<=" | ">>=" | "&=" | "^="
-                    | "|=" => match &**left {
+                    "*=" | "/=" | "~/=" | "%=" | "+=" | "-=" | "<<=" | ">>=" | ">>>=" | "&="
+                    | "^=" | "|=" => match &**left {
                         NodeExpression::Identifier { identifier } => {
+                            self.resolve_identifier(identifier);
                             let value = identifier.value.to_string();
                             self.push_load_var(&value);
 
                             self.compile_expr(right)?;
-                            match *operator {
-                                "*=" => self.push_op(OpCode::InplaceMultiply),
-                                "/=" => self.push_op(OpCode::InplaceTrueDivide),
-                                "~/=" => self.push_op(OpCode::InplaceFloorDivide),
-                                "%=" => self.push_op(OpCode::InplaceModulo),
-                                "+=" => self.push_op(OpCode::InplaceAdd),
-                                "-=" => self.push_op(OpCode::InplaceSubtract),
-                                "<<=" => self.push_op(OpCode::InplaceLShift),
-                                ">>=" => self.push_op(OpCode::InplaceRShift),
-                                "&=" => self.push_op(OpCode::InplaceAnd),
-                                "^=" => self.push_op(OpCode::InplaceXor),
-                                "|=" => self.push_op(OpCode::InplaceOr),
-                                _ => (),
-                            }
+                            self.push_arithmetic_op(operator.trim_end_matches('='), right, true)?;
                             self.push_op(OpCode::DupTop);
 
                             self.push_store_var(&value);
@@ -414,20 +701,11 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                                     self.push_op(OpCode::LoadAttr(p));
 
                                     self.compile_expr(right)?;
-                                    match *operator {
-                                        "*=" => self.push_op(OpCode::InplaceMultiply),
-                                        "/=" => self.push_op(OpCode::InplaceTrueDivide),
-                                        "~/=" => self.push_op(OpCode::InplaceFloorDivide),
-                                        "%=" => self.push_op(OpCode::InplaceModulo),
-                                        "+=" => self.push_op(OpCode::InplaceAdd),
-                                        "-=" => self.push_op(OpCode::InplaceSubtract),
-                                        "<<=" => self.push_op(OpCode::InplaceLShift),
-                                        ">>=" => self.push_op(OpCode::InplaceRShift),
-                                        "&=" => self.push_op(OpCode::InplaceAnd),
-                                        "^=" => self.push_op(OpCode::InplaceXor),
-                                        "|=" => self.push_op(OpCode::InplaceOr),
-                                        _ => (),
-                                    }
+                                    self.push_arithmetic_op(
+                                        operator.trim_end_matches('='),
+                                        right,
+                                        true,
+                                    )?;
                                     self.push_op(OpCode::DupTop);
 
                                     self.push_op(OpCode::RotThree);
@@ -439,20 +717,11 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                                     self.push_op(OpCode::BinarySubScr);
 
                                     self.compile_expr(right)?;
-                                    match *operator {
-                                        "*=" => self.push_op(OpCode::InplaceMultiply),
-                                        "/=" => self.push_op(OpCode::InplaceTrueDivide),
-                                        "~/=" => self.push_op(OpCode::InplaceFloorDivide),
-                                        "%=" => self.push_op(OpCode::InplaceModulo),
-                                        "+=" => self.push_op(OpCode::InplaceAdd),
-                                        "-=" => self.push_op(OpCode::InplaceSubtract),
-                                        "<<=" => self.push_op(OpCode::InplaceLShift),
-                                        ">>=" => self.push_op(OpCode::InplaceRShift),
-                                        "&=" => self.push_op(OpCode::InplaceAnd),
-                                        "^=" => self.push_op(OpCode::InplaceXor),
-                                        "|=" => self.push_op(OpCode::InplaceOr),
-                                        _ => (),
-                                    }
+                                    self.push_arithmetic_op(
+                                        operator.trim_end_matches('='),
+                                        right,
+                                        true,
+                                    )?;
                                     self.push_op(OpCode::DupTop);
                                     self.push_op(OpCode::RotFour);
 
@@ -465,6 +734,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                     },
                     "??=" => match &**left {
                         NodeExpression::Identifier { identifier } => {
+                            self.resolve_identifier(identifier);
                             let value = identifier.value.to_string();
 
                             self.push_load_var(&value);
@@ -560,10 +830,10 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             NodeExpression::StringLiteral { str_list } => {
                 for (i, single_str) in str_list.iter().enumerate() {
                     for (i, str) in single_str.string_list.iter().enumerate() {
-                        // string + str(expr) + string + str(expr) + ...
+                        // string + dartStr(expr) + string + dartStr(expr) + ...
                         if i > 0 {
-                            // str(expr)
-                            self.push_load_var(&"str".to_string());
+                            // dartStr(expr)
+                            self.push_load_runtime("dartStr");
                             self.compile_expr(&single_str.interpolation_list[i - 1])?;
                             self.push_op(OpCode::CallFunction(1));
 
@@ -596,7 +866,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 self.push_load_const(PyObject::None(false));
             }
             NodeExpression::ListLiteral { element_list } => {
-                let size = element_list.len() as u8;
+                let size = element_list.len() as u32;
                 for elem in element_list {
                     match elem {
                         CollectionElement::ExpressionElement { expr } => {
@@ -612,7 +882,16 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 }
                 self.push_op(OpCode::BuildList(size));
             }
-            NodeExpression::SetOrMapLiteral { element_list } => {
+            NodeExpression::SetOrMapLiteral {
+                element_list,
+                is_const,
+            } => {
+                // 要素が全て定数のconstな集合はfrozensetの定数にする
+                let constant = if *is_const {
+                    constant_set(element_list)?
+                } else {
+                    None
+                };
                 let first_elem = element_list.first();
                 let is_map = if let Some(elem) = first_elem {
                     match elem {
@@ -626,8 +905,10 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                     true
                 };
 
-                if is_map {
-                    let size = element_list.len() as u8;
+                if let Some(set) = constant {
+                    self.push_load_const(set);
+                } else if is_map {
+                    let size = element_list.len() as u32;
                     for elem in element_list {
                         match elem {
                             CollectionElement::ExpressionElement { expr: _ } => {
@@ -644,7 +925,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                     }
                     self.push_op(OpCode::BuildMap(size));
                 } else {
-                    let size = element_list.len() as u8;
+                    let size = element_list.len() as u32;
                     for elem in element_list {
                         match elem {
                             CollectionElement::ExpressionElement { expr } => {
@@ -662,34 +943,53 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 }
             }
             NodeExpression::Identifier { identifier } => {
-                let value = identifier.value.to_string();
-                self.push_load_var(&value);
+                self.push_load_identifier(identifier);
             }
             NodeExpression::Selector { child, selector } => {
+                // {1, 2, 3}.contains(x) → x in frozenset({1, 2, 3})
+                if let (
+                    NodeExpression::SetOrMapLiteral { element_list, .. },
+                    Selector::Method {
+                        identifier,
+                        arguments,
+                    },
+                ) = (&**child, selector)
+                {
+                    if identifier.value == "contains" && arguments.len() == 1 {
+                        if let (None, Some(set)) =
+                            (&arguments[0].identifier, constant_set(element_list)?)
+                        {
+                            self.compile_expr(&arguments[0].expr)?;
+                            self.push_load_const(set);
+                            self.push_op(OpCode::ContainsOp(0));
+                            return Ok(());
+                        }
+                    }
+                }
+
                 // 右辺値として処理される場合
+                if let (NodeExpression::Identifier { identifier }, Selector::Args { .. }) =
+                    (&**child, selector)
+                {
+                    self.check_instantiation(identifier);
+                }
                 self.compile_expr(child)?;
 
                 match selector {
                     Selector::Args { args } => {
-                        let mut name_list: Vec<&str> = vec![];
-                        for param in args {
-                            self.compile_expr(&param.expr)?;
-                            if let Some(v) = &param.identifier {
-                                name_list.push(v.value);
-                            }
-                        }
-                        if !name_list.is_empty() {
-                            self.push_load_const(PyObject::SmallTuple {
-                                children: name_list
-                                    .iter()
-                                    .map(|v| PyObject::new_string(v.to_string(), false))
-                                    .collect(),
-                                add_ref: false,
-                            });
-                            self.push_op(OpCode::CallFunctionKw(args.len() as u8));
-                        } else {
-                            self.push_op(OpCode::CallFunction(args.len() as u8));
-                        }
+                        self.push_call_arguments(args, 0)?;
+                    }
+                    Selector::Attr { identifier }
+                        if self.is_core_member_access(child, identifier.value) =>
+                    {
+                        // getprop(child, name)
+                        self.push_load_runtime("getprop");
+                        self.push_op(OpCode::RotTwo);
+                        self.push_load_const(PyObject::new_string(
+                            identifier.value.to_string(),
+                            false,
+                        ));
+                        self.push_op(OpCode::CallFunction(2));
                     }
                     Selector::Attr { identifier } => {
                         let name = identifier.value;
@@ -703,6 +1003,33 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
 
                         self.push_op(OpCode::BinarySubScr);
                     }
+                    Selector::Method {
+                        identifier,
+                        arguments,
+                    } if self.options.numeric_semantics == NumericSemantics::Dart
+                        && identifier.value == "remainder"
+                        && arguments.len() == 1
+                        && arguments[0].identifier.is_none() =>
+                    {
+                        // a.remainder(b) → remainder(a, b)
+                        self.compile_expr(&arguments[0].expr)?;
+                        self.push_load_runtime("remainder");
+                        self.push_op(OpCode::RotThree);
+                        self.push_op(OpCode::CallFunction(2));
+                    }
+                    Selector::Method {
+                        identifier,
+                        arguments,
+                    } if self.is_core_member_access(child, identifier.value) => {
+                        // invoke(child, name, *args)
+                        self.push_load_runtime("invoke");
+                        self.push_op(OpCode::RotTwo);
+                        self.push_load_const(PyObject::new_string(
+                            identifier.value.to_string(),
+                            false,
+                        ));
+                        self.push_call_arguments(arguments, 2)?;
+                    }
                     Selector::Method {
                         identifier,
                         arguments,
@@ -721,16 +1048,16 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                             }
                         }
                         if !name_list.is_empty() {
-                            self.push_load_const(PyObject::SmallTuple {
-                                children: name_list
+                            self.push_load_const(PyObject::new_tuple(
+                                name_list
                                     .iter()
                                     .map(|v| PyObject::new_string(v.to_string(), false))
                                     .collect(),
-                                add_ref: false,
-                            });
-                            self.push_op(OpCode::CallFunctionKw(arguments.len() as u8));
+                                false,
+                            ));
+                            self.push_op(OpCode::CallFunctionKw(arguments.len() as u32));
                         } else {
-                            self.push_op(OpCode::CallMethod(arguments.len() as u8))
+                            self.push_op(OpCode::CallMethod(arguments.len() as u32))
                         }
                     }
                 }
@@ -788,7 +1115,8 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 let label_id = self.gen_jump_label();
 
                 // break用のラベルはこの時点で用意する
-                self.break_label_table.insert(label_str.clone(), label_id);
+                self.break_label_table
+                    .insert(label_str.clone(), (label_id, self.block_stack.len()));
 
                 self.compile_stmt(stmt, Some(&label_str))?;
 
@@ -799,16 +1127,12 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 Some(identifier) => {
                     let label_str = identifier.value;
                     match self.break_label_table.get(label_str) {
-                        Some(v) => {
-                            self.push_op(OpCode::JumpAbsolute(*v));
-                        }
+                        Some(&(label, depth)) => self.push_jump_out(label, depth)?,
                         None => bail!("label {} is not existing in this scope.", label_str),
                     }
                 }
                 None => match self.default_scope_stack.last() {
-                    Some(v) => {
-                        self.push_op(OpCode::JumpAbsolute(v.break_label));
-                    }
+                    Some(v) => self.push_jump_out(v.break_label, v.break_depth)?,
                     None => bail!("break statement is not available here"),
                 },
             },
@@ -816,15 +1140,15 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 Some(identifier) => {
                     let label_str = identifier.value;
                     match self.continue_label_table.get(label_str) {
-                        Some(v) => {
-                            self.push_op(OpCode::JumpAbsolute(*v));
-                        }
+                        Some(&(label, depth)) => self.push_jump_out(label, depth)?,
                         None => bail!("label {} is not existing in this scope.", label_str),
                     }
                 }
                 None => match self.default_scope_stack.last() {
                     Some(v) => match v.continue_label {
-                        Some(continue_label) => self.push_op(OpCode::JumpAbsolute(continue_label)),
+                        Some(continue_label) => {
+                            self.push_jump_out(continue_label, v.continue_depth)?
+                        }
                         None => bail!("continue statement is not available here"),
                     },
                     None => bail!("continue statement is not available here"),
@@ -839,6 +1163,8 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                         self.push_load_const(PyObject::None(false));
                     }
                 }
+                // 戻り値を残したまま、全てのブロックを抜ける
+                self.unwind_blocks(0, true)?;
                 self.push_op(OpCode::ReturnValue);
             }
             NodeStatement::Empty => {}
@@ -850,6 +1176,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                     outer: self.context_stack.last().unwrap().clone(),
                     variables: vec![],
+                    variable_types: HashMap::new(),
                 })));
                 for child in statements {
                     self.compile_stmt(child, None)?;
@@ -857,77 +1184,100 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 self.context_stack.pop();
             }
             NodeStatement::Rethrow => {
-                self.push_op(OpCode::Reraise);
+                // 処理中の例外を再送出する
+                self.push_op(OpCode::RaiseVarargs(0));
             }
             NodeStatement::VariableDeclarationList { decl_list } => {
                 for declaration in decl_list {
+                    let value = declaration.identifier.value.to_string();
+                    let is_global = self.context_stack.last().unwrap().borrow().is_global();
                     match &declaration.expr {
                         Some(e) => {
                             self.compile_expr(e)?;
-                            let value = declaration.identifier.value.to_string();
-                            let position = (**self.context_stack.last().unwrap())
-                                .borrow_mut()
-                                .declare_variable(&value);
-                            if self.context_stack.last().unwrap().borrow().is_global() {
-                                // トップレベル変数の場合
-                                self.push_op(OpCode::StoreName(position));
-                            } else {
-                                // ローカル変数の場合
-                                let local_position = self
-                                    .context_stack
-                                    .last()
-                                    .unwrap()
-                                    .borrow()
-                                    .get_local_variable(&value);
-                                self.push_op(OpCode::StoreFast(local_position));
-                            }
                         }
-                        None => {
-                            let value = declaration.identifier.value.to_string();
+                        None if is_global => {
                             (**self.context_stack.last().unwrap())
                                 .borrow_mut()
                                 .declare_variable(&value);
+                            self.set_variable_type(&value, declaration.dart_type.as_ref(), None);
+                            continue;
+                        }
+                        None => {
+                            // ループ内で宣言された場合も、毎回nullから始まる
+                            self.push_load_const(PyObject::None(false));
                         }
                     }
+                    let position = self.declare_block_variable(&value)?;
+                    self.set_variable_type(
+                        &value,
+                        declaration.dart_type.as_ref(),
+                        declaration.expr.as_deref(),
+                    );
+                    if is_global {
+                        // トップレベル変数の場合
+                        self.push_op(OpCode::StoreName(position));
+                    } else {
+                        // ローカル変数の場合
+                        self.push_op(OpCode::StoreFast(position));
+                    }
                 }
             }
-            NodeStatement::FunctionDeclaration { signature, body } => {
-                self.compile_declare_function(
-                    &signature.name.value.to_string(),
-                    &signature.param,
-                    body,
-                    None,
-                    None,
-                    |_| Ok(()),
-                )?;
+            NodeStatement::FunctionDeclaration {
+                signature,
+                body,
+                metadata_list,
+            } => {
+                let name = signature.name.value.to_string();
+                self.compile_decorated(metadata_list, &name, |compiler| {
+                    compiler
+                        .push_make_function(&name, &signature.param, body, None, None, |_| Ok(()))
+                })?;
+            }
+            NodeStatement::ExternalDeclaration { identifier: _ } => {
+                // 名前は名前解決のためにだけ使う
             }
             NodeStatement::ClassDeclaration {
                 identifier,
                 member_list,
+                metadata_list,
+                is_abstract,
+                superclass,
             } => {
-                self.push_op(OpCode::LoadBuildClass);
-
                 let name = identifier.value.to_string();
-                self.push_load_const(run_class(
-                    &"main.py".to_string(),
-                    &name,
-                    member_list,
-                    self,
-                    self.source,
-                )?);
+                self.compile_decorated(metadata_list, &name, |compiler| {
+                    compiler.push_op(OpCode::LoadBuildClass);
 
-                self.push_load_const(PyObject::new_string(name.clone(), false));
+                    compiler.push_load_const(run_class(
+                        &"main.py".to_string(),
+                        &name,
+                        member_list,
+                        superclass.as_ref(),
+                        compiler,
+                        compiler.source,
+                    )?);
 
-                self.push_op(OpCode::MakeFunction(0));
+                    compiler.push_load_const(PyObject::new_string(name.clone(), false));
 
-                self.push_load_const(PyObject::new_string(name.clone(), false));
+                    compiler.push_op(OpCode::MakeFunction(0));
 
-                self.push_op(OpCode::CallFunction(2));
+                    compiler.push_load_const(PyObject::new_string(name.clone(), false));
 
-                let p = (**self.context_stack.last().unwrap())
-                    .borrow_mut()
-                    .declare_variable(&name);
-                self.push_op(OpCode::StoreName(p));
+                    let mut num_args = 2;
+                    if let Some(superclass) = superclass {
+                        compiler.push_load_type(superclass)?;
+                        num_args += 1;
+                    }
+
+                    if *is_abstract {
+                        // class A(metaclass=ABCMeta)
+                        compiler.push_load_runtime("ABCMeta");
+                        compiler.push_load_const(new_name_tuple(&["metaclass"]));
+                        compiler.push_op(OpCode::CallFunctionKw(num_args + 1));
+                    } else {
+                        compiler.push_op(OpCode::CallFunction(num_args));
+                    }
+                    Ok(())
+                })?;
             }
             NodeStatement::If {
                 condition,
@@ -944,6 +1294,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                         self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                             outer: self.context_stack.last().unwrap().clone(),
                             variables: vec![],
+                            variable_types: HashMap::new(),
                         })));
                         self.compile_stmt(if_true_stmt, None)?;
                         self.context_stack.pop();
@@ -956,6 +1307,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                         self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                             outer: self.context_stack.last().unwrap().clone(),
                             variables: vec![],
+                            variable_types: HashMap::new(),
                         })));
                         self.compile_stmt(if_false_stmt, None)?;
                         self.context_stack.pop();
@@ -981,17 +1333,20 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 let label_end = self.gen_jump_label();
 
                 self.push_op(OpCode::SetupFinally(label_finally));
-                let label_finally_zero = self.byte_operations.borrow().len() as u8;
 
                 // 通常フロー
+                self.block_stack.push(FrameBlock::FinallyTry(block_finally));
                 self.compile_stmt(block_try, None)?;
+                self.block_stack.pop();
                 self.push_op(OpCode::PopBlock);
                 self.compile_stmt(block_finally, None)?;
                 self.push_op(OpCode::JumpAbsolute(label_end));
 
                 // 例外が起きたときのフロー
-                self.set_jump_label_value_offset(label_finally, label_finally_zero);
+                self.set_jump_label_value(label_finally);
+                self.block_stack.push(FrameBlock::FinallyEnd);
                 self.compile_stmt(block_finally, None)?;
+                self.block_stack.pop();
                 self.push_op(OpCode::Reraise);
 
                 self.set_jump_label_value(label_end);
@@ -1004,21 +1359,23 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 let label_end = self.gen_jump_label();
 
                 self.push_op(OpCode::SetupFinally(label_finally));
-                let label_finally_zero = self.byte_operations.borrow().len() as u8;
 
                 // 通常のフロー
+                self.block_stack.push(FrameBlock::TryExcept);
                 self.compile_stmt(block_try, None)?;
+                self.block_stack.pop();
                 self.push_op(OpCode::PopBlock);
                 self.push_op(OpCode::JumpAbsolute(label_end));
 
                 // 例外時のフロー
-                self.set_jump_label_value_offset(label_finally, label_finally_zero);
+                self.set_jump_label_value(label_finally);
                 for on_part in on_part_list {
                     let label_next = self.gen_jump_label();
 
                     self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                         outer: self.context_stack.last().unwrap().clone(),
                         variables: vec![],
+                        variable_types: HashMap::new(),
                     })));
 
                     // catchする型の指定がある場合はloadして検証する
@@ -1030,11 +1387,9 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                                 is_nullable: _,
                             } = v
                             {
-                                let name = type_name.identifier.value.to_string();
-                                let p = (**self.context_stack.last().unwrap())
-                                    .borrow_mut()
-                                    .register_or_get_name(&name);
-                                self.push_op(OpCode::LoadName(p));
+                                // JumpIfNotExcMatchは比較する2つの値を取り除くので、例外を複製しておく
+                                self.push_op(OpCode::DupTop);
+                                self.push_load_identifier(&type_name.identifier);
                                 self.push_op(OpCode::JumpIfNotExcMatch(label_next));
                             }
                         }
@@ -1046,31 +1401,23 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                     match &on_part.catch_part {
                         Some(catch_part) => {
                             // on E catch() { }
-                            // self.push_op(OpCode::PopTop);
-                            // 実行してみると最初にpopしたものがexception、
-                            // 2番目がtraceback objectになっている
-                            // なぜ？
+                            // 例外の型は使わないので捨て、値をcatchの変数に束縛する
+                            self.push_op(OpCode::PopTop);
 
                             let name = catch_part.id_error.value.to_string();
-                            (**self.context_stack.last().unwrap())
-                                .borrow_mut()
-                                .declare_variable(&name);
+                            self.declare_block_variable(&name)?;
                             self.push_store_var(&name);
 
                             match &catch_part.id_trace {
                                 Some(id_trace) => {
                                     let name = id_trace.value.to_string();
-                                    (**self.context_stack.last().unwrap())
-                                        .borrow_mut()
-                                        .declare_variable(&name);
+                                    self.declare_block_variable(&name)?;
                                     self.push_store_var(&name);
                                 }
                                 None => {
                                     self.push_op(OpCode::PopTop);
                                 }
                             }
-
-                            self.push_op(OpCode::PopTop);
                         }
                         None => {
                             // on E { }
@@ -1080,7 +1427,9 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                         }
                     }
 
+                    self.block_stack.push(FrameBlock::HandlerCleanup);
                     self.compile_stmt(&on_part.block, None)?;
+                    self.block_stack.pop();
                     self.push_op(OpCode::PopExcept);
                     self.push_op(OpCode::JumpAbsolute(label_end));
 
@@ -1101,14 +1450,16 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             } => {
                 let label_for_end = self.gen_jump_label();
                 let label_loop_start = self.gen_jump_label();
-                self.default_scope_stack.push(DefaultScope {
-                    break_label: label_for_end,
-                    continue_label: Some(label_loop_start),
-                });
-                if let Some(stmt_label) = label {
-                    self.continue_label_table
-                        .insert(stmt_label.to_string(), label_loop_start);
-                }
+                // continueは更新式に移動する
+                let label_update = self.gen_jump_label();
+                self.push_loop_scope(label_for_end, label_update, label);
+
+                // 初期化部で宣言した変数はループの中だけで有効
+                self.context_stack.push(Rc::new(RefCell::new(BlockContext {
+                    outer: self.context_stack.last().unwrap().clone(),
+                    variables: vec![],
+                    variable_types: HashMap::new(),
+                })));
                 if let Some(node) = init {
                     self.compile_stmt(node, None)?;
                 }
@@ -1121,10 +1472,12 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                     outer: self.context_stack.last().unwrap().clone(),
                     variables: vec![],
+                    variable_types: HashMap::new(),
                 })));
                 self.compile_stmt(stmt, None)?;
                 self.context_stack.pop();
 
+                self.set_jump_label_value(label_update);
                 if let Some(node_list) = update {
                     for node in node_list {
                         self.compile_expr(node)?;
@@ -1133,31 +1486,52 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 }
                 self.push_op(OpCode::JumpAbsolute(label_loop_start));
                 self.set_jump_label_value(label_for_end);
-                self.default_scope_stack.pop();
-                if let Some(stmt_label) = label {
-                    self.continue_label_table.remove(stmt_label);
-                }
+                self.context_stack.pop();
+                self.pop_loop_scope(label);
             }
             NodeStatement::ForIn {
                 variable,
                 is_variable_declared,
+                dart_type,
                 iterable,
                 stmt,
             } => {
-                if *is_variable_declared {
-                    (**self.context_stack.last().unwrap())
-                        .borrow_mut()
-                        .declare_variable(&variable.value.to_string());
-                }
                 self.compile_expr(iterable)?;
                 self.push_op(OpCode::GetIter);
 
+                // ループ変数はループの中だけで有効
+                self.context_stack.push(Rc::new(RefCell::new(BlockContext {
+                    outer: self.context_stack.last().unwrap().clone(),
+                    variables: vec![],
+                    variable_types: HashMap::new(),
+                })));
+                if *is_variable_declared {
+                    self.declare_block_variable(variable.value)?;
+                    self.set_variable_type(variable.value, dart_type.as_ref(), None);
+                } else {
+                    self.resolve_identifier(variable);
+                }
+
                 let label_for_end = self.gen_jump_label();
                 let label_loop_start = self.gen_jump_label();
 
+                // ループ中はスタックにイテレータが積まれている
+                // breakではイテレータを取り除いてから抜ける
+                self.block_stack.push(FrameBlock::StackValue);
+                let depth = self.block_stack.len();
+                self.default_scope_stack.push(DefaultScope {
+                    break_label: label_for_end,
+                    continue_label: Some(label_loop_start),
+                    break_depth: depth - 1,
+                    continue_depth: depth,
+                });
+                if let Some(stmt_label) = label {
+                    self.continue_label_table
+                        .insert(stmt_label.to_string(), (label_loop_start, depth));
+                }
+
                 self.set_jump_label_value(label_loop_start);
                 self.push_op(OpCode::ForIter(label_for_end));
-                let label_for_zero = self.byte_operations.borrow().len() as u8;
 
                 let var_name = variable.value.to_string();
                 self.push_store_var(&var_name);
@@ -1166,19 +1540,16 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
 
                 self.push_op(OpCode::JumpAbsolute(label_loop_start));
 
-                self.set_jump_label_value_offset(label_for_end, label_for_zero);
+                // ForIterはループの終了時にイテレータを取り除く
+                self.set_jump_label_value(label_for_end);
+                self.pop_loop_scope(label);
+                self.block_stack.pop();
+                self.context_stack.pop();
             }
             NodeStatement::While { condition, stmt } => {
                 let label_while_end = self.gen_jump_label();
                 let label_loop_start = self.gen_jump_label();
-                self.default_scope_stack.push(DefaultScope {
-                    break_label: label_while_end,
-                    continue_label: Some(label_loop_start),
-                });
-                if let Some(stmt_label) = label {
-                    self.continue_label_table
-                        .insert(stmt_label.to_string(), label_loop_start);
-                }
+                self.push_loop_scope(label_while_end, label_loop_start, label);
                 self.set_jump_label_value(label_loop_start);
                 self.compile_expr(condition)?;
                 self.push_op(OpCode::PopJumpIfFalse(label_while_end));
@@ -1186,6 +1557,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                     outer: self.context_stack.last().unwrap().clone(),
                     variables: vec![],
+                    variable_types: HashMap::new(),
                 })));
                 self.compile_stmt(stmt, None)?;
                 self.context_stack.pop();
@@ -1194,27 +1566,18 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
 
                 self.set_jump_label_value(label_while_end);
 
-                self.default_scope_stack.pop();
-                if let Some(stmt_label) = label {
-                    self.continue_label_table.remove(stmt_label);
-                }
+                self.pop_loop_scope(label);
             }
             NodeStatement::Do { condition, stmt } => {
                 let label_do_start = self.gen_jump_label();
                 let label_do_end = self.gen_jump_label();
-                self.default_scope_stack.push(DefaultScope {
-                    break_label: label_do_end,
-                    continue_label: Some(label_do_start),
-                });
-                if let Some(stmt_label) = label {
-                    self.continue_label_table
-                        .insert(stmt_label.to_string(), label_do_start);
-                }
+                self.push_loop_scope(label_do_end, label_do_start, label);
                 self.set_jump_label_value(label_do_start);
 
                 self.context_stack.push(Rc::new(RefCell::new(BlockContext {
                     outer: self.context_stack.last().unwrap().clone(),
                     variables: vec![],
+                    variable_types: HashMap::new(),
                 })));
                 self.compile_stmt(stmt, None)?;
                 self.context_stack.pop();
@@ -1223,10 +1586,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                 self.push_op(OpCode::PopJumpIfTrue(label_do_start));
                 self.set_jump_label_value(label_do_end);
 
-                self.default_scope_stack.pop();
-                if let Some(stmt_label) = label {
-                    self.continue_label_table.remove(stmt_label);
-                }
+                self.pop_loop_scope(label);
             }
             NodeStatement::Switch {
                 expr,
@@ -1235,12 +1595,18 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             } => {
                 self.compile_expr(expr)?;
                 let label_switch_end = self.gen_jump_label();
+
+                // switchの中ではスタックに値が積まれている
+                self.block_stack.push(FrameBlock::StackValue);
+                let (continue_label, continue_depth) = match self.default_scope_stack.last() {
+                    Some(v) => (v.continue_label, v.continue_depth),
+                    None => (None, 0),
+                };
                 self.default_scope_stack.push(DefaultScope {
                     break_label: label_switch_end,
-                    continue_label: match self.default_scope_stack.last() {
-                        Some(v) => v.continue_label,
-                        None => None,
-                    },
+                    continue_label,
+                    break_depth: self.block_stack.len() - 1,
+                    continue_depth,
                 });
                 let case_labels: Vec<u32> =
                     case_list.iter().map(|_| self.gen_jump_label()).collect();
@@ -1252,10 +1618,9 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                     self.push_op(OpCode::compare_op_from_str("==")?);
                     self.push_op(OpCode::PopJumpIfTrue(case_label));
                 }
+                // どのcaseにも一致しなければdefaultか末尾に移動する
                 let label_default_start = self.gen_jump_label();
-                if let Some(_) = default_case {
-                    self.push_op(OpCode::JumpAbsolute(label_default_start));
-                }
+                self.push_op(OpCode::JumpAbsolute(label_default_start));
                 for case_index in 0..case_list.len() {
                     let case = &case_list[case_index];
                     let case_label = case_labels[case_index];
@@ -1264,12 +1629,14 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                         self.compile_stmt(stmt, None)?;
                     }
                 }
+                self.set_jump_label_value(label_default_start);
                 if let Some(default_case) = default_case {
-                    self.set_jump_label_value(label_default_start);
                     for stmt in &default_case.stmt_list {
                         self.compile_stmt(stmt, None)?;
                     }
                 }
+                self.block_stack.pop();
+                self.push_op(OpCode::PopTop);
                 self.set_jump_label_value(label_switch_end);
                 self.default_scope_stack.pop();
             }
@@ -1277,11 +1644,12 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
         Ok(())
     }
 
-    fn compile_declare_function<F: FnOnce(&mut ByteCompiler<'ctx, 'value>) -> Result<()>>(
+    // 関数オブジェクトを作ってスタックに積む
+    fn push_make_function<F: FnOnce(&mut ByteCompiler<'ctx, 'value>) -> Result<()>>(
         &mut self,
         name: &String,
         param: &'value FunctionParamSignature,
-        body: &'value Box<NodeStatement>,
+        body: &'value NodeStatement,
         function_name_prefix: Option<String>,
         implicit_arg: Option<&String>,
         preface: F,
@@ -1308,9 +1676,21 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             num_normal_args += 1;
         }
         let num_kw_only_args = param.named_list.len() as u32;
+        // 引数の宣言された型を記録してから本体をコンパイルする
+        let preface = |compiler: &mut ByteCompiler<'ctx, 'value>| {
+            for p in param
+                .normal_list
+                .iter()
+                .chain(&param.option_list)
+                .chain(&param.named_list)
+            {
+                compiler.set_variable_type(p.identifier.value, p.dart_type.as_ref(), None);
+            }
+            preface(compiler)
+        };
         let py_code = run_function(
             &"main.py".to_string(),
-            &name,
+            name,
             argument_list,
             num_normal_args,
             0,
@@ -1324,7 +1704,7 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
         // 通常引数のデフォルト値の設定
         let has_default = !param.option_list.is_empty();
         if has_default {
-            let size = param.option_list.len() as u8;
+            let size = param.option_list.len() as u32;
             for v in &param.option_list {
                 match &v.expr {
                     Some(expr) => {
@@ -1351,18 +1731,18 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
                     None => (),
                 }
             }
-            self.push_load_const(PyObject::SmallTuple {
-                children: name_list
+            self.push_load_const(PyObject::new_tuple(
+                name_list
                     .iter()
                     .map(|v| PyObject::new_string(v.to_string(), false))
                     .collect(),
-                add_ref: false,
-            });
-            let size = name_list.len() as u8;
+                false,
+            ));
+            let size = name_list.len() as u32;
             self.push_op(OpCode::BuildConstKeyMap(size));
         }
 
-        // アノテーションは未実装
+        // 型アノテーションは未実装
         // クロージャは未実装
 
         // コードオブジェクトの読み込み
@@ -1377,11 +1757,31 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
             }
         }
         // 関数作成と収納
-        let make_flag = (has_default as u8) | ((has_kw_default as u8) << 1);
+        let make_flag = (has_default as u32) | ((has_kw_default as u32) << 1);
         self.push_op(OpCode::MakeFunction(make_flag));
+        Ok(())
+    }
+
+    // @で付けられたアノテーションをデコレータとして適用し、nameに収納する
+    // makeは関数やクラスをスタックに積む
+    // @a @b f → a(b(f))
+    fn compile_decorated<F: FnOnce(&mut ByteCompiler<'ctx, 'value>) -> Result<()>>(
+        &mut self,
+        metadata_list: &'value [NodeExpression],
+        name: &String,
+        make: F,
+    ) -> Result<()> {
+        // デコレータは関数やクラスを作るより先に評価する
+        for metadata in metadata_list {
+            self.compile_expr(metadata)?;
+        }
+        make(self)?;
+        for _ in metadata_list {
+            self.push_op(OpCode::CallFunction(1));
+        }
         let p = (**self.context_stack.last().unwrap())
             .borrow_mut()
-            .declare_variable(&name);
+            .declare_variable(name);
         self.push_op(OpCode::StoreName(p));
         Ok(())
     }
@@ -1390,15 +1790,206 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
         self.byte_operations.borrow_mut().push(op);
     }
 
-    fn push_load_const(&self, value: PyObject) -> u8 {
-        let position = self.context_stack.last().unwrap().borrow().const_len() as u8;
-        (**self.context_stack.last().unwrap())
+    fn push_load_const(&self, value: PyObject) -> u32 {
+        let position = (**self.context_stack.last().unwrap())
             .borrow_mut()
             .push_const(value);
         self.push_op(OpCode::LoadConst(position));
         position
     }
 
+    // 数値演算の演算子を出力する
+    // スタック上の2つの値を被演算子として消費し、演算結果を積む
+    fn push_arithmetic_op(
+        &self,
+        operator: &str,
+        right: &NodeExpression,
+        inplace: bool,
+    ) -> Result<()> {
+        if let Some(helper) = self.dart_numeric_helper(operator, right) {
+            // helper(left, right)
+            self.push_load_runtime(helper);
+            self.push_op(OpCode::RotThree);
+            self.push_op(OpCode::CallFunction(2));
+            return Ok(());
+        }
+        let op = if inplace {
+            match operator {
+                "*" => OpCode::InplaceMultiply,
+                "/" => OpCode::InplaceTrueDivide,
+                "~/" => OpCode::InplaceFloorDivide,
+                "%" => OpCode::InplaceModulo,
+                "+" => OpCode::InplaceAdd,
+                "-" => OpCode::InplaceSubtract,
+                "<<" => OpCode::InplaceLShift,
+                ">>" => OpCode::InplaceRShift,
+                "&" => OpCode::InplaceAnd,
+                "^" => OpCode::InplaceXor,
+                "|" => OpCode::InplaceOr,
+                _ => bail!("unknown assignment operator: {}=", operator),
+            }
+        } else {
+            match operator {
+                "<<" => OpCode::BinaryLShift,
+                ">>" => OpCode::BinaryRShift,
+                "&" => OpCode::BinaryAnd,
+                "^" => OpCode::BinaryXor,
+                "|" => OpCode::BinaryOr,
+                "+" => OpCode::BinaryAdd,
+                "-" => OpCode::BinarySubtract,
+                "*" => OpCode::BinaryMultiply,
+                "/" => OpCode::BinaryTrueDivide,
+                "%" => OpCode::BinaryModulo,
+                "~/" => OpCode::BinaryFloorDivide,
+                _ => bail!("unknown binary operator: {}", operator),
+            }
+        };
+        self.push_op(op);
+        Ok(())
+    }
+
+    // Dartの意味論で計算するために実行時ライブラリを呼ぶ必要があれば、その関数名を返す
+    fn dart_numeric_helper(&self, operator: &str, right: &NodeExpression) -> Option<&'static str> {
+        if operator == ">>>" {
+            // Pythonに対応する演算子がない
+            return Some("ushr");
+        }
+        if self.options.numeric_semantics != NumericSemantics::Dart {
+            return None;
+        }
+        // 右辺が0でない数値リテラルならPythonの演算子と結果が一致する
+        let is_nonzero_literal = match right {
+            NodeExpression::NumericLiteral { value } => match PyObject::new_numeric(value, false) {
+                Ok(PyObject::Int(v, _)) => v != 0,
+                Ok(PyObject::Float(v, _)) => v != 0.0,
+                Ok(PyObject::Long { .. }) => true,
+                _ => false,
+            },
+            _ => false,
+        };
+        match operator {
+            "/" if !is_nonzero_literal => Some("div"),
+            "%" if !is_nonzero_literal => Some("mod"),
+            "~/" => Some("truncdiv"),
+            _ => None,
+        }
+    }
+
+    // 型に対応するクラスを積む
+    fn push_load_type(&self, dart_type: &DartType) -> Result<()> {
+        match dart_type {
+            DartType::Named { type_name, .. } => {
+                self.push_load_identifier(&type_name.identifier);
+                Ok(())
+            }
+            DartType::Void => bail!("void is not a class"),
+        }
+    }
+
+    // 識別子の値を積む
+    fn push_load_identifier(&self, identifier: &Identifier) {
+        self.resolve_identifier(identifier);
+        let name = identifier.value;
+        let value = name.to_string();
+        let scope = self
+            .context_stack
+            .last()
+            .unwrap()
+            .borrow()
+            .check_variable_scope(&value);
+        let export = runtime::CORE_EXPORTS
+            .iter()
+            .find(|(dart_name, _)| *dart_name == name);
+        match (scope, export) {
+            (VariableScope::NotDefined, Some((_, runtime_name))) => {
+                // 宣言されていなければ実行時ライブラリのものを使う
+                self.push_load_runtime(runtime_name);
+            }
+            _ => self.push_load_var(&value),
+        }
+    }
+
+    // 関数を呼び出す
+    // 呼び出す関数と、先に積んだnum_pushed個の引数がスタックにある状態で使う
+    fn push_call_arguments(
+        &mut self,
+        arguments: &'value Vec<CallParameter>,
+        num_pushed: u32,
+    ) -> Result<()> {
+        let mut name_list: Vec<&str> = vec![];
+        for param in arguments {
+            self.compile_expr(&param.expr)?;
+            if let Some(v) = &param.identifier {
+                name_list.push(v.value);
+            }
+        }
+        let num_args = arguments.len() as u32 + num_pushed;
+        if !name_list.is_empty() {
+            self.push_load_const(PyObject::new_tuple(
+                name_list
+                    .iter()
+                    .map(|v| PyObject::new_string(v.to_string(), false))
+                    .collect(),
+                false,
+            ));
+            self.push_op(OpCode::CallFunctionKw(num_args));
+        } else {
+            self.push_op(OpCode::CallFunction(num_args));
+        }
+        Ok(())
+    }
+
+    // 実行時ライブラリの関数を積む
+    fn push_load_runtime(&self, name: &str) {
+        self.push_load_var(&runtime::CORE_ALIAS.to_string());
+        let p = (**self.context_stack.last().unwrap())
+            .borrow_mut()
+            .register_or_get_name(&name.to_string());
+        self.push_op(OpCode::LoadAttr(p));
+    }
+
+    // 実行時ライブラリを__elaphe__としてインポートする
+    fn push_runtime_prologue(&self) {
+        // import elaphe_core as __elaphe__
+        self.push_load_const(PyObject::Int(0, false));
+        self.push_load_const(PyObject::None(false));
+        let p = (**self.context_stack.last().unwrap())
+            .borrow_mut()
+            .register_or_get_name(&runtime::CORE_MODULE_NAME.to_string());
+        self.push_op(OpCode::ImportName(p));
+        let p = (**self.context_stack.last().unwrap())
+            .borrow_mut()
+            .register_or_get_name(&runtime::CORE_ALIAS.to_string());
+        self.push_op(OpCode::StoreName(p));
+    }
+
+    // 識別子が宣言されているか確かめる
+    // ローカル変数でもインスタンス変数でもなければ、トップレベルの名前から探す
+    fn resolve_identifier(&self, identifier: &Identifier) {
+        let scope = self
+            .context_stack
+            .last()
+            .unwrap()
+            .borrow()
+            .check_variable_scope(&identifier.value.to_string());
+        if let VariableScope::NotDefined = scope {
+            self.resolver.resolve(identifier);
+        }
+    }
+
+    // 抽象クラスのインスタンスを作ろうとしていないか確かめる
+    fn check_instantiation(&self, identifier: &Identifier) {
+        let scope = self
+            .context_stack
+            .last()
+            .unwrap()
+            .borrow()
+            .check_variable_scope(&identifier.value.to_string());
+        if let VariableScope::Global | VariableScope::NotDefined = scope {
+            self.resolver.check_instantiation(identifier);
+        }
+    }
+
     fn push_load_var(&self, value: &String) {
         let scope = self
             .context_stack
@@ -1491,6 +2082,108 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
         }
     }
 
+    // 今のブロックに変数を宣言する
+    // 同じブロックで同じ名前を宣言し直すことはできない
+    fn declare_block_variable(&self, name: &str) -> Result<u32> {
+        let context = self.context_stack.last().unwrap();
+        ensure!(
+            !context.borrow().is_declared_in_block(name),
+            "'{}' is already declared in this scope",
+            name
+        );
+        let position = context.borrow_mut().declare_variable(&name.to_string());
+        Ok(position)
+    }
+
+    fn push_loop_scope(&mut self, break_label: u32, continue_label: u32, label: Option<&String>) {
+        let depth = self.block_stack.len();
+        self.default_scope_stack.push(DefaultScope {
+            break_label,
+            continue_label: Some(continue_label),
+            break_depth: depth,
+            continue_depth: depth,
+        });
+        if let Some(stmt_label) = label {
+            self.continue_label_table
+                .insert(stmt_label.to_string(), (continue_label, depth));
+        }
+    }
+
+    fn pop_loop_scope(&mut self, label: Option<&String>) {
+        self.default_scope_stack.pop();
+        if let Some(stmt_label) = label {
+            self.continue_label_table.remove(stmt_label);
+        }
+    }
+
+    // break、continueでdepthの深さまでブロックを抜けてからlabelへ移動する
+    fn push_jump_out(&mut self, label: u32, depth: usize) -> Result<()> {
+        self.unwind_blocks(depth, false)?;
+        self.push_op(OpCode::JumpAbsolute(label));
+        Ok(())
+    }
+
+    // block_stackがdepthの深さになるまで、内側のブロックから順に後始末する
+    // preserve_tosがtrueの場合はスタックの先頭(戻り値)を残す
+    fn unwind_blocks(&mut self, depth: usize, preserve_tos: bool) -> Result<()> {
+        if self.block_stack.len() <= depth {
+            return Ok(());
+        }
+        // finally節の中では、そのブロック自身は既に抜けている
+        let block = self.block_stack.pop().unwrap();
+        let result = self
+            .unwind_block(block, preserve_tos)
+            .and_then(|_| self.unwind_blocks(depth, preserve_tos));
+        self.block_stack.push(block);
+        result
+    }
+
+    fn unwind_block(&mut self, block: FrameBlock<'value>, preserve_tos: bool) -> Result<()> {
+        match block {
+            FrameBlock::StackValue => {
+                if preserve_tos {
+                    self.push_op(OpCode::RotTwo);
+                }
+                self.push_op(OpCode::PopTop);
+            }
+            FrameBlock::TryExcept => {
+                self.push_op(OpCode::PopBlock);
+            }
+            FrameBlock::FinallyTry(block_finally) => {
+                self.push_op(OpCode::PopBlock);
+                if preserve_tos {
+                    // finally節の中でreturnした場合は、この戻り値を捨てる
+                    self.block_stack.push(FrameBlock::StackValue);
+                }
+                self.compile_stmt(block_finally, None)?;
+                if preserve_tos {
+                    self.block_stack.pop();
+                }
+            }
+            FrameBlock::FinallyEnd => {
+                // [前の例外情報 x3, 例外情報 x3] -> TOP
+                if preserve_tos {
+                    self.push_op(OpCode::RotFour);
+                }
+                self.push_op(OpCode::PopTop);
+                self.push_op(OpCode::PopTop);
+                self.push_op(OpCode::PopTop);
+                if preserve_tos {
+                    self.push_op(OpCode::RotFour);
+                }
+                self.push_op(OpCode::PopExcept);
+            }
+            FrameBlock::HandlerCleanup => {
+                // [前の例外情報 x3] -> TOP
+                if preserve_tos {
+                    self.push_op(OpCode::RotFour);
+                }
+                self.push_op(OpCode::PopExcept);
+            }
+        }
+        Ok(())
+    }
+
     fn gen_jump_label(&self) -> u32 {
         let key = *self.jump_label_key_index.borrow();
         *self.jump_label_key_index.borrow_mut() += 1;
@@ -1498,30 +2191,130 @@ impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
     }
 
     // 今の次の位置にラベル位置を合わせる
+    // 相対分岐のオフセットやEXTENDED_ARGはassembleで解決する
     fn set_jump_label_value(&self, key: u32) {
-        let index = self.byte_operations.borrow().len() as u8;
-        // 1命令あたり2バイトなので2倍
-        self.jump_label_table.borrow_mut().insert(key, index * 2);
+        let index = self.byte_operations.borrow().len() as u32;
+        self.jump_label_table.borrow_mut().insert(key, index);
     }
+}
 
-    fn set_jump_label_value_offset(&self, key: u32, offset: u8) {
-        let index = (self.byte_operations.borrow().len() as u8) - offset;
-        // 1命令あたり2バイトなので2倍
-        self.jump_label_table.borrow_mut().insert(key, index * 2);
+impl<'a, 'b> ByteCompiler<'a, 'b> {
+    /// 最適化レベルに応じてバイトコードを最適化する
+    /// 畳み込んだ定数はconstant_listに追加される
+    pub fn optimize(&self, constant_list: &mut Vec<PyObject>) -> Result<()> {
+        if self.options.opt_level == OptLevel::O0 {
+            return Ok(());
+        }
+        let (operation_list, label_table) = optimizer::optimize(
+            &self.byte_operations.borrow(),
+            &self.jump_label_table.borrow(),
+            constant_list,
+            self.options.opt_level,
+        )?;
+        *self.byte_operations.borrow_mut() = operation_list;
+        *self.jump_label_table.borrow_mut() = label_table;
+        Ok(())
+    }
+
+    /// 分岐先を解決する前の命令列を書き出す
+    pub fn dump_operations(&self, code_name: &str) -> Result<()> {
+        if let Some(listing) = self.listing {
+            let operations = disasm::format_operations(
+                code_name,
+                &self.byte_operations.borrow(),
+                &self.jump_label_table.borrow(),
+            )?;
+            listing.borrow_mut().push_str(&operations);
+        }
+        Ok(())
+    }
+
+    pub fn resolve_references(&self) -> Result<Vec<ByteCode>> {
+        bytecode::assemble(
+            &self.byte_operations.borrow(),
+            &self.jump_label_table.borrow(),
+        )
     }
 }
 
-impl<'a, 'b> ByteCompiler<'a, 'b> {
-    pub fn resolve_references(&self) -> Vec<ByteCode> {
-        let opcode_list = self.byte_operations.borrow();
+// 定数式ならその値を返す
+fn constant_value(node: &NodeExpression) -> Result<Option<PyObject>> {
+    let value = match node {
+        NodeExpression::NumericLiteral { value } => PyObject::new_numeric(value, false)?,
+        NodeExpression::BooleanLiteral { value } => PyObject::new_boolean(value, false)?,
+        NodeExpression::NullLiteral => PyObject::None(false),
+        NodeExpression::StringLiteral { str_list } => {
+            let mut value = String::new();
+            for single_str in str_list {
+                if !single_str.interpolation_list.is_empty() {
+                    return Ok(None);
+                }
+                for str in &single_str.string_list {
+                    value.push_str(&replace_escape(str)?);
+                }
+            }
+            PyObject::new_string(value, false)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+// 要素が全て定数の集合リテラルをfrozensetにする
+// Pythonでは1とTrueが等しいので、真偽値と数値が混ざる場合は対象外とする
+fn constant_set(element_list: &[CollectionElement]) -> Result<Option<PyObject>> {
+    let mut children = vec![];
+    for elem in element_list {
+        let value = match elem {
+            CollectionElement::ExpressionElement { expr } => constant_value(expr)?,
+            CollectionElement::MapElement { .. } => None,
+        };
+        match value {
+            Some(v) => children.push(v),
+            None => return Ok(None),
+        }
+    }
+    let has_bool = children
+        .iter()
+        .any(|v| matches!(v, PyObject::True(_) | PyObject::False(_)));
+    let has_number = children.iter().any(|v| {
+        matches!(
+            v,
+            PyObject::Int(..) | PyObject::Long { .. } | PyObject::Float(..)
+        )
+    });
+    if children.is_empty() || (has_bool && has_number) {
+        return Ok(None);
+    }
+    Ok(Some(PyObject::FrozenSet {
+        children,
+        add_ref: false,
+    }))
+}
 
-        let result = opcode_list
+fn new_name_tuple(names: &[&str]) -> PyObject {
+    PyObject::new_tuple(
+        names
             .iter()
-            .map(|v| v.resolve(&self.jump_label_table.borrow()))
-            .collect();
+            .map(|v| PyObject::new_string(v.to_string(), false))
+            .collect(),
+        false,
+    )
+}
 
-        result
+// showで指定された名前がライブラリで宣言されているか確かめる
+fn check_shown_names(node: &LibraryImport, declared_names: &[&str]) -> Result<()> {
+    for combinator in node.combinator_list.iter().filter(|v| v.is_show) {
+        for target in &combinator.target_list {
+            ensure!(
+                declared_names.contains(&target.value),
+                "'{}' is not declared in {}",
+                target.value,
+                node.uri
+            );
+        }
     }
+    Ok(())
 }
 
 fn replace_escape(source: &str) -> Result<String> {
@@ -1,11 +1,12 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Context, Result};
 
 use crate::bytecode::{calc_stack_size, OpCode};
 use crate::executioncontext::{ClassContext, ExecutionContext, PyContext};
 use crate::parser::node::{
-    ConstructorSignature, FunctionParamSignature, Member, NodeStatement, VariableDeclaration,
+    ConstructorSignature, DartType, FunctionParamSignature, Member, NodeExpression, NodeStatement,
+    VariableDeclaration,
 };
 use crate::pyobject::PyObject;
 
@@ -15,6 +16,7 @@ pub fn run_class<'ctx, 'value, 'cpl>(
     file_name: &String,
     code_name: &String,
     member_list: &'value Vec<Member>,
+    superclass: Option<&'value DartType>,
     outer_compiler: &'cpl ByteCompiler<'ctx, 'value>,
     source: &'value str,
 ) -> Result<PyObject> {
@@ -29,6 +31,7 @@ pub fn run_class<'ctx, 'value, 'cpl>(
     let class_context = Rc::new(RefCell::new(ClassContext {
         outer: py_context.clone(),
         instance_variables: vec![],
+        variable_types: HashMap::new(),
     }));
 
     let mut compiler = ByteCompiler {
@@ -39,7 +42,12 @@ pub fn run_class<'ctx, 'value, 'cpl>(
         default_scope_stack: vec![],
         break_label_table: HashMap::new(),
         continue_label_table: HashMap::new(),
+        block_stack: vec![],
         source,
+        options: outer_compiler.options,
+        resolver: outer_compiler.resolver.clone(),
+        declared_types: outer_compiler.declared_types.clone(),
+        listing: outer_compiler.listing,
     };
 
     // __module__ = __name__
@@ -73,24 +81,45 @@ pub fn run_class<'ctx, 'value, 'cpl>(
                     class_context
                         .borrow_mut()
                         .declare_variable(&decl.identifier.value.to_string());
+                    compiler.set_variable_type(
+                        decl.identifier.value,
+                        decl.dart_type.as_ref(),
+                        decl.expr.as_deref(),
+                    );
                 }
             }
-            Member::MethodImpl { signature, body: _ } => {
+            Member::MethodImpl { signature, .. } => {
                 if signature.name.value == code_name {
                     primary_constructor = Some(member);
                 } else {
                     method_declaration_list.push(member);
                 }
             }
-            Member::ConstructorImpl {
-                signature: _,
-                body: _,
-            } => {
+            Member::ConstructorImpl { signature, .. } => {
+                if let Some(name) = &signature.name {
+                    ensure!(
+                        name.value == code_name,
+                        "named constructors are not supported: {}",
+                        name.value
+                    );
+                }
                 primary_constructor = Some(member);
             }
+            Member::AbstractMethod { .. } | Member::GetterImpl { .. } => {
+                method_declaration_list.push(member);
+            }
         }
     }
 
+    // スーパークラスのインスタンス変数もselfを通して参照する
+    if let Some(DartType::Named { type_name, .. }) = superclass {
+        class_context.borrow_mut().instance_variables.extend(
+            outer_compiler
+                .resolver
+                .inherited_fields(type_name.identifier.value),
+        );
+    }
+
     let dummy_constructor = Member::ConstructorImpl {
         signature: ConstructorSignature {
             name: None,
@@ -101,6 +130,7 @@ pub fn run_class<'ctx, 'value, 'cpl>(
             },
         },
         body: Box::new(NodeStatement::Empty),
+        super_arguments: None,
     };
     if !instance_variable_declaration_list.is_empty() && primary_constructor.is_none() {
         primary_constructor = Some(&dummy_constructor);
@@ -112,12 +142,14 @@ pub fn run_class<'ctx, 'value, 'cpl>(
             method,
             code_name,
             instance_variable_declaration_list,
+            superclass,
         )?;
     }
 
-    for method in method_declaration_list {
-        compile_method(&mut compiler, &method, code_name)?;
+    for method in &method_declaration_list {
+        compile_method(&mut compiler, method, code_name)?;
     }
+    compile_object_members(&mut compiler, &method_declaration_list);
 
     // 終わり
     compiler.push_load_const(PyObject::None(false));
@@ -130,8 +162,16 @@ pub fn run_class<'ctx, 'value, 'cpl>(
     *outer_compiler.jump_label_key_index.borrow_mut() = *compiler.jump_label_key_index.borrow();
 
     // PyCodeの作成
-    let stack_size = calc_stack_size(&compiler.byte_operations.borrow()) as u32;
-    let operation_list = compiler.resolve_references();
+    compiler.optimize(&mut py_context.borrow_mut().constant_list)?;
+    let stack_size = calc_stack_size(
+        &compiler.byte_operations.borrow(),
+        &compiler.jump_label_table.borrow(),
+    )
+    .with_context(|| format!("invalid bytecode in {}", code_name))?;
+    compiler.dump_operations(code_name)?;
+    let operation_list = compiler
+        .resolve_references()
+        .with_context(|| format!("invalid bytecode in {}", code_name))?;
 
     let py_context = Rc::try_unwrap(py_context).ok().unwrap().into_inner();
 
@@ -144,18 +184,14 @@ pub fn run_class<'ctx, 'value, 'cpl>(
         num_locals: 0,
         stack_size,
         operation_list,
-        constant_list: Box::new(PyObject::SmallTuple {
-            children: py_context.constant_list,
-            add_ref: false,
-        }),
-        name_list: Box::new(PyObject::SmallTuple {
-            children: py_context.name_list,
-            add_ref: false,
-        }),
+        constant_list: Box::new(PyObject::new_tuple(py_context.constant_list, false)),
+        name_list: Box::new(PyObject::new_tuple(py_context.name_list, false)),
         local_list: Box::new(PyObject::SmallTuple {
             children: vec![],
             add_ref: false,
         }),
+        first_line_number: 1,
+        line_table: vec![],
         add_ref: false,
     })
 }
@@ -165,28 +201,173 @@ fn compile_method<'ctx, 'value, 'cpl>(
     node: &'value Member,
     class_name: &'value str,
 ) -> Result<()> {
-    if let Member::MethodImpl { signature, body } = node {
+    if let Member::MethodImpl {
+        signature,
+        body,
+        metadata_list,
+    } = node
+    {
         let prefix = format!("{}{}", class_name, ".");
-        compiler.compile_declare_function(
-            &signature.name.value.to_string(),
-            &signature.param,
-            &body,
-            Some(prefix),
-            Some(&"self".to_string()),
-            |_| Ok(()),
-        )?;
+        // @staticmethodが付いていればselfを受け取らない
+        let is_static = metadata_list.iter().any(|v| match v {
+            NodeExpression::Identifier { identifier } => identifier.value == "staticmethod",
+            _ => false,
+        });
+        let self_name = "self".to_string();
+        let name = python_member_name(signature.name.value).to_string();
+        compiler.compile_decorated(metadata_list, &name, |compiler| {
+            compiler.push_make_function(
+                &name,
+                &signature.param,
+                body,
+                Some(prefix),
+                if is_static { None } else { Some(&self_name) },
+                |_| Ok(()),
+            )
+        })?;
+        Ok(())
+    } else if let Member::GetterImpl {
+        signature,
+        body,
+        metadata_list,
+    } = node
+    {
+        // property(function)
+        let prefix = format!("{}{}", class_name, ".");
+        let name = signature.name.value.to_string();
+        compiler.compile_decorated(metadata_list, &name, |compiler| {
+            push_load_name(compiler, "property");
+            compiler.push_make_function(
+                &name,
+                &signature.param,
+                body,
+                Some(prefix),
+                Some(&"self".to_string()),
+                |_| Ok(()),
+            )?;
+            compiler.push_op(OpCode::CallFunction(1));
+            Ok(())
+        })?;
+        Ok(())
+    } else if let Member::AbstractMethod {
+        signature,
+        metadata_list,
+    } = node
+    {
+        // 本体のない関数をabstractmethodで修飾する
+        let prefix = format!("{}{}", class_name, ".");
+        let name = signature.name.value.to_string();
+        compiler.compile_decorated(metadata_list, &name, |compiler| {
+            compiler.push_load_runtime("abstractmethod");
+            compiler.push_make_function(
+                &name,
+                &signature.param,
+                &NodeStatement::Empty,
+                Some(prefix),
+                Some(&"self".to_string()),
+                |_| Ok(()),
+            )?;
+            compiler.push_op(OpCode::CallFunction(1));
+            Ok(())
+        })?;
         Ok(())
     } else {
         bail!("Members except MethodImpl are not supported.");
     }
 }
 
+// DartのメンバーをPythonの特殊メソッドとして使えるようにする
+// toString → __str__, __repr__
+// hashCode → __hash__
+// call → __call__
+// noSuchMethod → __getattr__
+fn compile_object_members(compiler: &mut ByteCompiler, method_declaration_list: &[&Member]) {
+    let mut methods: Vec<&str> = vec![];
+    let mut getters: Vec<&str> = vec![];
+    for member in method_declaration_list {
+        match member {
+            Member::MethodImpl { signature, .. } => methods.push(signature.name.value),
+            Member::GetterImpl { signature, .. } => getters.push(signature.name.value),
+            _ => (),
+        }
+    }
+
+    for (dart_name, python_name) in [
+        ("toString", "__str__"),
+        ("toString", "__repr__"),
+        ("hashCode", "__hash__"),
+        ("call", "__call__"),
+    ] {
+        if methods.contains(&dart_name) {
+            push_load_name(compiler, dart_name);
+        } else if getters.contains(&dart_name) {
+            // ゲッターはpropertyの中の関数を使う
+            push_load_name(compiler, dart_name);
+            let p = (**compiler.context_stack.last().unwrap())
+                .borrow_mut()
+                .register_or_get_name(&"fget".to_string());
+            compiler.push_op(OpCode::LoadAttr(p));
+        } else {
+            continue;
+        }
+        push_store_name(compiler, python_name);
+    }
+
+    // __getattr__ = noSuchMethodGetattr(noSuchMethod)
+    if methods.contains(&"noSuchMethod") {
+        compiler.push_load_runtime("noSuchMethodGetattr");
+        push_load_name(compiler, "noSuchMethod");
+        compiler.push_op(OpCode::CallFunction(1));
+        push_store_name(compiler, "__getattr__");
+    }
+
+    // Pythonでは__eq__だけを定義すると__hash__がNoneになるので
+    // Dartと同じようにObjectのhashCodeを使う
+    if methods.contains(&"==") && !methods.contains(&"hashCode") && !getters.contains(&"hashCode") {
+        push_load_name(compiler, "object");
+        let p = (**compiler.context_stack.last().unwrap())
+            .borrow_mut()
+            .register_or_get_name(&"__hash__".to_string());
+        compiler.push_op(OpCode::LoadAttr(p));
+        push_store_name(compiler, "__hash__");
+    }
+}
+
+// 演算子のメソッドはPythonの特殊メソッドとして定義する
+fn python_member_name(name: &str) -> &str {
+    match name {
+        "==" => "__eq__",
+        _ => name,
+    }
+}
+
+fn push_load_name(compiler: &ByteCompiler, name: &str) {
+    let p = (**compiler.context_stack.last().unwrap())
+        .borrow_mut()
+        .register_or_get_name(&name.to_string());
+    compiler.push_op(OpCode::LoadName(p));
+}
+
+fn push_store_name(compiler: &ByteCompiler, name: &str) {
+    let p = (**compiler.context_stack.last().unwrap())
+        .borrow_mut()
+        .register_or_get_name(&name.to_string());
+    compiler.push_op(OpCode::StoreName(p));
+}
+
 fn compile_constructor<'ctx, 'value, 'cpl>(
     compiler: &'cpl mut ByteCompiler<'ctx, 'value>,
     node: &'value Member,
     class_name: &'value str,
     instance_variable_declaration_list: Vec<&'value Vec<VariableDeclaration>>,
+    superclass: Option<&'value DartType>,
 ) -> Result<()> {
+    let super_arguments = match node {
+        Member::ConstructorImpl {
+            super_arguments, ..
+        } => super_arguments.as_ref(),
+        _ => None,
+    };
     let preface = |compiler: &mut ByteCompiler<'ctx, 'value>| {
         for decl_list in instance_variable_declaration_list {
             for decl in decl_list {
@@ -212,30 +393,64 @@ fn compile_constructor<'ctx, 'value, 'cpl>(
                 compiler.push_op(OpCode::StoreAttr(p));
             }
         }
+        // インスタンス変数の初期化の後に、スーパークラスのコンストラクタを呼ぶ
+        // Base.__init__(self, ...)
+        // 初期化リストにsuper(...)が無ければ引数なしで呼ぶ
+        if let Some(superclass) = superclass {
+            compiler.push_load_type(superclass)?;
+            let p = (**compiler.context_stack.last().unwrap())
+                .borrow_mut()
+                .register_or_get_name(&"__init__".to_string());
+            compiler.push_op(OpCode::LoadAttr(p));
+            let p = compiler
+                .context_stack
+                .last()
+                .unwrap()
+                .borrow()
+                .get_local_variable(&"self".to_string());
+            compiler.push_op(OpCode::LoadFast(p));
+            match super_arguments {
+                Some(arguments) => compiler.push_call_arguments(arguments, 1)?,
+                None => compiler.push_op(OpCode::CallFunction(1)),
+            }
+            compiler.push_op(OpCode::PopTop);
+        }
         Ok(())
     };
     match node {
-        Member::MethodImpl { signature, body } => {
+        Member::MethodImpl {
+            signature,
+            body,
+            metadata_list,
+        } => {
             let prefix = format!("{}{}", class_name, ".");
-            compiler.compile_declare_function(
-                &"__init__".to_string(),
-                &signature.param,
-                &body,
-                Some(prefix),
-                Some(&"self".to_string()),
-                preface,
-            )?;
+            let name = "__init__".to_string();
+            compiler.compile_decorated(metadata_list, &name, |compiler| {
+                compiler.push_make_function(
+                    &name,
+                    &signature.param,
+                    body,
+                    Some(prefix),
+                    Some(&"self".to_string()),
+                    preface,
+                )
+            })?;
         }
-        Member::ConstructorImpl { signature, body } => {
+        Member::ConstructorImpl {
+            signature, body, ..
+        } => {
             let prefix = format!("{}{}", class_name, ".");
-            compiler.compile_declare_function(
-                &"__init__".to_string(),
-                &signature.param,
-                &body,
-                Some(prefix),
-                Some(&"self".to_string()),
-                preface,
-            )?;
+            let name = "__init__".to_string();
+            compiler.compile_decorated(&[], &name, |compiler| {
+                compiler.push_make_function(
+                    &name,
+                    &signature.param,
+                    body,
+                    Some(prefix),
+                    Some(&"self".to_string()),
+                    preface,
+                )
+            })?;
         }
         _ => (),
     }
@@ -1,6 +1,6 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use crate::bytecode::{calc_stack_size, OpCode};
 use crate::executioncontext::{BlockContext, ExecutionContext, PyContext};
@@ -37,6 +37,7 @@ pub fn run_function<
     let block_context = Rc::new(RefCell::new(BlockContext {
         outer: py_context.clone(),
         variables: vec![],
+        variable_types: HashMap::new(),
     }));
 
     for arg in argument_list {
@@ -51,7 +52,12 @@ pub fn run_function<
         default_scope_stack: vec![],
         break_label_table: HashMap::new(),
         continue_label_table: HashMap::new(),
+        block_stack: vec![],
         source,
+        options: outer_compiler.options,
+        resolver: outer_compiler.resolver.clone(),
+        declared_types: outer_compiler.declared_types.clone(),
+        listing: outer_compiler.listing,
     };
 
     preface(&mut compiler)?;
@@ -68,8 +74,16 @@ pub fn run_function<
     *outer_compiler.jump_label_key_index.borrow_mut() = *compiler.jump_label_key_index.borrow();
 
     // PyCodeの作成
-    let stack_size = calc_stack_size(&compiler.byte_operations.borrow()) as u32;
-    let operation_list = compiler.resolve_references();
+    compiler.optimize(&mut py_context.borrow_mut().constant_list)?;
+    let stack_size = calc_stack_size(
+        &compiler.byte_operations.borrow(),
+        &compiler.jump_label_table.borrow(),
+    )
+    .with_context(|| format!("invalid bytecode in {}", code_name))?;
+    compiler.dump_operations(code_name)?;
+    let operation_list = compiler
+        .resolve_references()
+        .with_context(|| format!("invalid bytecode in {}", code_name))?;
 
     let py_context = Rc::try_unwrap(py_context).ok().unwrap().into_inner();
 
@@ -82,22 +96,18 @@ pub fn run_function<
         num_locals: py_context.local_variables.len() as u32,
         stack_size,
         operation_list,
-        constant_list: Box::new(PyObject::SmallTuple {
-            children: py_context.constant_list,
-            add_ref: false,
-        }),
-        name_list: Box::new(PyObject::SmallTuple {
-            children: py_context.name_list,
-            add_ref: false,
-        }),
-        local_list: Box::new(PyObject::SmallTuple {
-            children: py_context
+        constant_list: Box::new(PyObject::new_tuple(py_context.constant_list, false)),
+        name_list: Box::new(PyObject::new_tuple(py_context.name_list, false)),
+        local_list: Box::new(PyObject::new_tuple(
+            py_context
                 .local_variables
                 .iter()
                 .map(|v| PyObject::new_string(v.to_string(), false))
                 .collect(),
-            add_ref: false,
-        }),
+            false,
+        )),
+        first_line_number: 1,
+        line_table: vec![],
         add_ref: false,
     })
 }
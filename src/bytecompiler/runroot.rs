@@ -1,13 +1,16 @@
 use std::time::SystemTime;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use crate::bytecode::{calc_stack_size, OpCode};
 use crate::executioncontext::{ExecutionContext, GlobalContext};
-use crate::parser::node::LibraryDeclaration;
+use crate::parser::node::{LibraryDeclaration, NodeStatement};
 use crate::pyobject::PyObject;
+use crate::resolver::Resolver;
+use crate::BuildOptions;
 
+use super::statictype::DeclaredTypes;
 use super::ByteCompiler;
 
 pub fn run_root<'value>(
@@ -16,12 +19,15 @@ pub fn run_root<'value>(
     source: &'value str,
     time_start_build: SystemTime,
     is_root: bool,
+    options: &'value BuildOptions,
+    listing: Option<&'value RefCell<String>>,
 ) -> Result<PyObject> {
     let global_context = Rc::new(RefCell::new(GlobalContext {
         constant_list: vec![],
         name_list: vec![],
         name_map: HashMap::new(),
         global_variables: vec![],
+        variable_types: HashMap::new(),
     }));
 
     let mut compiler = ByteCompiler {
@@ -32,22 +38,63 @@ pub fn run_root<'value>(
         default_scope_stack: vec![],
         break_label_table: HashMap::new(),
         continue_label_table: HashMap::new(),
+        block_stack: vec![],
         source,
+        options,
+        resolver: Rc::new(Resolver::new()),
+        declared_types: Rc::new(DeclaredTypes::new(&root_node.top_level_declaration_list)),
+        listing,
     };
 
+    // トップレベルの宣言は宣言より前からも参照できる
+    for node in &root_node.top_level_declaration_list {
+        match &**node {
+            NodeStatement::FunctionDeclaration { signature, .. } => {
+                compiler.resolver.declare(signature.name.value)
+            }
+            NodeStatement::VariableDeclarationList { decl_list } => {
+                for decl in decl_list {
+                    compiler.resolver.declare(decl.identifier.value);
+                }
+            }
+            NodeStatement::ClassDeclaration { identifier, .. }
+            | NodeStatement::ExternalDeclaration { identifier } => {
+                compiler.resolver.declare(identifier.value)
+            }
+            _ => (),
+        }
+    }
+    let class_list: Vec<&NodeStatement> = root_node
+        .top_level_declaration_list
+        .iter()
+        .map(|v| &**v)
+        .filter(|v| matches!(v, NodeStatement::ClassDeclaration { .. }))
+        .collect();
+    compiler.resolver.check_classes(&class_list);
+
     // 0番目の定数にNoneを追加
     (*global_context)
         .borrow_mut()
         .push_const(PyObject::None(false));
 
+    compiler.push_runtime_prologue();
+
     for node in &root_node.import_list {
         compiler.compile_import(node, time_start_build)?;
     }
 
+    // exportはモジュールの名前空間に取り込むことで再公開する
+    for node in &root_node.export_list {
+        compiler.compile_import(node, time_start_build)?;
+    }
+
     for node in &root_node.top_level_declaration_list {
         compiler.compile_stmt(&node, None)?;
     }
 
+    // 解決できなかった名前をまとめて報告する
+    compiler.resolver.finish()?;
+
     if is_root {
         // main関数を実行
         let main_position = (*global_context)
@@ -60,22 +107,24 @@ pub fn run_root<'value>(
     compiler.push_op(OpCode::LoadConst(0));
     compiler.push_op(OpCode::ReturnValue);
 
-    let stack_size = calc_stack_size(&compiler.byte_operations.borrow()) as u32;
-    let operation_list = compiler.resolve_references();
+    compiler.optimize(&mut global_context.borrow_mut().constant_list)?;
+    let stack_size = calc_stack_size(
+        &compiler.byte_operations.borrow(),
+        &compiler.jump_label_table.borrow(),
+    )
+    .context("invalid bytecode in <module>")?;
+    compiler.dump_operations("<module>")?;
+    let operation_list = compiler
+        .resolve_references()
+        .context("invalid bytecode in <module>")?;
 
     compiler.context_stack.pop();
 
     // 所有が1箇所しかないはずなのでRcの外に出す
     let global_context = Rc::try_unwrap(global_context).ok().unwrap().into_inner();
 
-    let constant_list = PyObject::SmallTuple {
-        children: global_context.constant_list,
-        add_ref: false,
-    };
-    let name_list = PyObject::SmallTuple {
-        children: global_context.name_list,
-        add_ref: false,
-    };
+    let constant_list = PyObject::new_tuple(global_context.constant_list, false);
+    let name_list = PyObject::new_tuple(global_context.name_list, false);
 
     Ok(PyObject::Code {
         file_name: file_name.to_string(),
@@ -92,6 +141,8 @@ pub fn run_root<'value>(
             children: vec![],
             add_ref: false,
         }),
+        first_line_number: 1,
+        line_table: vec![],
         add_ref: true,
     })
 }
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use crate::executioncontext::{StaticType, VariableScope};
+use crate::parser::node::{DartType, Member, NodeExpression, NodeStatement, Selector};
+use crate::runtime;
+
+use super::ByteCompiler;
+
+// クラスのスーパークラスと、型の分かるメンバー
+struct ClassTypes {
+    superclass: Option<String>,
+    members: HashMap<String, StaticType>,
+}
+
+/// トップレベルで宣言されたクラスのメンバーと関数の戻り値の型
+/// dart:coreのメンバーを実行時ライブラリで扱うかを、レシーバーの型で決めるために使う
+#[derive(Default)]
+pub struct DeclaredTypes {
+    classes: HashMap<String, ClassTypes>,
+    functions: HashMap<String, StaticType>,
+}
+
+impl DeclaredTypes {
+    pub fn new(declaration_list: &[Box<NodeStatement>]) -> Self {
+        let mut declared_types = DeclaredTypes::default();
+        // 型の名前を解決するために、先にクラスの名前を登録する
+        for node in declaration_list {
+            if let NodeStatement::ClassDeclaration {
+                identifier,
+                superclass,
+                ..
+            } = &**node
+            {
+                let superclass = match superclass {
+                    Some(DartType::Named { type_name, .. }) => {
+                        Some(type_name.identifier.value.to_string())
+                    }
+                    _ => None,
+                };
+                declared_types.classes.insert(
+                    identifier.value.to_string(),
+                    ClassTypes {
+                        superclass,
+                        members: HashMap::new(),
+                    },
+                );
+            }
+        }
+
+        for node in declaration_list {
+            match &**node {
+                NodeStatement::FunctionDeclaration { signature, .. } => {
+                    if let Some(v) = declared_types.resolve_type(signature.return_type.as_ref()) {
+                        declared_types
+                            .functions
+                            .insert(signature.name.value.to_string(), v);
+                    }
+                }
+                NodeStatement::ClassDeclaration {
+                    identifier,
+                    member_list,
+                    ..
+                } => {
+                    let members = declared_types.member_types(member_list);
+                    if let Some(class) = declared_types.classes.get_mut(identifier.value) {
+                        class.members = members;
+                    }
+                }
+                _ => (),
+            }
+        }
+        declared_types
+    }
+
+    /// 宣言された型をStaticTypeにする
+    pub fn resolve_type(&self, dart_type: Option<&DartType>) -> Option<StaticType> {
+        let Some(DartType::Named { type_name, .. }) = dart_type else {
+            return None;
+        };
+        let name = type_name.identifier.value;
+        if type_name.module.is_some() {
+            None
+        } else if self.classes.contains_key(name) {
+            Some(StaticType::Class(name.to_string()))
+        } else if runtime::CORE_TYPES.contains(&name) {
+            Some(StaticType::Core)
+        } else {
+            None
+        }
+    }
+
+    // インスタンス変数と、戻り値の型が宣言されたメソッド
+    fn member_types(&self, member_list: &[Member]) -> HashMap<String, StaticType> {
+        let mut members = HashMap::new();
+        for member in member_list {
+            match member {
+                Member::VariableDecl { decl_list } => {
+                    for decl in decl_list {
+                        let static_type = match &decl.dart_type {
+                            Some(v) => self.resolve_type(Some(v)),
+                            None => decl.expr.as_deref().and_then(|v| self.literal_type(v)),
+                        };
+                        if let Some(v) = static_type {
+                            members.insert(decl.identifier.value.to_string(), v);
+                        }
+                    }
+                }
+                Member::MethodImpl { signature, .. }
+                | Member::GetterImpl { signature, .. }
+                | Member::AbstractMethod { signature, .. } => {
+                    if let Some(v) = self.resolve_type(signature.return_type.as_ref()) {
+                        members.insert(signature.name.value.to_string(), v);
+                    }
+                }
+                _ => (),
+            }
+        }
+        members
+    }
+
+    // スコープによらず型の分かるリテラルとコンストラクタの呼び出し
+    fn literal_type(&self, expr: &NodeExpression) -> Option<StaticType> {
+        match expr {
+            NodeExpression::NumericLiteral { .. }
+            | NodeExpression::StringLiteral { .. }
+            | NodeExpression::BooleanLiteral { .. }
+            | NodeExpression::ListLiteral { .. }
+            | NodeExpression::SetOrMapLiteral { .. } => Some(StaticType::Core),
+            NodeExpression::Selector {
+                child,
+                selector: Selector::Args { .. },
+            } => match &**child {
+                NodeExpression::Identifier { identifier }
+                    if self.classes.contains_key(identifier.value) =>
+                {
+                    Some(StaticType::Class(identifier.value.to_string()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // スーパークラスも辿ってメンバーの型を探す
+    fn member_type(&self, class_name: &str, name: &str) -> Option<StaticType> {
+        let mut current = Some(class_name);
+        // 循環した継承でも止まるよう、クラスの数だけ辿る
+        for _ in 0..self.classes.len() {
+            let class = self.classes.get(current?)?;
+            if let Some(v) = class.members.get(name) {
+                return Some(v.clone());
+            }
+            current = class.superclass.as_deref();
+        }
+        None
+    }
+}
+
+impl<'ctx, 'value> ByteCompiler<'ctx, 'value> {
+    /// child.nameを実行時ライブラリのgetprop, invokeで扱うか
+    /// Objectのメンバー以外は、レシーバーの静的な型がdart:coreの型の場合だけ扱う
+    /// 型の分からない値(dynamic, Pythonのオブジェクト)のメンバーはそのまま参照する
+    pub fn is_core_member_access(&self, child: &NodeExpression, name: &str) -> bool {
+        runtime::CORE_MEMBERS.contains(&name)
+            && (runtime::OBJECT_MEMBERS.contains(&name)
+                || self.static_type(child) == Some(StaticType::Core))
+    }
+
+    /// 式の静的な型を返す。分からなければNone
+    pub fn static_type(&self, expr: &NodeExpression) -> Option<StaticType> {
+        match expr {
+            NodeExpression::NumericLiteral { .. }
+            | NodeExpression::StringLiteral { .. }
+            | NodeExpression::BooleanLiteral { .. }
+            | NodeExpression::ListLiteral { .. }
+            | NodeExpression::SetOrMapLiteral { .. }
+            | NodeExpression::TypeTest { .. } => Some(StaticType::Core),
+            NodeExpression::Unary { operator, expr } => match *operator {
+                "!" => Some(StaticType::Core),
+                _ => self.static_type(expr).filter(|v| *v == StaticType::Core),
+            },
+            NodeExpression::Update { child, .. } => {
+                self.static_type(child).filter(|v| *v == StaticType::Core)
+            }
+            NodeExpression::Binary {
+                left,
+                operator,
+                right,
+            } => match *operator {
+                // 比較や論理演算の結果はbool
+                "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||" => Some(StaticType::Core),
+                "??" => self
+                    .static_type(left)
+                    .filter(|v| self.static_type(right).as_ref() == Some(v)),
+                // 演算子を定義したクラスの演算結果の型は分からない
+                _ => self.static_type(left).filter(|v| *v == StaticType::Core),
+            },
+            NodeExpression::Conditional {
+                true_expr,
+                false_expr,
+                ..
+            } => self
+                .static_type(true_expr)
+                .filter(|v| self.static_type(false_expr).as_ref() == Some(v)),
+            NodeExpression::Assignment {
+                operator, right, ..
+            } if *operator == "=" => self.static_type(right),
+            NodeExpression::TypeCast { type_cast, .. } => self.resolve_type(Some(type_cast)),
+            NodeExpression::Identifier { identifier } => self
+                .context_stack
+                .last()
+                .unwrap()
+                .borrow()
+                .get_variable_type(identifier.value),
+            NodeExpression::Selector { child, selector } => match selector {
+                Selector::Attr { identifier } | Selector::Method { identifier, .. } => {
+                    self.member_type(child, selector, identifier.value)
+                }
+                Selector::Args { .. } => match &**child {
+                    // コンストラクタと、戻り値の型が宣言された関数の呼び出し
+                    NodeExpression::Identifier { identifier }
+                        if self.is_top_level_name(identifier.value) =>
+                    {
+                        let name = identifier.value;
+                        if self.declared_types.classes.contains_key(name) {
+                            Some(StaticType::Class(name.to_string()))
+                        } else {
+                            self.declared_types.functions.get(name).cloned()
+                        }
+                    }
+                    _ => None,
+                },
+                Selector::Index { .. } => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// 宣言された型をStaticTypeにする
+    pub fn resolve_type(&self, dart_type: Option<&DartType>) -> Option<StaticType> {
+        self.declared_types.resolve_type(dart_type)
+    }
+
+    /// 現在のスコープで宣言した変数の静的な型を記録する
+    /// 型が書かれていなければ初期化式から推論する
+    pub fn set_variable_type(
+        &self,
+        name: &str,
+        dart_type: Option<&DartType>,
+        initializer: Option<&NodeExpression>,
+    ) {
+        let static_type = match (dart_type, initializer) {
+            (Some(_), _) => self.resolve_type(dart_type),
+            (None, Some(expr)) => self.static_type(expr),
+            (None, None) => None,
+        };
+        self.context_stack
+            .last()
+            .unwrap()
+            .borrow_mut()
+            .set_variable_type(name, static_type);
+    }
+
+    // child.nameの型
+    fn member_type(
+        &self,
+        child: &NodeExpression,
+        selector: &Selector,
+        name: &str,
+    ) -> Option<StaticType> {
+        if runtime::OBJECT_MEMBERS.contains(&name) && name != "runtimeType" {
+            return Some(StaticType::Core);
+        }
+        match child {
+            // int.parse, List.filledなどの静的メンバー
+            NodeExpression::Identifier { identifier }
+                if runtime::CORE_TYPES.contains(&identifier.value)
+                    && self.is_top_level_name(identifier.value)
+                    && !self.declared_types.classes.contains_key(identifier.value) =>
+            {
+                return Some(StaticType::Core);
+            }
+            // this.xはインスタンス変数の宣言された型による
+            NodeExpression::This if matches!(selector, Selector::Attr { .. }) => {
+                let context = self.context_stack.last().unwrap().borrow();
+                return match context.check_variable_scope(&name.to_string()) {
+                    VariableScope::Instance => context.get_variable_type(name),
+                    _ => None,
+                };
+            }
+            _ => (),
+        }
+        match self.static_type(child)? {
+            StaticType::Core => (runtime::CORE_MEMBERS.contains(&name)
+                && !runtime::UNTYPED_CORE_MEMBERS.contains(&name))
+            .then_some(StaticType::Core),
+            StaticType::Class(class_name) => self.declared_types.member_type(&class_name, name),
+        }
+    }
+
+    // ローカル変数などに隠されていないトップレベルの名前か
+    fn is_top_level_name(&self, name: &str) -> bool {
+        matches!(
+            self.context_stack
+                .last()
+                .unwrap()
+                .borrow()
+                .check_variable_scope(&name.to_string()),
+            VariableScope::Global | VariableScope::NotDefined
+        )
+    }
+}
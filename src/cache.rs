@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::library::{scan_imported_uris, scan_part_uris};
+use crate::{compile_file, siphash, transition_map, BuildOptions, PYC_MAGIC_NUMBER};
+use dart_parser_generator::parser_generator::TransitionMap;
+
+// インポートしたDartのモジュールのビルドキャッシュ
+// モジュールごとに、ビルドしたときのソースと依存するファイルのキーを記録し、
+// どれかが変わったモジュールだけをビルドし直す
+const CACHE_DIRECTORY: &str = ".elaphe/cache";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    source: String,
+    key: String,
+    dependencies: Vec<Dependency>,
+    parts: Vec<Dependency>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Dependency {
+    source: String,
+    key: String,
+}
+
+/// uriのモジュールが古ければビルドする
+/// 新しければ、依存するモジュールも新しいか確かめる
+pub fn build_module(
+    uri: &str,
+    output: &Path,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+) -> Result<()> {
+    build_module_internal(uri, output, time_start_build, options, &mut vec![])
+}
+
+fn build_module_internal(
+    uri: &str,
+    output: &Path,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+    visiting: &mut Vec<String>,
+) -> Result<()> {
+    // このビルドの中でビルド済み(循環するインポートではビルド中)
+    if is_built_in_this_session(output, time_start_build) || visiting.iter().any(|v| v == uri) {
+        return Ok(());
+    }
+
+    let source = fs::read_to_string(uri).with_context(|| format!("failed to read {}", uri))?;
+    if let Some(entry) = read_entry(uri).filter(|v| !needs_build(v, &source, output, options)) {
+        visiting.push(uri.to_string());
+        for dependency in &entry.dependencies {
+            if let Some(output) = module_output(&dependency.source) {
+                build_module_internal(
+                    &dependency.source,
+                    &output,
+                    time_start_build,
+                    options,
+                    visiting,
+                )?;
+            }
+        }
+        visiting.pop();
+        return Ok(());
+    }
+
+    claim(uri, output)?;
+    build_claimed(
+        uri,
+        output,
+        &source,
+        time_start_build,
+        options,
+        transition_map(),
+    )
+}
+
+/// uriのモジュールをビルドし直す必要があるか
+pub fn is_stale(uri: &str, source: &str, output: &Path, options: &BuildOptions) -> bool {
+    read_entry(uri).is_none_or(|v| needs_build(&v, source, output, options))
+}
+
+/// これからビルドするモジュールの出力先を先に作り、このビルドの中でビルド済みとして扱わせる
+/// ビルドに失敗したときに古いキャッシュが残らないよう、キャッシュは消しておく
+pub fn claim(uri: &str, output: &Path) -> Result<()> {
+    remove_entry(uri);
+    fs::File::create(output).with_context(|| format!("failed to create {}", output.display()))?;
+    Ok(())
+}
+
+/// claimしたモジュールをビルドし、キャッシュに記録する
+pub fn build_claimed(
+    uri: &str,
+    output: &Path,
+    source: &str,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+    transition_map: &TransitionMap,
+) -> Result<()> {
+    compile_file(
+        output.to_str().unwrap(),
+        uri,
+        source,
+        time_start_build,
+        false,
+        options,
+        transition_map,
+    )?;
+
+    // ファイルを読めない依存先は、空のファイルとして記録する
+    let dependencies = scan_imported_uris(source)?
+        .into_iter()
+        .filter(|v| !v.contains(':'))
+        .map(|v| Dependency {
+            key: file_key(&v, options),
+            source: v,
+        })
+        .collect();
+    let parts = scan_part_uris(source)?
+        .into_iter()
+        .map(|v| Dependency {
+            key: file_key(&v, options),
+            source: v,
+        })
+        .collect();
+    write_entry(&CacheEntry {
+        source: uri.to_string(),
+        key: source_key(source, options),
+        dependencies,
+        parts,
+    })
+}
+
+// 出力先が無いか、ソース、partのファイル、依存するファイルのどれかが変わった
+fn needs_build(entry: &CacheEntry, source: &str, output: &Path, options: &BuildOptions) -> bool {
+    !output.exists() || !is_up_to_date(entry, source, options)
+}
+
+fn is_up_to_date(entry: &CacheEntry, source: &str, options: &BuildOptions) -> bool {
+    entry.key == source_key(source, options)
+        && entry
+            .dependencies
+            .iter()
+            .chain(&entry.parts)
+            .all(|v| v.key == file_key(&v.source, options))
+}
+
+pub fn is_built_in_this_session(output: &Path, time_start_build: SystemTime) -> bool {
+    fs::metadata(output)
+        .and_then(|v| v.modified())
+        .is_ok_and(|v| v >= time_start_build)
+}
+
+// ビルドするDartのモジュールであれば、出力する.pycのパス
+// .d.dartのスタブはビルドしない
+pub fn module_output(uri: &str) -> Option<PathBuf> {
+    let is_stub = uri.ends_with(".d.dart")
+        || uri
+            .split('/')
+            .find(|v| !v.chars().all(|c| c == '.'))
+            .is_some_and(|v| v == "elaphe");
+    if uri.ends_with(".dart") && !is_stub {
+        Some(Path::new(uri).with_extension("pyc"))
+    } else {
+        None
+    }
+}
+
+/// コンパイラのバージョン、対象のPythonのバージョン、ビルドの設定とソースから決まるキー
+fn source_key(source: &str, options: &BuildOptions) -> String {
+    let mut data = vec![];
+    data.extend(env!("CARGO_PKG_VERSION").as_bytes());
+    data.push(0);
+    data.extend(PYC_MAGIC_NUMBER);
+    data.extend(format!("{:?}", options).as_bytes());
+    data.push(0);
+    data.extend(source.as_bytes());
+    hash(&data)
+}
+
+fn file_key(uri: &str, options: &BuildOptions) -> String {
+    source_key(&fs::read_to_string(uri).unwrap_or_default(), options)
+}
+
+// 128bitのハッシュ
+fn hash(data: &[u8]) -> String {
+    format!(
+        "{:016x}{:016x}",
+        siphash::siphash(0, 0, data),
+        siphash::siphash(0, 1, data)
+    )
+}
+
+fn entry_path(uri: &str) -> PathBuf {
+    Path::new(CACHE_DIRECTORY).join(format!("{}.json", hash(uri.as_bytes())))
+}
+
+// 壊れたキャッシュは無いものとして扱う
+fn read_entry(uri: &str) -> Option<CacheEntry> {
+    let text = fs::read_to_string(entry_path(uri)).ok()?;
+    serde_json::from_str::<CacheEntry>(&text)
+        .ok()
+        .filter(|v| v.source == uri)
+}
+
+fn remove_entry(uri: &str) {
+    fs::remove_file(entry_path(uri)).ok();
+}
+
+fn write_entry(entry: &CacheEntry) -> Result<()> {
+    let path = entry_path(&entry.source);
+    fs::create_dir_all(CACHE_DIRECTORY)
+        .with_context(|| format!("failed to create {}", CACHE_DIRECTORY))?;
+    fs::write(&path, serde_json::to_string_pretty(entry)? + "\n")
+        .with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,355 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+
+use crate::bytecode::{self, OpCode, EXTENDED_ARG};
+use crate::pyobject::PyObject;
+
+const HAVE_ARGUMENT: u8 = 90;
+
+/// コードオブジェクトを`python -m dis`と同じような形式で出力する
+/// 定数に含まれるコードオブジェクトも続けて出力する
+pub fn disassemble(code: &PyObject) -> Result<String> {
+    let mut result = String::new();
+    let mut code_list = vec![code];
+    while let Some(code) = code_list.pop() {
+        let PyObject::Code {
+            code_name,
+            operation_list,
+            constant_list,
+            name_list,
+            local_list,
+            first_line_number,
+            line_table,
+            ..
+        } = code
+        else {
+            bail!("not a code object");
+        };
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        writeln!(result, "Disassembly of {}:", code_name)?;
+
+        let constants = children(constant_list);
+        let names = children(name_list);
+        let locals = children(local_list);
+        let line_starts = line_starts(*first_line_number, line_table);
+
+        // 引数を伸ばすEXTENDED_ARGを畳み込みながら命令を読む
+        let mut instructions = vec![];
+        let mut extended_arg = 0u32;
+        for (i, op) in operation_list.iter().enumerate() {
+            let arg = extended_arg | op.operand() as u32;
+            extended_arg = if op.operation() == EXTENDED_ARG {
+                arg << 8
+            } else {
+                0
+            };
+            instructions.push((i as u32 * 2, op.operation(), arg));
+        }
+
+        let decoded = instructions
+            .iter()
+            .map(|&(offset, operation, arg)| {
+                OpCode::decode(operation, arg).map(|op| (op, jump_target(&op, offset)))
+            })
+            .collect::<Vec<_>>();
+        let targets: HashSet<u32> = decoded
+            .iter()
+            .filter_map(|v| v.and_then(|(_, target)| target))
+            .collect();
+
+        for (&(offset, operation, arg), decoded) in instructions.iter().zip(&decoded) {
+            let line = match line_starts.get(&offset) {
+                Some(line) => {
+                    if offset > 0 {
+                        result.push('\n');
+                    }
+                    format!("{:>3}", line)
+                }
+                None => "   ".to_string(),
+            };
+            let marker = if targets.contains(&offset) {
+                ">>"
+            } else {
+                "  "
+            };
+            let (name, description) = match decoded {
+                Some((op, target)) => (
+                    op.name().to_string(),
+                    describe(op, arg, *target, &constants, &names, &locals),
+                ),
+                None if operation == EXTENDED_ARG => ("EXTENDED_ARG".to_string(), None),
+                None => (format!("<{}>", operation), None),
+            };
+            let mut line = format!("{}     {} {:>4} {:<20}", line, marker, offset, name);
+            if operation >= HAVE_ARGUMENT {
+                write!(line, " {:>5}", arg)?;
+            }
+            if let Some(description) = description {
+                write!(line, " ({})", description)?;
+            }
+            writeln!(result, "{}", line.trim_end())?;
+        }
+
+        // 入れ子のコードオブジェクトは定数表の順に出力する
+        code_list.extend(
+            constants
+                .iter()
+                .rev()
+                .filter(|v| matches!(v, PyObject::Code { .. })),
+        );
+    }
+    Ok(result)
+}
+
+/// 分岐先を解決する前の命令列を、ジャンプをラベルで表して出力する
+pub fn format_operations(
+    code_name: &str,
+    operation_list: &[OpCode],
+    label_table: &HashMap<u32, u32>,
+) -> Result<String> {
+    // 分岐先の命令の位置とラベル
+    let mut labels: HashMap<usize, Vec<u32>> = HashMap::new();
+    for (i, op) in operation_list.iter().enumerate() {
+        if let (Some(label), Some(target)) = (
+            bytecode::jump_label(op),
+            bytecode::jump_target(operation_list, i, label_table)?,
+        ) {
+            let list = labels.entry(target).or_default();
+            if !list.contains(&label) {
+                list.push(label);
+            }
+        }
+    }
+
+    let mut result = String::new();
+    writeln!(result, "{}:", code_name)?;
+    for (i, op) in operation_list.iter().enumerate() {
+        if let Some(list) = labels.get_mut(&i) {
+            list.sort_unstable();
+            for label in list {
+                writeln!(result, "  L{}:", label)?;
+            }
+        }
+        let mut line = format!("    {:<20}", op.name());
+        if let Some(label) = bytecode::jump_label(op) {
+            write!(line, " L{}", label)?;
+        } else if let Some(argument) = op.argument() {
+            write!(line, " {}", argument)?;
+        }
+        writeln!(result, "{}", line.trim_end())?;
+    }
+    Ok(result)
+}
+
+fn children(object: &PyObject) -> Vec<&PyObject> {
+    match object {
+        PyObject::SmallTuple { children, .. } | PyObject::Tuple { children, .. } => {
+            children.iter().collect()
+        }
+        _ => vec![],
+    }
+}
+
+// 命令のオフセットから分岐先のオフセットを求める
+fn jump_target(op: &OpCode, offset: u32) -> Option<u32> {
+    let target = bytecode::jump_label(op)?;
+    if bytecode::is_relative_jump(op) {
+        Some(offset + 2 + target)
+    } else {
+        Some(target)
+    }
+}
+
+// 引数の意味
+fn describe(
+    op: &OpCode,
+    arg: u32,
+    target: Option<u32>,
+    constants: &[&PyObject],
+    names: &[&PyObject],
+    locals: &[&PyObject],
+) -> Option<String> {
+    if let Some(target) = target {
+        return Some(format!("to {}", target));
+    }
+    let index = arg as usize;
+    match op {
+        OpCode::LoadConst(_) => constants.get(index).map(|v| repr(v)),
+        OpCode::StoreName(_)
+        | OpCode::StoreAttr(_)
+        | OpCode::StoreGlobal(_)
+        | OpCode::LoadName(_)
+        | OpCode::LoadAttr(_)
+        | OpCode::ImportName(_)
+        | OpCode::ImportFrom(_)
+        | OpCode::LoadGlobal(_)
+        | OpCode::LoadMethod(_) => names.get(index).and_then(|v| v.as_str()).map(String::from),
+        OpCode::LoadFast(_) | OpCode::StoreFast(_) => {
+            locals.get(index).and_then(|v| v.as_str()).map(String::from)
+        }
+        OpCode::CompareOp(_) => ["<", "<=", "==", "!=", ">", ">="]
+            .get(index)
+            .map(|v| v.to_string()),
+        OpCode::ContainsOp(_) => Some(if arg == 0 { "in" } else { "not in" }.to_string()),
+        _ => None,
+    }
+}
+
+// lnotabから、行の始まる命令のオフセットと行番号を求める
+// https://github.com/python/cpython/blob/3.9/Objects/lnotab_notes.txt
+fn line_starts(first_line_number: u32, line_table: &[u8]) -> HashMap<u32, i64> {
+    let mut result = HashMap::new();
+    let mut last_line = None;
+    let mut line = first_line_number as i64;
+    let mut offset = 0u32;
+    for pair in line_table.chunks(2) {
+        let (offset_incr, line_incr) = (pair[0], *pair.get(1).unwrap_or(&0) as i8);
+        if offset_incr > 0 {
+            if last_line != Some(line) {
+                result.insert(offset, line);
+                last_line = Some(line);
+            }
+            offset += offset_incr as u32;
+        }
+        line += line_incr as i64;
+    }
+    if last_line != Some(line) {
+        result.insert(offset, line);
+    }
+    result
+}
+
+/// Pythonのreprに近い形式の文字列
+fn repr(object: &PyObject) -> String {
+    match object {
+        PyObject::Int(v, _) => v.to_string(),
+        PyObject::Long {
+            digits, negative, ..
+        } => long_to_string(digits, *negative),
+        PyObject::Float(v, _) => float_to_string(*v),
+        PyObject::Complex(real, imag, _) => {
+            let imag = float_to_string(*imag);
+            if *real == 0.0 && real.is_sign_positive() {
+                format!("{}j", imag)
+            } else if imag.starts_with('-') {
+                format!("({}{}j)", float_to_string(*real), imag)
+            } else {
+                format!("({}+{}j)", float_to_string(*real), imag)
+            }
+        }
+        PyObject::String(v, _) => {
+            let mut result = "b'".to_string();
+            for &c in v {
+                match c {
+                    b'\'' => result.push_str("\\'"),
+                    b'\\' => result.push_str("\\\\"),
+                    b'\n' => result.push_str("\\n"),
+                    b'\r' => result.push_str("\\r"),
+                    b'\t' => result.push_str("\\t"),
+                    0x20..=0x7E => result.push(c as char),
+                    _ => result.push_str(&format!("\\x{:02x}", c)),
+                }
+            }
+            result.push('\'');
+            result
+        }
+        PyObject::Ascii(v, _) | PyObject::AsciiShort(v, _) | PyObject::Unicode(v, _) => {
+            let quote = if v.contains('\'') && !v.contains('"') {
+                '"'
+            } else {
+                '\''
+            };
+            let mut result = quote.to_string();
+            for c in v.chars() {
+                match c {
+                    '\\' => result.push_str("\\\\"),
+                    '\n' => result.push_str("\\n"),
+                    '\r' => result.push_str("\\r"),
+                    '\t' => result.push_str("\\t"),
+                    c if c == quote => {
+                        result.push('\\');
+                        result.push(c);
+                    }
+                    c if c.is_control() => result.push_str(&format!("\\x{:02x}", c as u32)),
+                    c => result.push(c),
+                }
+            }
+            result.push(quote);
+            result
+        }
+        PyObject::None(_) => "None".to_string(),
+        PyObject::True(_) => "True".to_string(),
+        PyObject::False(_) => "False".to_string(),
+        PyObject::Ellipsis(_) => "Ellipsis".to_string(),
+        PyObject::SmallTuple { children, .. } | PyObject::Tuple { children, .. } => {
+            let children: Vec<String> = children.iter().map(repr).collect();
+            if children.len() == 1 {
+                format!("({},)", children[0])
+            } else {
+                format!("({})", children.join(", "))
+            }
+        }
+        PyObject::FrozenSet { children, .. } => {
+            if children.is_empty() {
+                "frozenset()".to_string()
+            } else {
+                let children: Vec<String> = children.iter().map(repr).collect();
+                format!("frozenset({{{}}})", children.join(", "))
+            }
+        }
+        PyObject::Code { code_name, .. } => format!("<code object {}>", code_name),
+    }
+}
+
+// Pythonと同じく、指数表記では指数を2桁以上で符号付きにする
+fn float_to_string(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let text = format!("{:?}", value);
+    match text.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let (sign, digits) = match exponent.strip_prefix('-') {
+                Some(digits) => ('-', digits),
+                None => ('+', exponent),
+            };
+            let mantissa = mantissa.strip_suffix(".0").unwrap_or(mantissa);
+            format!("{}e{}{:0>2}", mantissa, sign, digits)
+        }
+        None => text,
+    }
+}
+
+// 2^15進数の桁列を10進数の文字列にする
+fn long_to_string(digits: &[u16], negative: bool) -> String {
+    let mut digits: Vec<u32> = digits.iter().map(|&v| v as u32).collect();
+    let mut chunks = vec![];
+    while digits.iter().any(|&v| v != 0) {
+        // 10000で割った余りを下位から求める
+        let mut remainder = 0u32;
+        for digit in digits.iter_mut().rev() {
+            let v = (remainder << 15) | *digit;
+            *digit = v / 10000;
+            remainder = v % 10000;
+        }
+        chunks.push(remainder);
+    }
+    let mut result = match chunks.pop() {
+        Some(v) => v.to_string(),
+        None => "0".to_string(),
+    };
+    for chunk in chunks.iter().rev() {
+        write!(result, "{:04}", chunk).unwrap();
+    }
+    if negative {
+        result.insert(0, '-');
+    }
+    result
+}
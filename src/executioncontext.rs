@@ -5,56 +5,88 @@ use crate::pyobject::PyObject;
 pub struct GlobalContext {
     pub constant_list: Vec<PyObject>,
     pub name_list: Vec<PyObject>,
-    pub name_map: HashMap<String, u8>,
+    pub name_map: HashMap<String, u32>,
     pub global_variables: Vec<String>,
+    pub variable_types: HashMap<String, StaticType>,
 }
 pub struct PyContext<'ctx> {
     pub outer: Rc<RefCell<dyn ExecutionContext + 'ctx>>,
     pub constant_list: Vec<PyObject>,
     pub name_list: Vec<PyObject>,
-    pub name_map: HashMap<String, u8>,
+    pub name_map: HashMap<String, u32>,
     pub local_variables: Vec<String>,
 }
 pub struct BlockContext<'ctx> {
     pub outer: Rc<RefCell<dyn ExecutionContext + 'ctx>>,
-    pub variables: Vec<String>,
+    // ブロック内で宣言された変数と、割り当てられたローカル変数の位置
+    pub variables: Vec<(String, u32)>,
+    pub variable_types: HashMap<String, StaticType>,
 }
 
 pub struct ClassContext<'ctx> {
     pub outer: Rc<RefCell<dyn ExecutionContext + 'ctx>>,
     pub instance_variables: Vec<String>,
+    pub variable_types: HashMap<String, StaticType>,
 }
 
 pub trait ExecutionContext {
-    fn push_const(&mut self, value: PyObject);
-    fn const_len(&self) -> usize;
-    fn declare_variable(&mut self, symbol: &String) -> u8;
-    fn get_local_variable(&self, symbol: &String) -> u8;
+    fn push_const(&mut self, value: PyObject) -> u32;
+    fn declare_variable(&mut self, symbol: &String) -> u32;
+    fn allocate_local_variable(&mut self, symbol: &str) -> u32;
+    fn is_declared_in_block(&self, symbol: &str) -> bool;
+    fn get_local_variable(&self, symbol: &String) -> u32;
     fn check_variable_scope(&self, symbol: &String) -> VariableScope;
-    fn register_or_get_name(&mut self, name: &String) -> u8;
+    fn register_or_get_name(&mut self, name: &String) -> u32;
     fn is_global(&self) -> bool;
+    // 変数の静的な型を記録する (分からなければNone)
+    fn set_variable_type(&mut self, symbol: &str, static_type: Option<StaticType>);
+    fn get_variable_type(&self, symbol: &str) -> Option<StaticType>;
 }
 
-impl ExecutionContext for GlobalContext {
-    fn push_const(&mut self, value: PyObject) {
-        self.constant_list.push(value);
-    }
+/// コンパイル時に分かる値の型
+#[derive(Clone, PartialEq, Debug)]
+pub enum StaticType {
+    // int, String, Listなどのdart:coreの型
+    Core,
+    // このファイルで宣言されたクラス
+    Class(String),
+}
+
+// 型の分からない宣言は、外側の同名の変数の型を隠す
+fn insert_variable_type(
+    variable_types: &mut HashMap<String, StaticType>,
+    symbol: &str,
+    static_type: Option<StaticType>,
+) {
+    match static_type {
+        Some(v) => variable_types.insert(symbol.to_string(), v),
+        None => variable_types.remove(symbol),
+    };
+}
 
-    fn const_len(&self) -> usize {
-        self.constant_list.len()
+impl ExecutionContext for GlobalContext {
+    fn push_const(&mut self, value: PyObject) -> u32 {
+        intern_constant(&mut self.constant_list, value)
     }
 
-    fn declare_variable(&mut self, symbol: &String) -> u8 {
+    fn declare_variable(&mut self, symbol: &String) -> u32 {
         // グローバル変数の定義
-        let position = self.name_list.len() as u8;
-        let obj = PyObject::new_string(symbol.to_string(), false);
-        self.name_list.push(obj);
-        self.name_map.insert(symbol.to_string(), position);
-        self.global_variables.push(symbol.clone());
+        let position = self.register_or_get_name(symbol);
+        if !self.global_variables.contains(symbol) {
+            self.global_variables.push(symbol.clone());
+        }
         position
     }
 
-    fn get_local_variable(&self, _symbol: &String) -> u8 {
+    fn allocate_local_variable(&mut self, _symbol: &str) -> u32 {
+        unreachable!("GlobalContext has no local variable");
+    }
+
+    fn is_declared_in_block(&self, _symbol: &str) -> bool {
+        false
+    }
+
+    fn get_local_variable(&self, _symbol: &String) -> u32 {
         unreachable!("GlobalContext has no local variable");
     }
 
@@ -66,11 +98,11 @@ impl ExecutionContext for GlobalContext {
         }
     }
 
-    fn register_or_get_name(&mut self, name: &String) -> u8 {
+    fn register_or_get_name(&mut self, name: &String) -> u32 {
         match self.name_map.get(name) {
             Some(v) => *v,
             None => {
-                let position = self.name_list.len() as u8;
+                let position = self.name_list.len() as u32;
                 let obj = PyObject::new_string(name.to_string(), false);
                 self.name_list.push(obj);
                 self.name_map.insert(name.clone(), position);
@@ -82,44 +114,62 @@ impl ExecutionContext for GlobalContext {
     fn is_global(&self) -> bool {
         true
     }
+
+    fn set_variable_type(&mut self, symbol: &str, static_type: Option<StaticType>) {
+        insert_variable_type(&mut self.variable_types, symbol, static_type);
+    }
+
+    fn get_variable_type(&self, symbol: &str) -> Option<StaticType> {
+        self.variable_types.get(symbol).cloned()
+    }
 }
 
 impl<'ctx> ExecutionContext for PyContext<'ctx> {
-    fn push_const(&mut self, value: PyObject) {
-        self.constant_list.push(value);
+    fn push_const(&mut self, value: PyObject) -> u32 {
+        intern_constant(&mut self.constant_list, value)
     }
 
-    fn const_len(&self) -> usize {
-        self.constant_list.len()
+    fn declare_variable(&mut self, symbol: &String) -> u32 {
+        // ローカル変数の定義
+        let position = self.register_or_get_name(symbol);
+        if !self.local_variables.contains(symbol) {
+            self.local_variables.push(symbol.clone());
+        }
+        position
     }
 
-    fn declare_variable(&mut self, symbol: &String) -> u8 {
-        // ローカル変数の定義
-        let position = self.name_list.len() as u8;
-        let obj = PyObject::new_string(symbol.to_string(), false);
-        self.name_list.push(obj);
-        self.name_map.insert(symbol.to_string(), position);
+    fn allocate_local_variable(&mut self, symbol: &str) -> u32 {
+        // 同じ名前のローカル変数があれば、別名(x@1など)の領域を割り当てる
+        let mut name = symbol.to_string();
+        let mut count = 0;
+        while self.local_variables.contains(&name) {
+            count += 1;
+            name = format!("{}@{}", symbol, count);
+        }
+        self.local_variables.push(name);
+        (self.local_variables.len() - 1) as u32
+    }
 
-        self.local_variables.push(symbol.clone());
-        position
+    fn is_declared_in_block(&self, _symbol: &str) -> bool {
+        false
     }
 
-    fn get_local_variable(&self, symbol: &String) -> u8 {
+    fn get_local_variable(&self, symbol: &String) -> u32 {
         self.local_variables
             .iter()
             .position(|v| v == symbol)
-            .unwrap() as u8
+            .unwrap() as u32
     }
 
     fn check_variable_scope(&self, symbol: &String) -> VariableScope {
         self.outer.borrow().check_variable_scope(symbol)
     }
 
-    fn register_or_get_name(&mut self, name: &String) -> u8 {
+    fn register_or_get_name(&mut self, name: &String) -> u32 {
         match self.name_map.get(name) {
             Some(v) => *v,
             None => {
-                let position = self.name_list.len() as u8;
+                let position = self.name_list.len() as u32;
                 let obj = PyObject::new_string(name.to_string(), false);
                 self.name_list.push(obj);
                 self.name_map.insert(name.clone(), position);
@@ -131,60 +181,93 @@ impl<'ctx> ExecutionContext for PyContext<'ctx> {
     fn is_global(&self) -> bool {
         false
     }
+
+    fn set_variable_type(&mut self, _symbol: &str, _static_type: Option<StaticType>) {}
+
+    fn get_variable_type(&self, symbol: &str) -> Option<StaticType> {
+        if self.local_variables.iter().any(|v| v == symbol) {
+            return None;
+        }
+        self.outer.borrow().get_variable_type(symbol)
+    }
 }
 
 impl<'ctx> ExecutionContext for BlockContext<'ctx> {
-    fn push_const(&mut self, value: PyObject) {
-        self.outer.borrow_mut().push_const(value);
+    fn push_const(&mut self, value: PyObject) -> u32 {
+        self.outer.borrow_mut().push_const(value)
     }
 
-    fn const_len(&self) -> usize {
-        self.outer.borrow().const_len()
+    fn declare_variable(&mut self, symbol: &String) -> u32 {
+        // ブロック内ローカル変数の定義
+        // 外側のブロックの変数を隠す場合も、別のローカル変数として扱う
+        let position = self.outer.borrow_mut().allocate_local_variable(symbol);
+        self.variables.push((symbol.clone(), position));
+        position
     }
 
-    fn declare_variable(&mut self, symbol: &String) -> u8 {
-        // ブロック内ローカル変数の定義
-        self.variables.push(symbol.clone());
-        self.outer.borrow_mut().declare_variable(symbol)
+    fn allocate_local_variable(&mut self, symbol: &str) -> u32 {
+        self.outer.borrow_mut().allocate_local_variable(symbol)
     }
 
-    fn get_local_variable(&self, symbol: &String) -> u8 {
-        self.outer.borrow_mut().get_local_variable(symbol)
+    fn is_declared_in_block(&self, symbol: &str) -> bool {
+        self.variables.iter().any(|(v, _)| v == symbol)
+    }
+
+    fn get_local_variable(&self, symbol: &String) -> u32 {
+        match self.variables.iter().rev().find(|(v, _)| v == symbol) {
+            Some((_, position)) => *position,
+            None => self.outer.borrow().get_local_variable(symbol),
+        }
     }
 
     fn check_variable_scope(&self, symbol: &String) -> VariableScope {
-        if self.variables.contains(symbol) {
+        if self.is_declared_in_block(symbol) {
             VariableScope::Local
         } else {
             self.outer.borrow().check_variable_scope(symbol)
         }
     }
 
-    fn register_or_get_name(&mut self, name: &String) -> u8 {
+    fn register_or_get_name(&mut self, name: &String) -> u32 {
         self.outer.borrow_mut().register_or_get_name(name)
     }
 
     fn is_global(&self) -> bool {
         false
     }
-}
 
-impl<'ctx> ExecutionContext for ClassContext<'ctx> {
-    fn push_const(&mut self, value: PyObject) {
-        self.outer.borrow_mut().push_const(value);
+    fn set_variable_type(&mut self, symbol: &str, static_type: Option<StaticType>) {
+        insert_variable_type(&mut self.variable_types, symbol, static_type);
     }
 
-    fn const_len(&self) -> usize {
-        self.outer.borrow().const_len()
+    fn get_variable_type(&self, symbol: &str) -> Option<StaticType> {
+        if self.is_declared_in_block(symbol) {
+            return self.variable_types.get(symbol).cloned();
+        }
+        self.outer.borrow().get_variable_type(symbol)
+    }
+}
+
+impl<'ctx> ExecutionContext for ClassContext<'ctx> {
+    fn push_const(&mut self, value: PyObject) -> u32 {
+        self.outer.borrow_mut().push_const(value)
     }
 
-    fn declare_variable(&mut self, symbol: &String) -> u8 {
+    fn declare_variable(&mut self, symbol: &String) -> u32 {
         self.instance_variables.push(symbol.clone());
         self.outer.borrow_mut().declare_variable(symbol)
     }
 
-    fn get_local_variable(&self, symbol: &String) -> u8 {
-        self.outer.borrow_mut().get_local_variable(symbol)
+    fn allocate_local_variable(&mut self, symbol: &str) -> u32 {
+        self.outer.borrow_mut().allocate_local_variable(symbol)
+    }
+
+    fn is_declared_in_block(&self, _symbol: &str) -> bool {
+        false
+    }
+
+    fn get_local_variable(&self, symbol: &String) -> u32 {
+        self.outer.borrow().get_local_variable(symbol)
     }
 
     fn check_variable_scope(&self, symbol: &String) -> VariableScope {
@@ -195,13 +278,36 @@ impl<'ctx> ExecutionContext for ClassContext<'ctx> {
         }
     }
 
-    fn register_or_get_name(&mut self, name: &String) -> u8 {
+    fn register_or_get_name(&mut self, name: &String) -> u32 {
         self.outer.borrow_mut().register_or_get_name(name)
     }
 
     fn is_global(&self) -> bool {
         false
     }
+
+    fn set_variable_type(&mut self, symbol: &str, static_type: Option<StaticType>) {
+        insert_variable_type(&mut self.variable_types, symbol, static_type);
+    }
+
+    fn get_variable_type(&self, symbol: &str) -> Option<StaticType> {
+        if self.instance_variables.iter().any(|v| v == symbol) {
+            return self.variable_types.get(symbol).cloned();
+        }
+        self.outer.borrow().get_variable_type(symbol)
+    }
+}
+
+/// 定数を定数表に登録し、その位置を返す
+/// CPythonと同じく、型が違う値(1, 1.0, True)や0.0と-0.0は別の定数として扱う
+pub fn intern_constant(constant_list: &mut Vec<PyObject>, value: PyObject) -> u32 {
+    match constant_list.iter().position(|v| *v == value) {
+        Some(position) => position as u32,
+        None => {
+            constant_list.push(value);
+            (constant_list.len() - 1) as u32
+        }
+    }
 }
 
 pub enum VariableScope {
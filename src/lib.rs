@@ -1,99 +1,479 @@
+use std::cell::RefCell;
 use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::path::Path;
+use std::sync::OnceLock;
 use std::time::SystemTime;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use ciborium::de;
 use dart_parser_generator::parser_generator;
-use parser::node::LibraryDeclaration;
+use parser::node::{Identifier, LibraryDeclaration, PartOf};
 
 mod bytecode;
 mod bytecompiler;
+mod cache;
+mod disasm;
 mod executioncontext;
+mod library;
+mod modulegraph;
+mod optimizer;
 mod parser;
 mod pyobject;
+mod resolver;
+mod runtime;
+mod siphash;
 mod tokenizer;
 
+/// 数値演算の意味論
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumericSemantics {
+    /// Pythonの演算子をそのまま使う
+    #[default]
+    Python,
+    /// `/`, `~/`, `%`などをDartと同じ結果になるよう実行時ライブラリ経由で計算する
+    Dart,
+}
+
+/// バイトコードの最適化レベル
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// 最適化しない
+    O0,
+    /// ジャンプの融合と短絡、到達できないコードの削除
+    #[default]
+    O1,
+    /// -O1に加えて定数の畳み込み
+    O2,
+}
+
+/// .pycが古くなっていないかをPythonがどう確かめるか(PEP 552)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidationMode {
+    /// ソースの更新日時と大きさを書く
+    #[default]
+    Timestamp,
+    /// ソースのハッシュを書き、読み込むときに確かめさせる
+    CheckedHash,
+    /// ソースのハッシュを書くが、読み込むときに確かめさせない
+    UncheckedHash,
+}
+
+/// コンパイル時の設定
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    pub numeric_semantics: NumericSemantics,
+    pub opt_level: OptLevel,
+    pub invalidation_mode: InvalidationMode,
+}
+
+/// --emitで出力する中間表現
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// トークン列
+    Tokens,
+    /// 構文規則の名前付きの構文木
+    Cst,
+    /// LibraryDeclarationの構文木
+    Ast,
+    /// LibraryDeclarationの構文木のJSON
+    AstJson,
+    /// 分岐先を解決する前の命令列
+    Bytecode,
+}
+
 pub fn build_from_file(
     output: &str,
     source_file: &str,
     time_start_build: SystemTime,
     is_root: bool,
+    options: &BuildOptions,
 ) -> Result<()> {
     let source = fs::read_to_string(source_file).unwrap();
-    run(output, &source, time_start_build, is_root)
+    compile_file(
+        output,
+        source_file,
+        &source,
+        time_start_build,
+        is_root,
+        options,
+        transition_map(),
+    )
 }
 
-pub fn build_from_code(output: &str, code: &str, time_start_build: SystemTime) -> Result<()> {
-    run(output, &code, time_start_build, true)
+pub fn build_from_code(
+    output: &str,
+    code: &str,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+) -> Result<()> {
+    run(
+        output,
+        &code,
+        time_start_build,
+        time_start_build,
+        true,
+        options,
+        transition_map(),
+    )
 }
 
 pub fn build_from_code_single(output: &str, code: &str) -> Result<()> {
-    run(output, &code, SystemTime::now(), true)
+    let now = SystemTime::now();
+    run(
+        output,
+        &code,
+        now,
+        now,
+        true,
+        &BuildOptions::default(),
+        transition_map(),
+    )
 }
 
-fn run(output: &str, source: &str, time_start_build: SystemTime, is_root: bool) -> Result<()> {
-    // Tokenize
-    let token_list = tokenizer::tokenize(source)
-        .with_context(|| format!("failed to tokenize the passed source: {}", source))?;
+pub fn build_from_code_single_with_options(
+    output: &str,
+    code: &str,
+    options: &BuildOptions,
+) -> Result<()> {
+    let now = SystemTime::now();
+    run(output, code, now, now, true, options, transition_map())
+}
 
-    // Parse
-    let reader = std::fs::File::open(concat!(env!("OUT_DIR"), "/parser.bin")).unwrap();
-    let transition_map: parser_generator::TransitionMap = de::from_reader(reader).unwrap();
-    let node = parser::parse(token_list, transition_map)
-        .with_context(|| format!("failed to parse the passed source: {}", source))?;
-
-    {
-        let path = Path::new(output);
-        match fs::remove_file(path) {
-            Result::Ok(_) => (),  // println!("file removed"),
-            Result::Err(_) => (), // println!("file does not exists"),
+/// .pycファイルの内容を逆アセンブルする
+pub fn disassemble_pyc(pyc: &[u8]) -> Result<String> {
+    ensure!(pyc.len() >= 16, "too short pyc file");
+    ensure!(
+        pyc[..4] == PYC_MAGIC_NUMBER,
+        "unsupported magic number: {:02x?}",
+        &pyc[..4]
+    );
+    let code = pyobject::PyObject::read(&pyc[16..])?;
+    disasm::disassemble(&code)
+}
+
+/// ソースコードの中間表現を文字列にする
+pub fn emit_from_code(code: &str, emit: Emit, options: &BuildOptions) -> Result<String> {
+    let transition_map = transition_map();
+    match emit {
+        Emit::Tokens => {
+            let token_list = tokenizer::tokenize(code)
+                .with_context(|| format!("failed to tokenize the passed source: {}", code))?;
+            Ok(token_list
+                .iter()
+                .map(|token| format!("{:?} {:?}\n", token.kind, token.str))
+                .collect())
+        }
+        Emit::Cst => {
+            let token_list = tokenizer::tokenize(code)
+                .with_context(|| format!("failed to tokenize the passed source: {}", code))?;
+            parser::format_parse_tree(token_list, transition_map)
+                .with_context(|| format!("failed to parse the passed source: {}", code))
+        }
+        Emit::Ast | Emit::AstJson | Emit::Bytecode => {
+            let mut node = parse_source(code, transition_map)?;
+            let part_sources = read_parts(&node)?;
+            merge_parts(&mut node, &part_sources, transition_map)?;
+            match format_ast(&node, emit)? {
+                Some(v) => Ok(v),
+                None => {
+                    let listing = RefCell::new(String::new());
+                    bytecompiler::runroot::run_root(
+                        &"main.pyc".to_string(),
+                        &node,
+                        code,
+                        SystemTime::now(),
+                        true,
+                        options,
+                        Some(&listing),
+                    )?;
+                    Ok(listing.into_inner())
+                }
+            }
         }
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(&path)
-            .unwrap();
-        let filename = path
-            .with_extension("pyc")
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-
-        write_header(&mut file);
-        write_root_py_code(&mut file, filename, source, node, time_start_build, is_root)?;
     }
+}
+
+/// ファイルをビルドし、--emitで指定された中間表現をlistingに書く
+/// トークン列や構文木はコンパイルより前に書くので、コンパイルに失敗しても確かめられる
+/// 命令列はビルドと同じコンパイルで作り、失敗した場合もそれまでのコードオブジェクトの分を残す
+pub fn build_from_file_with_emit(
+    output: &str,
+    source_file: &str,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+    emit: Emit,
+    listing: &RefCell<String>,
+) -> Result<()> {
+    let source = fs::read_to_string(source_file)
+        .with_context(|| format!("failed to read {}", source_file))?;
+    let source_mtime = source_mtime(source_file)?;
+    let transition_map = transition_map();
+    if matches!(emit, Emit::Tokens | Emit::Cst) {
+        listing
+            .borrow_mut()
+            .push_str(&emit_from_code(&source, emit, options)?);
+    }
+
+    let mut node = parse_source(&source, transition_map)?;
+    let part_sources = read_parts(&node)?;
+    merge_parts(&mut node, &part_sources, transition_map)?;
+    if let Some(v) = format_ast(&node, emit)? {
+        listing.borrow_mut().push_str(&v);
+    }
+
+    prepare_root(output, &source, time_start_build, options, transition_map)?;
+    let mut file = create_pyc(output, &source, source_mtime, options)?;
+    let code = bytecompiler::runroot::run_root(
+        &code_file_name(output),
+        &node,
+        &source,
+        time_start_build,
+        true,
+        options,
+        (emit == Emit::Bytecode).then_some(listing),
+    )?;
+    code.write(&mut file)?;
     Ok(())
 }
 
-fn write_header(file: &mut File) {
-    file.write(&[0x61, 0x0D, 0x0D, 0x0A]).unwrap(); // Magic Number
-    file.write(&[0x00, 0x00, 0x00, 0x00]).unwrap(); // Flag(PEP552)
+// 構文木の中間表現。構文木以外の指定ならNone
+fn format_ast(node: &LibraryDeclaration, emit: Emit) -> Result<Option<String>> {
+    match emit {
+        Emit::Ast => Ok(Some(format!("{:#?}\n", node))),
+        Emit::AstJson => Ok(Some(serde_json::to_string_pretty(node)? + "\n")),
+        _ => Ok(None),
+    }
+}
+
+fn source_mtime(source_file: &str) -> Result<SystemTime> {
+    fs::metadata(source_file)
+        .and_then(|v| v.modified())
+        .with_context(|| format!("failed to read the modification time of {}", source_file))
+}
 
-    let now = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as u32;
-    file.write(&(now.to_le_bytes())).unwrap(); // Timestamp
-    let file_size: u32 = 0;
-    file.write(&(file_size.to_le_bytes())).unwrap();
+// ファイルのソースコードをコンパイルする
+fn compile_file(
+    output: &str,
+    source_file: &str,
+    source: &str,
+    time_start_build: SystemTime,
+    is_root: bool,
+    options: &BuildOptions,
+    transition_map: &parser_generator::TransitionMap,
+) -> Result<()> {
+    run(
+        output,
+        source,
+        source_mtime(source_file)?,
+        time_start_build,
+        is_root,
+        options,
+        transition_map,
+    )
 }
 
-fn write_root_py_code(
-    file: &mut File,
-    file_name: String,
+fn run(
+    output: &str,
     source: &str,
-    node: LibraryDeclaration,
+    source_mtime: SystemTime,
     time_start_build: SystemTime,
     is_root: bool,
+    options: &BuildOptions,
+    transition_map: &parser_generator::TransitionMap,
+) -> Result<()> {
+    let mut node = parse_source(source, transition_map)?;
+    let part_sources = read_parts(&node)?;
+    merge_parts(&mut node, &part_sources, transition_map)?;
+
+    if is_root {
+        prepare_root(output, source, time_start_build, options, transition_map)?;
+    }
+    let mut file = create_pyc(output, source, source_mtime, options)?;
+    let code = bytecompiler::runroot::run_root(
+        &code_file_name(output),
+        &node,
+        source,
+        time_start_build,
+        is_root,
+        options,
+        None,
+    )?;
+    code.write(&mut file)?;
+    Ok(())
+}
+
+// ルートのファイルをコンパイルする前に、実行時ライブラリを置き、インポートしているモジュールをビルドする
+fn prepare_root(
+    output: &str,
+    source: &str,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+    transition_map: &parser_generator::TransitionMap,
+) -> Result<()> {
+    let directory = Path::new(output)
+        .parent()
+        .filter(|v| !v.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    runtime::write_runtime_modules(directory)?;
+    // インポートしているモジュールを先に並列にビルドしておく
+    modulegraph::build_imported_modules(source, time_start_build, options, transition_map)
+}
+
+// 出力先を作り直し、ヘッダーを書いておく
+fn create_pyc(
+    output: &str,
+    source: &str,
+    source_mtime: SystemTime,
+    options: &BuildOptions,
+) -> Result<File> {
+    let path = Path::new(output);
+    match fs::remove_file(path) {
+        Result::Ok(_) => (),  // println!("file removed"),
+        Result::Err(_) => (), // println!("file does not exists"),
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    write_header(&mut file, source, source_mtime, options)?;
+    Ok(file)
+}
+
+// コードオブジェクトのco_filename
+fn code_file_name(output: &str) -> String {
+    Path::new(output)
+        .with_extension("pyc")
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+// partのソースコードを読む
+fn read_parts(node: &LibraryDeclaration) -> Result<Vec<String>> {
+    ensure!(
+        node.part_of.is_none(),
+        "a part file cannot be compiled by itself"
+    );
+    node.part_list
+        .iter()
+        .map(|uri| {
+            ensure!(!uri.contains(":"), "invalid part uri: {}", uri);
+            fs::read_to_string(uri).with_context(|| format!("failed to read part: {}", uri))
+        })
+        .collect()
+}
+
+// partの宣言を取り込む
+fn merge_parts<'a>(
+    node: &mut LibraryDeclaration<'a>,
+    part_sources: &'a [String],
+    transition_map: &parser_generator::TransitionMap,
+) -> Result<()> {
+    for (uri, part_source) in node.part_list.clone().iter().zip(part_sources) {
+        let part = parse_source(part_source, transition_map)?;
+        match &part.part_of {
+            Some(PartOf::Uri(owner)) => {
+                ensure!(owner.ends_with(".dart"), "invalid part-of uri: {}", owner)
+            }
+            Some(PartOf::Name(name)) => {
+                // part ofの名前はlibraryディレクティブの名前と一致する必要がある
+                let library_name = node.library_name.as_ref().map(|v| dotted_name(v));
+                ensure!(
+                    library_name.as_deref() == Some(&dotted_name(name)[..]),
+                    "{} is not a part of this library",
+                    uri
+                );
+            }
+            None => bail!("{} is not a part file", uri),
+        }
+        ensure!(
+            part.import_list.is_empty() && part.export_list.is_empty(),
+            "a part file cannot have imports or exports: {}",
+            uri
+        );
+        ensure!(
+            part.part_list.is_empty(),
+            "a part file cannot have parts: {}",
+            uri
+        );
+        node.top_level_declaration_list
+            .extend(part.top_level_declaration_list);
+    }
+    Ok(())
+}
+
+fn parse_source<'a>(
+    source: &'a str,
+    transition_map: &parser_generator::TransitionMap,
+) -> Result<LibraryDeclaration<'a>> {
+    // Tokenize
+    let token_list = tokenizer::tokenize(source)
+        .with_context(|| format!("failed to tokenize the passed source: {}", source))?;
+
+    // Parse
+    parser::parse(token_list, transition_map)
+        .with_context(|| format!("failed to parse the passed source: {}", source))
+}
+
+// build.rsで生成した構文解析表
+// 実行ファイルに埋め込むので、target/が無い環境でも動く
+static PARSER_TABLE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/parser.bin"));
+
+// 構文解析表は一度だけ読み込み、すべてのコンパイルで共有する
+fn transition_map() -> &'static parser_generator::TransitionMap {
+    static TRANSITION_MAP: OnceLock<parser_generator::TransitionMap> = OnceLock::new();
+    TRANSITION_MAP.get_or_init(|| de::from_reader(PARSER_TABLE).expect("broken parser table"))
+}
+
+fn dotted_name(identifier_list: &[Identifier]) -> String {
+    identifier_list
+        .iter()
+        .map(|v| v.value)
+        .collect::<Vec<&str>>()
+        .join(".")
+}
+
+// Python 3.9のマジックナンバー
+const PYC_MAGIC_NUMBER: [u8; 4] = [0x61, 0x0D, 0x0D, 0x0A];
+
+// https://peps.python.org/pep-0552/
+fn write_header(
+    file: &mut File,
+    source: &str,
+    source_mtime: SystemTime,
+    options: &BuildOptions,
 ) -> Result<()> {
-    let code =
-        bytecompiler::runroot::run_root(&file_name, &node, source, time_start_build, is_root)?;
+    file.write_all(&PYC_MAGIC_NUMBER)?; // Magic Number
 
-    code.write(file)?;
+    match options.invalidation_mode {
+        InvalidationMode::Timestamp => {
+            // Pythonと同じく下位32bitだけを書く
+            let mtime = source_mtime
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|v| v.as_secs())
+                .unwrap_or(0) as u32;
+            file.write_all(&0u32.to_le_bytes())?; // Flag
+            file.write_all(&mtime.to_le_bytes())?; // Timestamp
+            file.write_all(&(source.len() as u32).to_le_bytes())?; // Size
+        }
+        InvalidationMode::CheckedHash | InvalidationMode::UncheckedHash => {
+            let check_source = options.invalidation_mode == InvalidationMode::CheckedHash;
+            let flags: u32 = 0b01 | ((check_source as u32) << 1);
+            file.write_all(&flags.to_le_bytes())?; // Flag
+            file.write_all(&source_hash(source.as_bytes()))?; // Hash
+        }
+    }
     Ok(())
 }
+
+/// importlib.util.source_hashと同じハッシュ
+/// マジックナンバーを鍵にする
+pub fn source_hash(source: &[u8]) -> [u8; 8] {
+    let key = u32::from_le_bytes(PYC_MAGIC_NUMBER) as u64;
+    siphash::siphash(key, 0, source).to_le_bytes()
+}
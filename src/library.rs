@@ -0,0 +1,231 @@
+use anyhow::Result;
+
+use crate::tokenizer::{self, Token, TokenKind};
+
+// ライブラリのトップレベルで宣言されている名前を集める
+// 構文解析は行わず、トークン列だけを見て推定する
+// .d.dartのスタブにも使うため、関数本体を持たない宣言にも対応する
+pub fn scan_top_level_names(source: &str) -> Result<Vec<String>> {
+    let tokens = tokenizer::tokenize(source)?;
+    let mut names: Vec<String> = vec![];
+
+    // 括弧の深さ
+    let mut depth = 0;
+    // 型引数の<>の深さ
+    let mut type_depth = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let next = tokens.get(i + 1);
+        match token.str {
+            "{" | "(" | "[" => depth += 1,
+            "}" | ")" | "]" => depth -= 1,
+            _ if depth > 0 => (),
+            "import" | "export" | "part" | "library" if is_statement_start(&tokens, i) => {
+                // ディレクティブは読み飛ばす
+                i = skip_until_semicolon(&tokens, i);
+            }
+            "@" => {
+                // メタデータの名前と引数は読み飛ばす
+                i = skip_metadata(&tokens, i + 1);
+                continue;
+            }
+            "=" | "=>" => {
+                // 初期化式や式本体は読み飛ばす
+                i = skip_expression(&tokens, i + 1);
+                continue;
+            }
+            "<" => type_depth += 1,
+            ">" => type_depth -= 1,
+            ">>" => type_depth -= 2,
+            "class" | "enum" | "mixin" | "typedef" | "extension" => {
+                if let Some(next) = next {
+                    if is_name(next) {
+                        push_name(&mut names, next.str);
+                        i += 1;
+                    }
+                }
+            }
+            _ if is_name(token) && type_depth == 0 => {
+                if let Some(next) = next {
+                    if matches!(next.str, "(" | ";" | "=" | "," | "=>") {
+                        push_name(&mut names, token.str);
+                    }
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    Ok(names)
+}
+
+// ライブラリをインポートしたときに使えるようになる名前を集める
+// export, partで他のファイルの名前も公開している場合は分からないのでNoneを返す
+pub fn scan_library_names(source: &str) -> Option<Vec<String>> {
+    let tokens = tokenizer::tokenize(source).ok()?;
+    let has_other_files = tokens
+        .iter()
+        .enumerate()
+        .any(|(i, v)| matches!(v.str, "export" | "part") && is_statement_start(&tokens, i));
+    if has_other_files {
+        return None;
+    }
+    scan_top_level_names(source).ok()
+}
+
+// import, exportしているファイルのuriを集める
+// 条件付きインポートのすべての候補を含む
+pub fn scan_imported_uris(source: &str) -> Result<Vec<String>> {
+    scan_directive_uris(source, &["import", "export"])
+}
+
+// partで取り込むファイルのuriを集める
+pub fn scan_part_uris(source: &str) -> Result<Vec<String>> {
+    scan_directive_uris(source, &["part"])
+}
+
+fn scan_directive_uris(source: &str, directives: &[&str]) -> Result<Vec<String>> {
+    let tokens = tokenizer::tokenize(source)?;
+    let mut uris: Vec<String> = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        let is_part_of = tokens.get(i + 1).is_some_and(|v| v.str == "of");
+        if directives.contains(&tokens[i].str) && !is_part_of && is_statement_start(&tokens, i) {
+            let end = skip_until_semicolon(&tokens, i);
+            for token in &tokens[i + 1..end] {
+                if token.kind == TokenKind::StringBeginEnd {
+                    push_name(&mut uris, token.str);
+                }
+            }
+            i = end;
+        }
+        i += 1;
+    }
+    Ok(uris)
+}
+
+fn is_name(token: &Token) -> bool {
+    matches!(
+        token.kind,
+        TokenKind::Identifier | TokenKind::OtherIdentifier
+    )
+}
+
+fn push_name(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|v| v == name) {
+        names.push(name.to_string());
+    }
+}
+
+fn is_statement_start(tokens: &[Token], index: usize) -> bool {
+    index == 0 || matches!(tokens[index - 1].str, ";" | "}")
+}
+
+fn skip_until_semicolon(tokens: &[Token], start: usize) -> usize {
+    let mut i = start;
+    while i < tokens.len() && tokens[i].str != ";" {
+        i += 1;
+    }
+    i
+}
+
+// @の後の修飾された名前と引数リストの次の位置を返す
+fn skip_metadata(tokens: &[Token], start: usize) -> usize {
+    let mut i = start;
+    while i < tokens.len() && (is_name(&tokens[i]) || tokens[i].str == ".") {
+        i += 1;
+    }
+    if tokens.get(i).is_none_or(|v| v.str != "(") {
+        return i;
+    }
+    let mut depth = 0;
+    while i < tokens.len() {
+        match tokens[i].str {
+            "{" | "(" | "[" => depth += 1,
+            "}" | ")" | "]" => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    i
+}
+
+// 式の終わり(深さ0の;か,)の位置を返す
+fn skip_expression(tokens: &[Token], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < tokens.len() {
+        match tokens[i].str {
+            "{" | "(" | "[" => depth += 1,
+            "}" | ")" | "]" => depth -= 1,
+            ";" | "," if depth == 0 => return i,
+            _ => (),
+        }
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::library::{scan_imported_uris, scan_part_uris, scan_top_level_names};
+
+    #[test]
+    fn top_level_names() {
+        let source = r#"
+            import 'elaphe/core.d.dart' show print;
+
+            /// doc comment
+            external double sqrt(double x);
+            external Function floor;
+            Map<String, List<int>> table = {"a": [1, 2]};
+            var a = f(1, 2), b;
+            int get answer => 42;
+
+            class Hoge {
+                int field = 1;
+                void method() {}
+            }
+
+            void main() {
+                var local = 1;
+            }
+
+            @app.route('/x')
+            index() {}
+
+            @deprecated
+            var old;
+        "#;
+        assert_eq!(
+            scan_top_level_names(source).unwrap(),
+            vec!["sqrt", "floor", "table", "a", "b", "answer", "Hoge", "main", "index", "old"]
+        );
+    }
+
+    #[test]
+    fn imported_uris() {
+        let source = r#"
+            library app;
+
+            import 'dart:math';
+            import 'a.dart' if (dart.library.io) "b.dart" show f;
+            export '../c.dart';
+            part 'd.dart';
+
+            var s = "import 'd.dart';";
+        "#;
+        assert_eq!(
+            scan_imported_uris(source).unwrap(),
+            vec!["dart:math", "a.dart", "b.dart", "../c.dart"]
+        );
+        assert_eq!(scan_part_uris(source).unwrap(), vec!["d.dart"]);
+        assert!(scan_part_uris("part of app;").unwrap().is_empty());
+    }
+}
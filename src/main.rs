@@ -1,6 +1,10 @@
-use anyhow::{anyhow, ensure, Context, Result};
-use elaphe::{build_from_code, build_from_file};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use elaphe::{
+    build_from_code, build_from_file, build_from_file_with_emit, disassemble_pyc, BuildOptions,
+    Emit, InvalidationMode, NumericSemantics, OptLevel,
+};
 use getopts::Options;
+use std::cell::RefCell;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
@@ -16,9 +20,11 @@ fn main() -> Result<()> {
     if command == "run" {
         let mut opts = Options::new();
         opts.optopt("c", "", "eval string", "CODE");
+        add_build_options(&mut opts);
         let matches = opts
             .parse(&args[2..])
             .with_context(|| "failed to parse arguments")?;
+        let options = parse_build_options(&matches)?;
 
         if !matches.free.is_empty() {
             // ファイル名で実行
@@ -26,7 +32,7 @@ fn main() -> Result<()> {
             let output = Path::new(&file_name).with_extension("pyc");
             let output = output.to_str().unwrap();
 
-            build_from_file(output, &file_name, now, true)?;
+            build_from_file(output, &file_name, now, true, &options)?;
             execute_pyc(output)
         } else {
             // 文字列を実行
@@ -35,17 +41,60 @@ fn main() -> Result<()> {
             let output = "main.pyc";
             match source {
                 Some(source) => {
-                    build_from_code(output, &source, now)?;
+                    build_from_code(output, &source, now, &options)?;
                     execute_pyc(output)
                 }
                 None => Err(anyhow!("invalid arguments")),
             }
         }
     } else if command == "build" {
-        let file_name = args[2].clone();
+        let mut opts = Options::new();
+        add_build_options(&mut opts);
+        opts.optopt(
+            "",
+            "emit",
+            "print an intermediate representation (tokens, cst, ast, ast-json or bytecode)",
+            "KIND",
+        );
+        let matches = opts
+            .parse(&args[2..])
+            .with_context(|| "failed to parse arguments")?;
+        let options = parse_build_options(&matches)?;
+        ensure!(
+            !matches.free.is_empty(),
+            "invalid arguments. please input file name."
+        );
+
+        let emit = match matches.opt_str("emit").as_deref() {
+            Some("tokens") => Some(Emit::Tokens),
+            Some("cst") => Some(Emit::Cst),
+            Some("ast") => Some(Emit::Ast),
+            Some("ast-json") => Some(Emit::AstJson),
+            Some("bytecode") => Some(Emit::Bytecode),
+            Some(kind) => bail!("invalid emit kind: {}", kind),
+            None => None,
+        };
+
+        let file_name = matches.free[0].clone();
         let output = Path::new(&file_name).with_extension("pyc");
         let output = output.to_str().unwrap();
-        build_from_file(output, &file_name, now, true)
+        match emit {
+            Some(emit) => {
+                // ビルドに失敗しても、それまでに作れた中間表現は出力する
+                let listing = RefCell::new(String::new());
+                let result =
+                    build_from_file_with_emit(output, &file_name, now, &options, emit, &listing);
+                print!("{}", listing.into_inner());
+                result
+            }
+            None => build_from_file(output, &file_name, now, true, &options),
+        }
+    } else if command == "disasm" {
+        ensure!(
+            args.len() >= 3,
+            "invalid arguments. please input pyc file name."
+        );
+        print_disassembly(&args[2])
     } else if command == "init" {
         let dir = &args[2];
         elaphe_init(dir)?;
@@ -59,6 +108,55 @@ fn main() -> Result<()> {
     }
 }
 
+// run, buildに共通するコンパイルオプション
+fn add_build_options(opts: &mut Options) {
+    opts.optopt(
+        "",
+        "numeric",
+        "semantics of numeric operators (python or dart)",
+        "MODE",
+    );
+    opts.optopt("O", "", "optimization level (0, 1 or 2)", "LEVEL");
+    opts.optopt(
+        "",
+        "invalidation-mode",
+        "how python checks the pyc is up to date (timestamp, checked-hash or unchecked-hash)",
+        "MODE",
+    );
+}
+
+fn parse_build_options(matches: &getopts::Matches) -> Result<BuildOptions> {
+    let mut options = BuildOptions::default();
+    if let Some(mode) = matches.opt_str("numeric") {
+        options.numeric_semantics = match mode.as_str() {
+            "python" => NumericSemantics::Python,
+            "dart" => NumericSemantics::Dart,
+            _ => bail!("invalid numeric mode: {}", mode),
+        };
+    }
+    if let Some(level) = matches.opt_str("O") {
+        options.opt_level = match level.as_str() {
+            "0" => OptLevel::O0,
+            "1" => OptLevel::O1,
+            "2" => OptLevel::O2,
+            _ => bail!("invalid optimization level: {}", level),
+        };
+    }
+    // py_compileと同じく、SOURCE_DATE_EPOCHがあれば再現できるようハッシュを使う
+    if env::var_os("SOURCE_DATE_EPOCH").is_some() {
+        options.invalidation_mode = InvalidationMode::CheckedHash;
+    }
+    if let Some(mode) = matches.opt_str("invalidation-mode") {
+        options.invalidation_mode = match mode.as_str() {
+            "timestamp" => InvalidationMode::Timestamp,
+            "checked-hash" => InvalidationMode::CheckedHash,
+            "unchecked-hash" => InvalidationMode::UncheckedHash,
+            _ => bail!("invalid invalidation mode: {}", mode),
+        };
+    }
+    Ok(options)
+}
+
 // fn compile_only(output: &str, source: &str) -> Result<(), Box<dyn Error>> {
 //     match elaphe::run(output, &source) {
 //         Ok(_) => println!("{} is generated!", output),
@@ -69,6 +167,14 @@ fn main() -> Result<()> {
 //     Ok(())
 // }
 
+fn print_disassembly(file_name: &str) -> Result<()> {
+    let pyc = fs::read(file_name).with_context(|| format!("failed to read {}", file_name))?;
+    let result =
+        disassemble_pyc(&pyc).with_context(|| format!("failed to disassemble {}", file_name))?;
+    print!("{}", result);
+    Ok(())
+}
+
 fn execute_pyc(file_name: &str) -> Result<()> {
     let output = Command::new("python")
         .args(&[file_name])
@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use dart_parser_generator::parser_generator::TransitionMap;
+
+use crate::cache;
+use crate::library::scan_imported_uris;
+use crate::BuildOptions;
+
+// インポートしているDartのモジュール
+struct Module {
+    uri: String,
+    output: PathBuf,
+    source: String,
+}
+
+/// ルートから辿れるDartのモジュールのうち、古いものをスレッドプールでビルドする
+/// モジュールのコンパイルはインポート先のソースしか読まないので、どの順にもビルドできる
+pub fn build_imported_modules(
+    root_source: &str,
+    time_start_build: SystemTime,
+    options: &BuildOptions,
+    transition_map: &TransitionMap,
+) -> Result<()> {
+    let stale: Vec<Module> = discover_modules(root_source)?
+        .into_iter()
+        .filter(|v| {
+            !cache::is_built_in_this_session(&v.output, time_start_build)
+                && cache::is_stale(&v.uri, &v.source, &v.output, options)
+        })
+        .collect();
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    // 他のモジュールのコンパイル中に、インポート先として重ねてビルドされないようにする
+    for module in &stale {
+        cache::claim(&module.uri, &module.output)?;
+    }
+
+    let next = AtomicUsize::new(0);
+    let errors = Mutex::new(vec![]);
+    let thread_count = thread::available_parallelism()
+        .map(|v| v.get())
+        .unwrap_or(1)
+        .min(stale.len());
+    thread::scope(|scope| {
+        for _ in 0..thread_count {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(module) = stale.get(index) else {
+                    break;
+                };
+                let result = cache::build_claimed(
+                    &module.uri,
+                    &module.output,
+                    &module.source,
+                    time_start_build,
+                    options,
+                    transition_map,
+                );
+                if let Err(error) = result {
+                    errors.lock().unwrap().push((index, error));
+                }
+            });
+        }
+    });
+
+    // 実行順によらず、最初に見つけたモジュールのエラーを返す
+    let mut errors = errors.into_inner().unwrap();
+    errors.sort_by_key(|(index, _)| *index);
+    match errors.into_iter().next() {
+        Some((_, error)) => Err(error),
+        None => Ok(()),
+    }
+}
+
+// インポート文だけを読んでインポートの依存関係を辿る
+fn discover_modules(root_source: &str) -> Result<Vec<Module>> {
+    let mut modules: Vec<Module> = vec![];
+    let mut queue = scan_imported_uris(root_source)?;
+    queue.reverse();
+    while let Some(uri) = queue.pop() {
+        let Some(output) = cache::module_output(&uri) else {
+            continue;
+        };
+        if modules.iter().any(|v| v.uri == uri) {
+            continue;
+        }
+        let source = fs::read_to_string(&uri).with_context(|| format!("failed to read {}", uri))?;
+        let mut imported = scan_imported_uris(&source)?;
+        imported.reverse();
+        queue.extend(imported);
+        modules.push(Module {
+            uri,
+            output,
+            source,
+        });
+    }
+    Ok(modules)
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, ensure, Result};
+
+use crate::bytecode::{is_relative_jump, is_terminator, jump_target, OpCode};
+use crate::executioncontext::intern_constant;
+use crate::pyobject::PyObject;
+use crate::OptLevel;
+
+// 分岐先をラベルではなく命令の位置で持つ命令
+struct Instruction {
+    op: OpCode,
+    target: Option<usize>,
+}
+
+/// 生成したバイトコードを最適化する
+/// -O1: ジャンプの融合と短絡、到達できないコードの削除
+/// -O2: -O1に加えて定数の畳み込み
+/// 最適化したバイトコードと、それに合わせて作り直したラベルの表を返す
+pub fn optimize(
+    operation_list: &[OpCode],
+    label_table: &HashMap<u32, u32>,
+    constant_list: &mut Vec<PyObject>,
+    opt_level: OptLevel,
+) -> Result<(Vec<OpCode>, HashMap<u32, u32>)> {
+    let mut instructions = decode(operation_list, label_table)?;
+    if opt_level >= OptLevel::O2 {
+        instructions = fold_constants(instructions, constant_list);
+    }
+    if opt_level >= OptLevel::O1 {
+        instructions = fuse_jumps(instructions);
+        thread_jumps(&mut instructions);
+        instructions = remove_unreachable(instructions);
+        instructions = remove_jumps_to_next(instructions);
+    }
+    encode(&instructions)
+}
+
+fn decode(operation_list: &[OpCode], label_table: &HashMap<u32, u32>) -> Result<Vec<Instruction>> {
+    let mut instructions = vec![];
+    for (i, op) in operation_list.iter().enumerate() {
+        instructions.push(Instruction {
+            op: *op,
+            target: jump_target(operation_list, i, label_table)?,
+        });
+    }
+    Ok(instructions)
+}
+
+// 分岐する命令ごとにラベルを作り直す
+fn encode(instructions: &[Instruction]) -> Result<(Vec<OpCode>, HashMap<u32, u32>)> {
+    let mut operation_list = vec![];
+    let mut label_table = HashMap::new();
+    for (i, instruction) in instructions.iter().enumerate() {
+        let Some(target) = instruction.target else {
+            operation_list.push(instruction.op);
+            continue;
+        };
+        ensure!(
+            !is_relative_jump(&instruction.op) || target > i,
+            "relative jump must go forward"
+        );
+        let label = i as u32;
+        label_table.insert(label, target as u32);
+        operation_list.push(with_label(instruction.op, label)?);
+    }
+    Ok((operation_list, label_table))
+}
+
+fn with_label(op: OpCode, label: u32) -> Result<OpCode> {
+    Ok(match op {
+        OpCode::JumpIfFalseOrPop(_) => OpCode::JumpIfFalseOrPop(label),
+        OpCode::JumpIfTrueOrPop(_) => OpCode::JumpIfTrueOrPop(label),
+        OpCode::JumpAbsolute(_) => OpCode::JumpAbsolute(label),
+        OpCode::PopJumpIfFalse(_) => OpCode::PopJumpIfFalse(label),
+        OpCode::PopJumpIfTrue(_) => OpCode::PopJumpIfTrue(label),
+        OpCode::JumpIfNotExcMatch(_) => OpCode::JumpIfNotExcMatch(label),
+        OpCode::SetupFinally(_) => OpCode::SetupFinally(label),
+        OpCode::ForIter(_) => OpCode::ForIter(label),
+        _ => bail!("{:?} is not a jump", op),
+    })
+}
+
+// 分岐先になっている命令
+fn jump_targets(instructions: &[Instruction]) -> Vec<bool> {
+    let mut is_target = vec![false; instructions.len()];
+    for instruction in instructions {
+        if let Some(target) = instruction.target {
+            is_target[target] = true;
+        }
+    }
+    is_target
+}
+
+// keepがfalseの命令を取り除き、分岐先を詰めた後の位置に付け替える
+// 取り除いた命令への分岐は、その次に残る命令への分岐になる
+fn compact(instructions: Vec<Instruction>, keep: &[bool]) -> Vec<Instruction> {
+    let mut new_index = vec![0; instructions.len() + 1];
+    let mut count = 0;
+    for i in 0..instructions.len() {
+        new_index[i] = count;
+        if keep[i] {
+            count += 1;
+        }
+    }
+    new_index[instructions.len()] = count;
+
+    instructions
+        .into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| **keep)
+        .map(|(instruction, _)| Instruction {
+            op: instruction.op,
+            target: instruction.target.map(|v| new_index[v]),
+        })
+        .collect()
+}
+
+// LOAD_CONST a; LOAD_CONST b; BINARY_ADD → LOAD_CONST (a + b)
+// 途中の命令が分岐先であれば畳み込まない
+fn fold_constants(
+    mut instructions: Vec<Instruction>,
+    constant_list: &mut Vec<PyObject>,
+) -> Vec<Instruction> {
+    let is_target = jump_targets(&instructions);
+    let mut keep = vec![true; instructions.len()];
+    // 残っている命令の位置を積んでおき、畳み込んだ結果をさらに畳み込めるようにする
+    let mut kept: Vec<usize> = vec![];
+    for i in 0..instructions.len() {
+        kept.push(i);
+        if is_target[i] {
+            continue;
+        }
+        let folded = match (instructions[i].op, &kept[..]) {
+            (OpCode::UnaryNegative, [.., a, _]) => match instructions[*a].op {
+                OpCode::LoadConst(v) => {
+                    fold_unary(&constant_list[v as usize]).map(|value| (vec![*a], value))
+                }
+                _ => None,
+            },
+            (op, [.., a, b, _]) if !is_target[*b] => {
+                match (instructions[*a].op, instructions[*b].op) {
+                    (OpCode::LoadConst(x), OpCode::LoadConst(y)) => {
+                        fold_binary(op, &constant_list[x as usize], &constant_list[y as usize])
+                            .map(|value| (vec![*a, *b], value))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let Some((operands, value)) = folded else {
+            continue;
+        };
+
+        instructions[i].op = OpCode::LoadConst(intern_constant(constant_list, value));
+        for operand in operands {
+            keep[operand] = false;
+            kept.retain(|v| *v != operand);
+        }
+    }
+    compact(instructions, &keep)
+}
+
+enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+fn as_number(value: &PyObject) -> Option<Number> {
+    match value {
+        PyObject::Int(v, _) => Some(Number::Int(*v as i64)),
+        PyObject::Float(v, _) => Some(Number::Float(*v)),
+        _ => None,
+    }
+}
+
+fn as_str(value: &PyObject) -> Option<&str> {
+    match value {
+        PyObject::Ascii(v, _) | PyObject::AsciiShort(v, _) | PyObject::Unicode(v, _) => Some(v),
+        _ => None,
+    }
+}
+
+fn from_number(value: Number) -> Option<PyObject> {
+    match value {
+        Number::Int(v) => PyObject::new_numeric(&v.to_string(), false).ok(),
+        Number::Float(v) => Some(PyObject::Float(v, false)),
+    }
+}
+
+fn fold_unary(value: &PyObject) -> Option<PyObject> {
+    match as_number(value)? {
+        Number::Int(v) => from_number(Number::Int(-v)),
+        Number::Float(v) => from_number(Number::Float(-v)),
+    }
+}
+
+// Pythonで実行した場合と同じ結果になる演算だけを畳み込む
+fn fold_binary(op: OpCode, left: &PyObject, right: &PyObject) -> Option<PyObject> {
+    if let (OpCode::BinaryAdd, Some(a), Some(b)) = (op, as_str(left), as_str(right)) {
+        return Some(PyObject::new_string(format!("{}{}", a, b), false));
+    }
+    let value = match (as_number(left)?, as_number(right)?) {
+        (Number::Int(a), Number::Int(b)) => Number::Int(match op {
+            OpCode::BinaryAdd => a.checked_add(b)?,
+            OpCode::BinarySubtract => a.checked_sub(b)?,
+            OpCode::BinaryMultiply => a.checked_mul(b)?,
+            OpCode::BinaryAnd => a & b,
+            OpCode::BinaryOr => a | b,
+            OpCode::BinaryXor => a ^ b,
+            _ => return None,
+        }),
+        (a, b) => {
+            // i32に収まる整数はf64で正確に表せる
+            let to_float = |v| match v {
+                Number::Int(v) => v as f64,
+                Number::Float(v) => v,
+            };
+            let (a, b) = (to_float(a), to_float(b));
+            Number::Float(match op {
+                OpCode::BinaryAdd => a + b,
+                OpCode::BinarySubtract => a - b,
+                OpCode::BinaryMultiply => a * b,
+                _ => return None,
+            })
+        }
+    };
+    from_number(value)
+}
+
+// DUP_TOP; POP_JUMP_IF_FALSE; POP_TOP → JUMP_IF_FALSE_OR_POP
+// UNARY_NOT; POP_JUMP_IF_FALSE → POP_JUMP_IF_TRUE
+fn fuse_jumps(mut instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let is_target = jump_targets(&instructions);
+    let mut keep = vec![true; instructions.len()];
+    let mut i = 0;
+    while i + 1 < instructions.len() {
+        if is_target[i + 1] {
+            i += 1;
+            continue;
+        }
+        match (instructions[i].op, instructions[i + 1].op) {
+            (OpCode::DupTop, jump @ (OpCode::PopJumpIfFalse(_) | OpCode::PopJumpIfTrue(_)))
+                if i + 2 < instructions.len()
+                    && !is_target[i + 2]
+                    && instructions[i + 2].op == OpCode::PopTop =>
+            {
+                instructions[i + 1].op = match jump {
+                    OpCode::PopJumpIfFalse(v) => OpCode::JumpIfFalseOrPop(v),
+                    OpCode::PopJumpIfTrue(v) => OpCode::JumpIfTrueOrPop(v),
+                    _ => unreachable!(),
+                };
+                keep[i] = false;
+                keep[i + 2] = false;
+                i += 3;
+            }
+            (OpCode::UnaryNot, OpCode::PopJumpIfFalse(v)) => {
+                instructions[i + 1].op = OpCode::PopJumpIfTrue(v);
+                keep[i] = false;
+                i += 2;
+            }
+            (OpCode::UnaryNot, OpCode::PopJumpIfTrue(v)) => {
+                instructions[i + 1].op = OpCode::PopJumpIfFalse(v);
+                keep[i] = false;
+                i += 2;
+            }
+            _ => i += 1,
+        }
+    }
+    compact(instructions, &keep)
+}
+
+// 無条件ジャンプへの分岐は、その先へ直接分岐する
+// 相対ジャンプは前方にしか分岐できないので、前方に付け替えられる場合だけ行う
+fn thread_jumps(instructions: &mut [Instruction]) {
+    for i in 0..instructions.len() {
+        let Some(mut target) = instructions[i].target else {
+            continue;
+        };
+        // ジャンプの循環で止まらないよう、たどる回数を命令数までにする
+        for _ in 0..instructions.len() {
+            match (&instructions[target].op, instructions[target].target) {
+                (OpCode::JumpAbsolute(_), Some(next)) if next != target => target = next,
+                _ => break,
+            }
+        }
+        if is_relative_jump(&instructions[i].op) && target <= i {
+            continue;
+        }
+        instructions[i].target = Some(target);
+    }
+}
+
+// 先頭からたどれない命令を取り除く
+fn remove_unreachable(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut reachable = vec![false; instructions.len()];
+    let mut worklist = vec![0];
+    while let Some(i) = worklist.pop() {
+        if i >= instructions.len() || reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        if let Some(target) = instructions[i].target {
+            worklist.push(target);
+        }
+        if !is_terminator(&instructions[i].op) {
+            worklist.push(i + 1);
+        }
+    }
+    compact(instructions, &reachable)
+}
+
+// 次の命令への無条件ジャンプを取り除く
+fn remove_jumps_to_next(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let keep: Vec<bool> = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, v)| !matches!(v.op, OpCode::JumpAbsolute(_)) || v.target != Some(i + 1))
+        .collect();
+    compact(instructions, &keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::optimize;
+    use crate::bytecode::OpCode;
+    use crate::pyobject::PyObject;
+    use crate::OptLevel;
+
+    #[test]
+    fn fold_constants() {
+        // 1 + 2 * 3, "a" + "b"
+        let operation_list = vec![
+            OpCode::LoadConst(0),
+            OpCode::LoadConst(1),
+            OpCode::LoadConst(2),
+            OpCode::BinaryMultiply,
+            OpCode::BinaryAdd,
+            OpCode::LoadConst(3),
+            OpCode::LoadConst(4),
+            OpCode::BinaryAdd,
+            OpCode::BuildTuple(2),
+            OpCode::ReturnValue,
+        ];
+        let mut constant_list = vec![
+            PyObject::Int(1, false),
+            PyObject::Int(2, false),
+            PyObject::Int(3, false),
+            PyObject::new_string("a".to_string(), false),
+            PyObject::new_string("b".to_string(), false),
+        ];
+        let (operation_list, _) = optimize(
+            &operation_list,
+            &HashMap::new(),
+            &mut constant_list,
+            OptLevel::O2,
+        )
+        .unwrap();
+        assert_eq!(
+            operation_list,
+            vec![
+                OpCode::LoadConst(6),
+                OpCode::LoadConst(7),
+                OpCode::BuildTuple(2),
+                OpCode::ReturnValue,
+            ]
+        );
+        assert!(matches!(constant_list[6], PyObject::Int(7, _)));
+    }
+
+    #[test]
+    fn fuse_and_thread_jumps() {
+        // a && b を返す。末尾の到達できない命令は取り除かれる
+        let operation_list = vec![
+            OpCode::LoadFast(0),
+            OpCode::DupTop,
+            OpCode::PopJumpIfFalse(0),
+            OpCode::PopTop,
+            OpCode::LoadFast(1),
+            OpCode::JumpAbsolute(1),
+            OpCode::ReturnValue,
+            OpCode::LoadConst(0),
+            OpCode::ReturnValue,
+        ];
+        let label_table = HashMap::from([(0, 5), (1, 6)]);
+        let (operation_list, label_table) =
+            optimize(&operation_list, &label_table, &mut vec![], OptLevel::O1).unwrap();
+        assert_eq!(
+            operation_list,
+            vec![
+                OpCode::LoadFast(0),
+                OpCode::JumpIfFalseOrPop(1),
+                OpCode::LoadFast(1),
+                OpCode::ReturnValue,
+            ]
+        );
+        assert_eq!(label_table[&1], 3);
+    }
+}
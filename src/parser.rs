@@ -17,6 +17,7 @@ mod parse_functions;
 mod parse_identifier;
 mod parse_library;
 mod parse_literal;
+mod parse_metadata;
 mod parse_selector;
 mod parse_statement;
 mod parse_type;
@@ -25,12 +26,20 @@ mod util;
 
 pub fn parse<'input>(
     input: Vec<Token<'input>>,
-    transition_map: TransitionMap,
-) -> Result<LibraryDeclaration> {
+    transition_map: &TransitionMap,
+) -> Result<LibraryDeclaration<'input>> {
     let internal_node = parse_internally(input, transition_map)?;
     parse_library(&internal_node)
 }
 
+/// 構文規則の名前付きで内部の構文木を出力する
+pub fn format_parse_tree(input: Vec<Token<'_>>, transition_map: &TransitionMap) -> Result<String> {
+    let internal_node = parse_internally(input, transition_map)?;
+    let mut result = String::new();
+    internal_node.format(0, &mut result);
+    Ok(result)
+}
+
 // 初期状態
 // input: token, token, token, token, ...
 // stack: (I0, _) ->
@@ -54,7 +63,7 @@ pub fn parse<'input>(
 //    5.1. acceptをtrueにして終了
 fn parse_internally<'input>(
     input: Vec<Token<'input>>,
-    transition_map: TransitionMap,
+    transition_map: &TransitionMap,
 ) -> Result<NodeInternal<'input>> {
     let mut stack: Vec<String> = Vec::new();
     let node_stack: Vec<NodeInternal> = Vec::new();
@@ -62,7 +71,7 @@ fn parse_internally<'input>(
 
     stack.push(String::from("I0"));
 
-    build_internal_node(&input, &transition_map, stack, node_stack, parse_index)
+    build_internal_node(&input, transition_map, stack, node_stack, parse_index)
 }
 
 fn build_internal_node<'input>(
@@ -136,7 +145,7 @@ fn build_internal_node<'input>(
                     );
                     match result {
                         Ok(node) => {
-                            println!("Reduce-Reduce Conflict resolved: {:?}", selected_rule);
+                            eprintln!("Reduce-Reduce Conflict resolved: {:?}", selected_rule);
                             return Ok(node);
                         }
                         Err(_) => {
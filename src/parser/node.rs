@@ -1,3 +1,8 @@
+use serde::Serialize;
+
+use crate::tokenizer::Position;
+
+#[derive(Debug, Serialize)]
 pub enum NodeExpression<'input> {
     Binary {
         left: Box<NodeExpression<'input>>,
@@ -46,6 +51,7 @@ pub enum NodeExpression<'input> {
     },
     SetOrMapLiteral {
         element_list: Vec<CollectionElement<'input>>,
+        is_const: bool,
     },
     Identifier {
         identifier: Identifier<'input>,
@@ -65,6 +71,7 @@ pub enum NodeExpression<'input> {
     This,
 }
 
+#[derive(Debug, Serialize)]
 pub enum NodeStatement<'input> {
     Labeled {
         label: Identifier<'input>,
@@ -93,10 +100,19 @@ pub enum NodeStatement<'input> {
     FunctionDeclaration {
         signature: FunctionSignature<'input>,
         body: Box<NodeStatement<'input>>,
+        // @で付けられたアノテーション。デコレータとして適用する
+        metadata_list: Vec<NodeExpression<'input>>,
     },
     ClassDeclaration {
         identifier: Identifier<'input>,
         member_list: Vec<Member<'input>>,
+        metadata_list: Vec<NodeExpression<'input>>,
+        is_abstract: bool,
+        superclass: Option<DartType<'input>>,
+    },
+    // 実行時に存在する名前の宣言。コードは生成しない
+    ExternalDeclaration {
+        identifier: Identifier<'input>,
     },
     If {
         condition: Box<NodeExpression<'input>>,
@@ -120,6 +136,7 @@ pub enum NodeStatement<'input> {
     ForIn {
         variable: Identifier<'input>,
         is_variable_declared: bool,
+        dart_type: Option<DartType<'input>>,
         iterable: Box<NodeExpression<'input>>,
         stmt: Box<NodeStatement<'input>>,
     },
@@ -138,6 +155,7 @@ pub enum NodeStatement<'input> {
     },
 }
 
+#[derive(Debug, Serialize)]
 pub enum Selector<'input> {
     Index {
         expr: Box<NodeExpression<'input>>,
@@ -154,55 +172,89 @@ pub enum Selector<'input> {
     },
 }
 
+#[derive(Debug, Serialize)]
 pub struct CallParameter<'input> {
     pub identifier: Option<Identifier<'input>>,
     pub expr: Box<NodeExpression<'input>>,
 }
 
+#[derive(Debug, Serialize)]
 pub enum IdentifierKind {
     Normal,
     BuiltIn,
     Other,
 }
 
+#[derive(Debug, Serialize)]
 pub struct Identifier<'input> {
     pub value: &'input str,
     pub kind: IdentifierKind,
+    pub position: Position,
 }
 
+#[derive(Debug, Serialize)]
 pub struct LibraryDeclaration<'input> {
+    pub library_name: Option<Vec<Identifier<'input>>>,
     pub import_list: Vec<LibraryImport<'input>>,
+    // exportはprefixのないimportとして扱う
+    pub export_list: Vec<LibraryImport<'input>>,
+    pub part_list: Vec<&'input str>,
+    pub part_of: Option<PartOf<'input>>,
     pub top_level_declaration_list: Vec<Box<NodeStatement<'input>>>,
 }
 
+#[derive(Debug, Serialize)]
+pub enum PartOf<'input> {
+    Uri(&'input str),
+    Name(Vec<Identifier<'input>>),
+}
+
+#[derive(Debug, Serialize)]
 pub struct LibraryImport<'input> {
     pub uri: &'input str,
     pub identifier: Option<Identifier<'input>>,
     pub combinator_list: Vec<Combinator<'input>>,
+    // loadLibrary()が呼ばれるまで読み込まない
+    pub deferred: bool,
+    // 条件を満たす最初のuriが使われる。どれも満たさなければuriを使う
+    pub configuration_list: Vec<ImportConfiguration<'input>>,
+}
+
+// if (python.cupy) 'cupy.dart'
+#[derive(Debug, Serialize)]
+pub struct ImportConfiguration<'input> {
+    pub test: Vec<Identifier<'input>>,
+    pub uri: &'input str,
 }
 
+#[derive(Debug, Serialize)]
 pub struct Combinator<'input> {
     pub is_show: bool,
     pub target_list: Vec<Identifier<'input>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct FunctionSignature<'input> {
     pub return_type: Option<DartType<'input>>,
     pub name: Identifier<'input>,
     pub param: FunctionParamSignature<'input>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct FunctionParamSignature<'input> {
     pub normal_list: Vec<FunctionParameter<'input>>,
     pub option_list: Vec<FunctionParameter<'input>>,
     pub named_list: Vec<FunctionParameter<'input>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct FunctionParameter<'input> {
     pub identifier: Identifier<'input>,
+    pub dart_type: Option<DartType<'input>>,
     pub expr: Option<Box<NodeExpression<'input>>>,
 }
 
+#[derive(Debug, Serialize)]
 pub enum DartType<'input> {
     Named {
         type_name: DartTypeName<'input>,
@@ -212,38 +264,47 @@ pub enum DartType<'input> {
     Void,
 }
 
+#[derive(Debug, Serialize)]
 pub struct DartTypeName<'input> {
     pub identifier: Identifier<'input>,
     pub module: Option<Identifier<'input>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct VariableDeclaration<'input> {
     pub identifier: Identifier<'input>,
+    // 宣言された型 (var, finalのみの場合はNone)
+    pub dart_type: Option<DartType<'input>>,
     pub expr: Option<Box<NodeExpression<'input>>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct SwitchCase<'input> {
     pub label_list: Vec<Box<NodeExpression<'input>>>,
     pub expr: Box<NodeExpression<'input>>,
     pub stmt_list: Vec<Box<NodeStatement<'input>>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct DefaultCase<'input> {
     pub label_list: Vec<Box<NodeExpression<'input>>>,
     pub stmt_list: Vec<Box<NodeStatement<'input>>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct TryOnPart<'input> {
     pub catch_part: Option<TryCatchPart<'input>>,
     pub exc_type: Option<DartType<'input>>,
     pub block: Box<NodeStatement<'input>>,
 }
 
+#[derive(Debug, Serialize)]
 pub struct TryCatchPart<'input> {
     pub id_error: Identifier<'input>,
     pub id_trace: Option<Identifier<'input>>,
 }
 
+#[derive(Debug, Serialize)]
 pub enum CollectionElement<'input> {
     ExpressionElement {
         expr: Box<NodeExpression<'input>>,
@@ -254,30 +315,48 @@ pub enum CollectionElement<'input> {
     },
 }
 
+#[derive(Debug, Serialize)]
 pub struct ConstructorSignature<'input> {
     pub name: Option<Identifier<'input>>,
     pub param: FunctionParamSignature<'input>,
 }
 
+#[derive(Debug, Serialize)]
 pub enum Member<'input> {
     MethodImpl {
         signature: FunctionSignature<'input>,
         body: Box<NodeStatement<'input>>,
+        metadata_list: Vec<NodeExpression<'input>>,
+    },
+    // ゲッター (引数のないsignatureを持つ)
+    GetterImpl {
+        signature: FunctionSignature<'input>,
+        body: Box<NodeStatement<'input>>,
+        metadata_list: Vec<NodeExpression<'input>>,
     },
     ConstructorImpl {
         signature: ConstructorSignature<'input>,
         body: Box<NodeStatement<'input>>,
+        // 初期化リストのsuper(...)の引数
+        super_arguments: Option<Vec<CallParameter<'input>>>,
+    },
+    // 本体のないメソッド宣言
+    AbstractMethod {
+        signature: FunctionSignature<'input>,
+        metadata_list: Vec<NodeExpression<'input>>,
     },
     VariableDecl {
         decl_list: Vec<VariableDeclaration<'input>>,
     },
 }
 
+#[derive(Debug, Serialize)]
 pub struct TypeTest<'input> {
     pub dart_type: DartType<'input>,
     pub check_matching: bool,
 }
 
+#[derive(Debug, Serialize)]
 pub struct StringWithInterpolation<'input> {
     pub string_list: Vec<&'input str>,
     pub interpolation_list: Vec<NodeExpression<'input>>,
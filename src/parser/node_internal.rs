@@ -6,3 +6,19 @@ pub struct NodeInternal<'input> {
     pub children: Vec<NodeInternal<'input>>,
     pub token: Option<Token<'input>>,
 }
+
+impl NodeInternal<'_> {
+    /// 子を字下げして1行に1つずつ出力する
+    pub fn format(&self, depth: usize, result: &mut String) {
+        let indent = "  ".repeat(depth);
+        match &self.token {
+            Some(token) => {
+                result.push_str(&format!("{}{:?} {:?}\n", indent, token.kind, token.str))
+            }
+            None => result.push_str(&format!("{}{}\n", indent, self.rule_name)),
+        }
+        for child in &self.children {
+            child.format(depth + 1, result);
+        }
+    }
+}
@@ -1,11 +1,17 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 
 use super::{
-    node::{DartType, Member, NodeStatement},
+    node::{
+        ConstructorSignature, DartType, FunctionParamSignature, FunctionSignature, Identifier,
+        IdentifierKind, Member, NodeStatement,
+    },
     node_internal::NodeInternal,
-    parse_functions::{parse_function_body, parse_function_signature},
+    parse_functions::{parse_formal_parameter_list, parse_function_body, parse_function_signature},
     parse_identifier::parse_identifier,
-    parse_variables::parse_initialized_identifier_list,
+    parse_metadata::parse_metadata_list,
+    parse_selector::parse_arguments,
+    parse_type::parse_type,
+    parse_variables::parse_typed_identifier_list,
     util::flatten,
 };
 
@@ -13,22 +19,41 @@ pub fn parse_class_declaration<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<NodeStatement<'input>> {
     if node.rule_name == "ClassDeclaration" {
-        if node.children.len() == 4 {
-            return Ok(NodeStatement::ClassDeclaration {
-                identifier: parse_identifier(&node.children[1])?,
-                member_list: vec![],
-            });
+        // abstract class A extends B { ... }
+        let is_abstract = node.children[0].rule_name == "abstract";
+        let rest = if is_abstract {
+            &node.children[2..]
         } else {
-            return Ok(NodeStatement::ClassDeclaration {
-                identifier: parse_identifier(&node.children[1])?,
-                member_list: parse_class_declaration_internal(&node.children[3])?,
-            });
-        }
+            &node.children[1..]
+        };
+        let superclass = match rest.len() {
+            3 => Some(parse_type(&rest[1].children[1])?),
+            _ => None,
+        };
+        return Ok(NodeStatement::ClassDeclaration {
+            identifier: parse_identifier(&rest[0])?,
+            member_list: parse_class_body(rest.last().unwrap())?,
+            metadata_list: vec![],
+            is_abstract,
+            superclass,
+        });
     }
 
     bail!("Parse Error in parse_class_declaration: {}", node.rule_name);
 }
 
+fn parse_class_body<'input>(node: &NodeInternal<'input>) -> Result<Vec<Member<'input>>> {
+    if node.rule_name == "ClassBody" {
+        if node.children.len() == 2 {
+            return Ok(vec![]);
+        } else {
+            return parse_class_declaration_internal(&node.children[1]);
+        }
+    }
+
+    bail!("Parse Error in parse_class_body: {}", node.rule_name);
+}
+
 fn parse_class_declaration_internal<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<Vec<Member<'input>>> {
@@ -53,9 +78,31 @@ fn parse_class_member_declaration<'input>(node: &NodeInternal<'input>) -> Result
     if node.rule_name == "ClassMemberDeclaration" {
         if node.children[0].rule_name == "Declaration" {
             return parse_declaration(&node.children[0]);
+        }
+        let (metadata_list, rest) = if node.children[0].rule_name == "MetadataList" {
+            (parse_metadata_list(&node.children[0])?, &node.children[1..])
         } else {
-            return parse_member_impl(&node.children[0]);
+            (vec![], &node.children[..])
+        };
+        if rest[0].rule_name == "FunctionSignature" {
+            return Ok(Member::AbstractMethod {
+                signature: parse_function_signature(&rest[0])?,
+                metadata_list,
+            });
+        }
+        let mut member = parse_member_impl(&rest[0])?;
+        if let Member::MethodImpl {
+            metadata_list: member_metadata_list,
+            ..
+        }
+        | Member::GetterImpl {
+            metadata_list: member_metadata_list,
+            ..
+        } = &mut member
+        {
+            *member_metadata_list = metadata_list;
         }
+        return Ok(member);
     }
 
     bail!(
@@ -66,7 +113,20 @@ fn parse_class_member_declaration<'input>(node: &NodeInternal<'input>) -> Result
 
 fn parse_member_impl<'input>(node: &NodeInternal<'input>) -> Result<Member<'input>> {
     if node.rule_name == "MemberImpl" {
-        let signature = parse_function_signature(&node.children[0])?;
+        let signature = match node.children[0].rule_name.as_str() {
+            "GetterSignature" => {
+                return Ok(Member::GetterImpl {
+                    signature: parse_getter_signature(&node.children[0])?,
+                    body: Box::new(parse_function_body(&node.children[1], false)?),
+                    metadata_list: vec![],
+                });
+            }
+            "OperatorSignature" => parse_operator_signature(&node.children[0])?,
+            _ => parse_function_signature(&node.children[0])?,
+        };
+        if node.children[1].rule_name == "Initializers" {
+            return parse_constructor_impl(signature, &node.children[1], &node.children[2]);
+        }
         let return_is_void = match &signature.return_type {
             Some(return_type) => match return_type {
                 DartType::Void => true,
@@ -77,16 +137,95 @@ fn parse_member_impl<'input>(node: &NodeInternal<'input>) -> Result<Member<'inpu
         return Ok(Member::MethodImpl {
             signature,
             body: Box::new(parse_function_body(&node.children[1], return_is_void)?),
+            metadata_list: vec![],
         });
     }
 
     bail!("Parse Error in parse_member_impl: {}", node.rule_name);
 }
 
+// Sq(double side) : super("square") { ... }
+// 初期化リストを持つのはコンストラクタだけ
+fn parse_constructor_impl<'input>(
+    signature: FunctionSignature<'input>,
+    initializers: &NodeInternal<'input>,
+    body: &NodeInternal<'input>,
+) -> Result<Member<'input>> {
+    ensure!(
+        signature.return_type.is_none(),
+        "a constructor cannot have a return type: {}",
+        signature.name.value
+    );
+    let body = match body.rule_name.as_str() {
+        ";" => NodeStatement::Empty,
+        _ => parse_function_body(body, true)?,
+    };
+    Ok(Member::ConstructorImpl {
+        signature: ConstructorSignature {
+            name: Some(signature.name),
+            param: signature.param,
+        },
+        body: Box::new(body),
+        super_arguments: Some(parse_arguments(&initializers.children[2])?),
+    })
+}
+
+// int get x
+fn parse_getter_signature<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<FunctionSignature<'input>> {
+    if node.rule_name == "GetterSignature" {
+        let return_type = if node.children.len() == 3 {
+            Some(parse_type(&node.children[0])?)
+        } else {
+            None
+        };
+        return Ok(FunctionSignature {
+            return_type,
+            name: parse_identifier(node.children.last().unwrap())?,
+            param: FunctionParamSignature {
+                normal_list: vec![],
+                option_list: vec![],
+                named_list: vec![],
+            },
+        });
+    }
+
+    bail!("Parse Error in parse_getter_signature: {}", node.rule_name);
+}
+
+// bool operator ==(Object other)
+// 演算子をメソッド名とするsignatureにする
+fn parse_operator_signature<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<FunctionSignature<'input>> {
+    if node.rule_name == "OperatorSignature" {
+        let (return_type, rest) = if node.children.len() == 4 {
+            (Some(parse_type(&node.children[0])?), &node.children[1..])
+        } else {
+            (None, &node.children[..])
+        };
+        return Ok(FunctionSignature {
+            return_type,
+            name: Identifier {
+                value: rest[1].token.clone().unwrap().str,
+                kind: IdentifierKind::Normal,
+                position: rest[1].token.clone().unwrap().position,
+            },
+            param: parse_formal_parameter_list(&rest[2])?,
+        });
+    }
+
+    bail!(
+        "Parse Error in parse_operator_signature: {}",
+        node.rule_name
+    );
+}
+
 fn parse_declaration<'input>(node: &NodeInternal<'input>) -> Result<Member<'input>> {
     if node.rule_name == "Declaration" {
         return Ok(Member::VariableDecl {
-            decl_list: parse_initialized_identifier_list(node.children.last().unwrap())?,
+            decl_list: parse_typed_identifier_list(&node.children, node.children.last().unwrap())?,
         });
     }
 
@@ -2,7 +2,8 @@ use anyhow::{bail, Result};
 
 use super::{
     node::{
-        FunctionParamSignature, FunctionParameter, FunctionSignature, Identifier, NodeStatement,
+        DartType, FunctionParamSignature, FunctionParameter, FunctionSignature, Identifier,
+        NodeExpression, NodeStatement,
     },
     node_internal::NodeInternal,
     parse_expression::parse_expression,
@@ -60,7 +61,7 @@ pub fn parse_function_signature<'input>(
     );
 }
 
-fn parse_formal_parameter_list<'input>(
+pub fn parse_formal_parameter_list<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<FunctionParamSignature<'input>> {
     if node.rule_name == "FormalParameterList" {
@@ -211,12 +212,10 @@ fn parse_named_formal_parameter_list_internal<'input>(
 
 fn parse_normal_formal_parameter<'input>(
     node: &NodeInternal<'input>,
-) -> Result<Identifier<'input>> {
+    expr: Option<Box<NodeExpression<'input>>>,
+) -> Result<FunctionParameter<'input>> {
     if node.rule_name == "NormalFormalParameter" {
-        if node.children[0].rule_name == "DeclaredIdentifier" {
-            return Ok(parse_declared_identifier(&node.children[0])?);
-        }
-        return Ok(parse_identifier(&node.children[0])?);
+        return parse_parameter(&node.children[0], expr);
     }
 
     bail!(
@@ -230,17 +229,14 @@ fn parse_normal_formal_parameter_list<'input>(
 ) -> Result<Vec<FunctionParameter<'input>>> {
     if node.rule_name == "NormalFormalParameterList" {
         if node.children.len() == 1 {
-            return Ok(vec![FunctionParameter {
-                identifier: parse_normal_formal_parameter(&node.children[0])?,
-                expr: None,
-            }]);
+            return Ok(vec![parse_normal_formal_parameter(
+                &node.children[0],
+                None,
+            )?]);
         } else {
             return flatten(
                 parse_normal_formal_parameter_list(&node.children[0]),
-                FunctionParameter {
-                    identifier: parse_normal_formal_parameter(&node.children[2])?,
-                    expr: None,
-                },
+                parse_normal_formal_parameter(&node.children[2], None)?,
             );
         }
     }
@@ -256,15 +252,12 @@ fn parse_default_formal_parameter<'input>(
 ) -> Result<FunctionParameter<'input>> {
     if node.rule_name == "DefaultFormalParameter" {
         if node.children.len() == 1 {
-            return Ok(FunctionParameter {
-                identifier: parse_normal_formal_parameter(&node.children[0])?,
-                expr: None,
-            });
+            return parse_normal_formal_parameter(&node.children[0], None);
         } else {
-            return Ok(FunctionParameter {
-                identifier: parse_normal_formal_parameter(&node.children[0])?,
-                expr: Some(Box::new(parse_expression(&node.children[2])?)),
-            });
+            return parse_normal_formal_parameter(
+                &node.children[0],
+                Some(Box::new(parse_expression(&node.children[2])?)),
+            );
         }
     }
 
@@ -278,46 +271,16 @@ fn parse_default_named_parameter<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<FunctionParameter<'input>> {
     if node.rule_name == "DefaultNamedParameter" {
-        if node.children.len() == 1 {
-            return Ok(FunctionParameter {
-                identifier: if node.children[0].rule_name == "Identifier" {
-                    parse_identifier(&node.children[0])?
-                } else {
-                    parse_declared_identifier(&node.children[0])?
-                },
-                expr: None,
-            });
-        }
-        if node.children.len() == 2 {
-            return Ok(FunctionParameter {
-                identifier: if node.children[1].rule_name == "Identifier" {
-                    parse_identifier(&node.children[1])?
-                } else {
-                    parse_declared_identifier(&node.children[1])?
-                },
-                expr: None,
-            });
-        }
-        if node.children.len() == 3 {
-            return Ok(FunctionParameter {
-                identifier: if node.children[0].rule_name == "Identifier" {
-                    parse_identifier(&node.children[0])?
-                } else {
-                    parse_declared_identifier(&node.children[0])?
-                },
-                expr: Some(Box::new(parse_expression(&node.children[2])?)),
-            });
-        }
-        if node.children.len() == 4 {
-            return Ok(FunctionParameter {
-                identifier: if node.children[1].rule_name == "Identifier" {
-                    parse_identifier(&node.children[1])?
-                } else {
-                    parse_declared_identifier(&node.children[1])?
-                },
-                expr: Some(Box::new(parse_expression(&node.children[3])?)),
-            });
-        }
+        // 'required'を読み飛ばす
+        let rest = match node.children[0].token.as_ref() {
+            Some(token) if token.str == "required" => &node.children[1..],
+            _ => &node.children[..],
+        };
+        let expr = match rest.len() {
+            1 => None,
+            _ => Some(Box::new(parse_expression(&rest[2])?)),
+        };
+        return parse_parameter(&rest[0], expr);
     }
 
     bail!(
@@ -326,11 +289,41 @@ fn parse_default_named_parameter<'input>(
     );
 }
 
+// IdentifierかDeclaredIdentifierで宣言された引数
+fn parse_parameter<'input>(
+    node: &NodeInternal<'input>,
+    expr: Option<Box<NodeExpression<'input>>>,
+) -> Result<FunctionParameter<'input>> {
+    if node.rule_name == "DeclaredIdentifier" {
+        return Ok(FunctionParameter {
+            identifier: parse_declared_identifier(node)?,
+            dart_type: parse_declared_type(&node.children)?,
+            expr,
+        });
+    }
+    Ok(FunctionParameter {
+        identifier: parse_identifier(node)?,
+        dart_type: None,
+        expr,
+    })
+}
+
+/// 宣言に型が書かれていれば、その型を返す
+pub fn parse_declared_type<'input>(
+    children: &[NodeInternal<'input>],
+) -> Result<Option<DartType<'input>>> {
+    children
+        .iter()
+        .find(|v| v.rule_name == "Type")
+        .map(parse_type)
+        .transpose()
+}
+
 pub fn parse_declared_identifier<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<Identifier<'input>> {
     if node.rule_name == "DeclaredIdentifier" {
-        return Ok(parse_identifier(&node.children.last().unwrap())?);
+        return parse_identifier(node.children.last().unwrap());
     }
 
     bail!(
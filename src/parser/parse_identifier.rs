@@ -28,16 +28,26 @@ pub fn parse_identifier<'input>(node: &NodeInternal<'input>) -> Result<Identifie
             return Ok(Identifier {
                 value: child_node.token.clone().unwrap().str,
                 kind: IdentifierKind::Normal,
+                position: child_node.token.clone().unwrap().position,
             });
         } else if child_node.rule_name == "BUILT_IN_IDENTIFIER" {
             return Ok(Identifier {
                 value: child_node.children[0].token.clone().unwrap().str,
                 kind: IdentifierKind::BuiltIn,
+                position: child_node.children[0].token.clone().unwrap().position,
+            });
+        } else if child_node.rule_name == "dynamic" {
+            // TypeIdentifierではdynamicが直接現れる
+            return Ok(Identifier {
+                value: child_node.token.clone().unwrap().str,
+                kind: IdentifierKind::BuiltIn,
+                position: child_node.token.clone().unwrap().position,
             });
         } else if child_node.rule_name == "OTHER_IDENTIFIER" {
             return Ok(Identifier {
                 value: child_node.children[0].token.clone().unwrap().str,
                 kind: IdentifierKind::Other,
+                position: child_node.children[0].token.clone().unwrap().position,
             });
         }
     }
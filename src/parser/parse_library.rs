@@ -1,22 +1,32 @@
 use std::vec;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 
 use super::{
-    node::{Combinator, DartType, LibraryDeclaration, LibraryImport, NodeStatement},
+    node::{
+        Combinator, DartType, Identifier, ImportConfiguration, LibraryDeclaration, LibraryImport,
+        NodeStatement, PartOf,
+    },
     node_internal::NodeInternal,
     parse_class::parse_class_declaration,
     parse_functions::{parse_function_body, parse_function_signature},
     parse_identifier::{parse_identifier, parse_identifier_list},
-    parse_variables::parse_initialized_identifier_list,
+    parse_metadata::parse_metadata_list,
+    parse_variables::parse_typed_identifier_list,
     util::flatten,
 };
 
 pub fn parse_library<'input>(node: &NodeInternal<'input>) -> Result<LibraryDeclaration<'input>> {
-    Ok(LibraryDeclaration {
-        import_list: parse_library_import_list(&node.children[0])?,
+    let mut library = LibraryDeclaration {
+        library_name: None,
+        import_list: vec![],
+        export_list: vec![],
+        part_list: vec![],
+        part_of: None,
         top_level_declaration_list: parse_top_level_declaration_list(&node.children[1])?,
-    })
+    };
+    parse_library_directive_list(&node.children[0], &mut library)?;
+    Ok(library)
 }
 
 fn parse_top_level_declaration_list<'input>(
@@ -53,6 +63,23 @@ fn parse_top_level_declaration<'input>(
             "ClassDeclaration" => {
                 return Ok(parse_class_declaration(&node.children[0])?);
             }
+            "TopExternalDeclaration" => {
+                return parse_top_external_declaration(&node.children[0]);
+            }
+            "MetadataList" => {
+                let mut declaration = match node.children[1].rule_name.as_str() {
+                    "ClassDeclaration" => parse_class_declaration(&node.children[1])?,
+                    _ => parse_top_function_declaration(&node.children[1])?,
+                };
+                match &mut declaration {
+                    NodeStatement::FunctionDeclaration { metadata_list, .. }
+                    | NodeStatement::ClassDeclaration { metadata_list, .. } => {
+                        *metadata_list = parse_metadata_list(&node.children[0])?;
+                    }
+                    _ => (),
+                }
+                return Ok(declaration);
+            }
             _ => {}
         }
     }
@@ -63,51 +90,229 @@ fn parse_top_level_declaration<'input>(
     );
 }
 
-fn parse_library_import_list<'input>(
+fn parse_library_directive_list<'input>(
     node: &NodeInternal<'input>,
-) -> Result<Vec<LibraryImport<'input>>> {
-    if node.rule_name == "LibraryImportList" {
+    library: &mut LibraryDeclaration<'input>,
+) -> Result<()> {
+    if node.rule_name == "LibraryDirectiveList" {
         if node.children.len() == 0 {
-            return Ok(vec![]);
+            return Ok(());
+        } else {
+            parse_library_directive_list(&node.children[0], library)?;
+            return parse_library_directive(&node.children[1], library);
+        }
+    }
+
+    bail!(
+        "Parse Error in parse_library_directive_list: {}",
+        node.rule_name
+    );
+}
+
+fn parse_library_directive<'input>(
+    node: &NodeInternal<'input>,
+    library: &mut LibraryDeclaration<'input>,
+) -> Result<()> {
+    if node.rule_name == "LibraryDirective" {
+        let child = &node.children[0];
+        match child.rule_name.as_str() {
+            "LibraryName" => {
+                ensure!(
+                    library.library_name.is_none(),
+                    "Duplicate library directive."
+                );
+                library.library_name = Some(if child.children.len() == 3 {
+                    parse_dotted_identifier_list(&child.children[1])?
+                } else {
+                    vec![]
+                });
+                return Ok(());
+            }
+            "LibraryImport" => {
+                library.import_list.push(parse_library_import(child)?);
+                return Ok(());
+            }
+            "LibraryExport" => {
+                library.export_list.push(parse_library_export(child)?);
+                return Ok(());
+            }
+            "PartDirective" => {
+                library.part_list.push(parse_uri(&child.children[1]));
+                return Ok(());
+            }
+            "PartHeader" => {
+                ensure!(library.part_of.is_none(), "Duplicate part-of directive.");
+                library.part_of = Some(parse_part_header(child)?);
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    bail!("Parse Error in parse_library_directive: {}", node.rule_name);
+}
+
+fn parse_uri<'input>(node: &NodeInternal<'input>) -> &'input str {
+    node.children[0].token.clone().unwrap().str
+}
+
+fn parse_library_export<'input>(node: &NodeInternal<'input>) -> Result<LibraryImport<'input>> {
+    if node.rule_name == "LibraryExport" {
+        let (uri, configuration_list) = parse_configurable_uri(&node.children[1])?;
+        if node.children.len() == 3 {
+            return Ok(LibraryImport {
+                uri,
+                identifier: None,
+                combinator_list: vec![],
+                deferred: false,
+                configuration_list,
+            });
+        } else if node.children.len() == 4 {
+            return Ok(LibraryImport {
+                uri,
+                identifier: None,
+                combinator_list: parse_combinator_list(&node.children[2])?,
+                deferred: false,
+                configuration_list,
+            });
+        }
+    }
+
+    bail!("Parse Error in parse_library_export: {}", node.rule_name);
+}
+
+fn parse_configurable_uri<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<(&'input str, Vec<ImportConfiguration<'input>>)> {
+    if node.rule_name == "ConfigurableUri" {
+        let uri = parse_uri(&node.children[0]);
+        if node.children.len() == 1 {
+            return Ok((uri, vec![]));
+        } else {
+            return Ok((uri, parse_configuration_uri_list(&node.children[1])?));
+        }
+    }
+
+    bail!("Parse Error in parse_configurable_uri: {}", node.rule_name);
+}
+
+fn parse_configuration_uri_list<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<Vec<ImportConfiguration<'input>>> {
+    if node.rule_name == "ConfigurationUriList" {
+        if node.children.len() == 1 {
+            return Ok(vec![parse_configuration_uri(&node.children[0])?]);
         } else {
             return flatten(
-                parse_library_import_list(&node.children[0]),
-                parse_library_import(&node.children[1])?,
+                parse_configuration_uri_list(&node.children[0]),
+                parse_configuration_uri(&node.children[1])?,
             );
         }
     }
 
     bail!(
-        "Parse Error in parse_library_import_list: {}",
+        "Parse Error in parse_configuration_uri_list: {}",
+        node.rule_name
+    );
+}
+
+fn parse_configuration_uri<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<ImportConfiguration<'input>> {
+    if node.rule_name == "ConfigurationUri" {
+        return Ok(ImportConfiguration {
+            test: parse_dotted_identifier_list(&node.children[2])?,
+            uri: parse_uri(&node.children[4]),
+        });
+    }
+
+    bail!("Parse Error in parse_configuration_uri: {}", node.rule_name);
+}
+
+fn parse_part_header<'input>(node: &NodeInternal<'input>) -> Result<PartOf<'input>> {
+    if node.rule_name == "PartHeader" {
+        let target = &node.children[2];
+        if target.rule_name == "Uri" {
+            return Ok(PartOf::Uri(parse_uri(target)));
+        } else {
+            return Ok(PartOf::Name(parse_dotted_identifier_list(target)?));
+        }
+    }
+
+    bail!("Parse Error in parse_part_header: {}", node.rule_name);
+}
+
+pub fn parse_dotted_identifier_list<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<Vec<Identifier<'input>>> {
+    if node.rule_name == "DottedIdentifierList" {
+        if node.children.len() == 1 {
+            return Ok(vec![parse_identifier(&node.children[0])?]);
+        } else {
+            return flatten(
+                parse_dotted_identifier_list(&node.children[0]),
+                parse_identifier(&node.children[2])?,
+            );
+        }
+    }
+
+    bail!(
+        "Parse Error in parse_dotted_identifier_list: {}",
         node.rule_name
     );
 }
 
 fn parse_library_import<'input>(node: &NodeInternal<'input>) -> Result<LibraryImport<'input>> {
     if node.rule_name == "LibraryImport" {
-        if node.children.len() == 3 {
+        let (uri, configuration_list) = parse_configurable_uri(&node.children[1])?;
+        let deferred = match &node.children[2].token {
+            Some(token) => token.str == "deferred",
+            None => false,
+        };
+        if deferred {
+            // import Uri deferred as Identifier CombinatorList? ;
+            return Ok(LibraryImport {
+                uri,
+                identifier: Some(parse_identifier(&node.children[4])?),
+                combinator_list: if node.children.len() == 7 {
+                    parse_combinator_list(&node.children[5])?
+                } else {
+                    vec![]
+                },
+                deferred,
+                configuration_list,
+            });
+        } else if node.children.len() == 3 {
             return Ok(LibraryImport {
-                uri: node.children[1].children[0].token.clone().unwrap().str,
+                uri,
                 identifier: None,
                 combinator_list: vec![],
+                deferred,
+                configuration_list,
             });
         } else if node.children.len() == 4 {
             return Ok(LibraryImport {
-                uri: node.children[1].children[0].token.clone().unwrap().str,
+                uri,
                 identifier: None,
                 combinator_list: parse_combinator_list(&node.children[2])?,
+                deferred,
+                configuration_list,
             });
         } else if node.children.len() == 5 {
             return Ok(LibraryImport {
-                uri: node.children[1].children[0].token.clone().unwrap().str,
+                uri,
                 identifier: Some(parse_identifier(&node.children[3])?),
                 combinator_list: vec![],
+                deferred,
+                configuration_list,
             });
         } else if node.children.len() == 6 {
             return Ok(LibraryImport {
-                uri: node.children[1].children[0].token.clone().unwrap().str,
+                uri,
                 identifier: Some(parse_identifier(&node.children[3])?),
                 combinator_list: parse_combinator_list(&node.children[4])?,
+                deferred,
+                configuration_list,
             });
         }
     }
@@ -130,6 +335,7 @@ fn parse_top_function_declaration<'input>(
         return Ok(NodeStatement::FunctionDeclaration {
             signature: parse_function_signature(&node.children[0])?,
             body: Box::new(parse_function_body(&node.children[1], return_is_void)?),
+            metadata_list: vec![],
         });
     }
 
@@ -139,21 +345,42 @@ fn parse_top_function_declaration<'input>(
     );
 }
 
+fn parse_top_external_declaration<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<NodeStatement<'input>> {
+    if node.rule_name == "TopExternalDeclaration" {
+        if node.children.len() == 3 {
+            return Ok(NodeStatement::ExternalDeclaration {
+                identifier: parse_function_signature(&node.children[1])?.name,
+            });
+        } else {
+            return Ok(NodeStatement::ExternalDeclaration {
+                identifier: parse_identifier(&node.children[2])?,
+            });
+        }
+    }
+
+    bail!(
+        "Parse Error in parse_top_external_declaration: {}",
+        node.rule_name
+    );
+}
+
 fn parse_top_variable_declaration<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<NodeStatement<'input>> {
     if node.rule_name == "TopVariableDeclaration" {
         if node.children.len() == 3 {
             return Ok(NodeStatement::VariableDeclarationList {
-                decl_list: parse_initialized_identifier_list(&node.children[1])?,
+                decl_list: parse_typed_identifier_list(&node.children, &node.children[1])?,
             });
         } else if node.children.len() == 4 {
             return Ok(NodeStatement::VariableDeclarationList {
-                decl_list: parse_initialized_identifier_list(&node.children[2])?,
+                decl_list: parse_typed_identifier_list(&node.children, &node.children[2])?,
             });
         } else if node.children.len() == 5 {
             return Ok(NodeStatement::VariableDeclarationList {
-                decl_list: parse_initialized_identifier_list(&node.children[3])?,
+                decl_list: parse_typed_identifier_list(&node.children, &node.children[3])?,
             });
         }
     }
@@ -62,7 +62,8 @@ fn parse_string_no_brace<'input>(
         || node.rule_name == "STRING_MID_MID"
         || node.rule_name == "STRING_MID_END"
     {
-        let text = node.token.clone().unwrap().str;
+        let token = node.token.clone().unwrap();
+        let text = token.str;
         let mut id_start_end_list: Vec<(usize, usize)> = vec![];
         for m in regex_non_escaped_dollar.find_iter(&text) {
             let end = m.end();
@@ -89,6 +90,7 @@ fn parse_string_no_brace<'input>(
                     identifier: Identifier {
                         value: &text[id_start..id_end],
                         kind: IdentifierKind::BuiltIn,
+                        position: token.position.after(&text[..id_start]),
                     },
                 });
             } else if OTHER_IDENTIFIER.contains(&&text[id_start..id_end]) {
@@ -96,6 +98,7 @@ fn parse_string_no_brace<'input>(
                     identifier: Identifier {
                         value: &text[id_start..id_end],
                         kind: IdentifierKind::Other,
+                        position: token.position.after(&text[..id_start]),
                     },
                 });
             } else {
@@ -103,6 +106,7 @@ fn parse_string_no_brace<'input>(
                     identifier: Identifier {
                         value: &text[id_start..id_end],
                         kind: IdentifierKind::Normal,
+                        position: token.position.after(&text[..id_start]),
                     },
                 });
             }
@@ -179,27 +183,36 @@ pub fn parse_set_or_map_literal<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<NodeExpression<'input>> {
     if node.rule_name == "SetOrMapLiteral" {
+        let is_const = node.children[0]
+            .token
+            .as_ref()
+            .is_some_and(|token| token.str == "const");
         if node.children.len() == 2 || node.children.len() == 3 {
             return Ok(NodeExpression::SetOrMapLiteral {
                 element_list: vec![],
+                is_const,
             });
         } else if node.children.len() == 4 {
             if node.children[0].token.clone().unwrap().str == "{" {
                 return Ok(NodeExpression::SetOrMapLiteral {
                     element_list: parse_element_list(&node.children[1])?,
+                    is_const,
                 });
             } else {
                 return Ok(NodeExpression::SetOrMapLiteral {
                     element_list: vec![],
+                    is_const,
                 });
             }
         } else if node.children.len() == 5 {
             return Ok(NodeExpression::SetOrMapLiteral {
                 element_list: parse_element_list(&node.children[2])?,
+                is_const,
             });
         } else {
             return Ok(NodeExpression::SetOrMapLiteral {
                 element_list: parse_element_list(&node.children[3])?,
+                is_const,
             });
         }
     }
@@ -0,0 +1,53 @@
+use anyhow::{bail, Result};
+
+use super::{
+    node::{NodeExpression, Selector},
+    node_internal::NodeInternal,
+    parse_library::parse_dotted_identifier_list,
+    parse_selector::parse_arguments,
+    util::flatten,
+};
+
+pub fn parse_metadata_list<'input>(
+    node: &NodeInternal<'input>,
+) -> Result<Vec<NodeExpression<'input>>> {
+    if node.rule_name == "MetadataList" {
+        if node.children.len() == 1 {
+            return Ok(vec![parse_metadata(&node.children[0])?]);
+        } else {
+            return flatten(
+                parse_metadata_list(&node.children[0]),
+                parse_metadata(&node.children[1])?,
+            );
+        }
+    }
+
+    bail!("Parse Error in parse_metadata_list: {}", node.rule_name);
+}
+
+// @a.b(c) → a.b(c)の式として扱う
+fn parse_metadata<'input>(node: &NodeInternal<'input>) -> Result<NodeExpression<'input>> {
+    if node.rule_name == "Metadata" {
+        let mut identifier_list = parse_dotted_identifier_list(&node.children[1])?.into_iter();
+        let mut expr = NodeExpression::Identifier {
+            identifier: identifier_list.next().unwrap(),
+        };
+        for identifier in identifier_list {
+            expr = NodeExpression::Selector {
+                child: Box::new(expr),
+                selector: Selector::Attr { identifier },
+            };
+        }
+        if node.children.len() == 3 {
+            expr = NodeExpression::Selector {
+                child: Box::new(expr),
+                selector: Selector::Args {
+                    args: parse_arguments(&node.children[2])?,
+                },
+            };
+        }
+        return Ok(expr);
+    }
+
+    bail!("Parse Error in parse_metadata: {}", node.rule_name);
+}
@@ -70,7 +70,7 @@ pub fn parse_selector<'input>(node: &NodeInternal<'input>) -> Result<Selector<'i
     bail!("Parse Error in parse_selector: {}", node.rule_name);
 }
 
-fn parse_arguments<'input>(node: &NodeInternal<'input>) -> Result<Vec<CallParameter<'input>>> {
+pub fn parse_arguments<'input>(node: &NodeInternal<'input>) -> Result<Vec<CallParameter<'input>>> {
     if node.rule_name == "Arguments" {
         if node.children.len() == 2 {
             return Ok(vec![]);
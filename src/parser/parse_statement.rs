@@ -2,7 +2,7 @@ use std::vec;
 
 use anyhow::{bail, Result};
 
-use crate::parser::parse_functions::parse_declared_identifier;
+use crate::parser::parse_functions::{parse_declared_identifier, parse_declared_type};
 
 use super::{
     node::{DefaultCase, Identifier, NodeStatement, SwitchCase, TryCatchPart, TryOnPart},
@@ -152,6 +152,7 @@ fn parse_for_statement<'input>(node: &NodeInternal<'input>) -> Result<NodeStatem
                 return Ok(NodeStatement::ForIn {
                     variable: parse_declared_identifier(&parts_node.children[0])?,
                     is_variable_declared: true,
+                    dart_type: parse_declared_type(&parts_node.children[0].children)?,
                     iterable: Box::new(parse_expression(&parts_node.children[2])?),
                     stmt: Box::new(parse_statement(&node.children[4])?),
                 });
@@ -159,6 +160,7 @@ fn parse_for_statement<'input>(node: &NodeInternal<'input>) -> Result<NodeStatem
                 return Ok(NodeStatement::ForIn {
                     variable: parse_identifier(&parts_node.children[0])?,
                     is_variable_declared: false,
+                    dart_type: None,
                     iterable: Box::new(parse_expression(&parts_node.children[2])?),
                     stmt: Box::new(parse_statement(&node.children[4])?),
                 });
@@ -1,8 +1,12 @@
 use anyhow::{bail, Result};
 
 use super::{
-    node::VariableDeclaration, node_internal::NodeInternal, parse_expression::parse_expression,
-    parse_functions::parse_declared_identifier, parse_identifier::parse_identifier, util::flatten,
+    node::VariableDeclaration,
+    node_internal::NodeInternal,
+    parse_expression::parse_expression,
+    parse_functions::{parse_declared_identifier, parse_declared_type},
+    parse_identifier::parse_identifier,
+    util::flatten,
 };
 
 pub fn parse_initialized_variable_declaration<'input>(
@@ -12,18 +16,22 @@ pub fn parse_initialized_variable_declaration<'input>(
         if node.children.len() == 1 {
             return Ok(vec![VariableDeclaration {
                 identifier: parse_declared_identifier(&node.children[0])?,
+                dart_type: parse_declared_type(&node.children[0].children)?,
                 expr: None,
             }]);
         } else if node.children[1].token.clone().unwrap().str == "=" {
             return Ok(vec![VariableDeclaration {
                 identifier: parse_declared_identifier(&node.children[0])?,
+                dart_type: parse_declared_type(&node.children[0].children)?,
                 expr: Some(Box::new(parse_expression(&node.children[2])?)),
             }]);
         } else {
-            return flatten(
-                parse_initialized_variable_declaration(&node.children[0]),
-                parse_initialized_identifier(&node.children[2])?,
-            );
+            // 2つ目以降の変数も最初の変数と同じ型を持つ
+            let mut decl_list = parse_initialized_variable_declaration(&node.children[0])?;
+            let mut decl = parse_initialized_identifier(&node.children[2])?;
+            decl.dart_type = parse_declared_type(&declared_identifier(node).children)?;
+            decl_list.push(decl);
+            return Ok(decl_list);
         }
     }
 
@@ -33,6 +41,14 @@ pub fn parse_initialized_variable_declaration<'input>(
     );
 }
 
+// InitializedVariableDeclarationの先頭のDeclaredIdentifier
+fn declared_identifier<'a, 'input>(node: &'a NodeInternal<'input>) -> &'a NodeInternal<'input> {
+    match node.children[0].rule_name.as_str() {
+        "DeclaredIdentifier" => &node.children[0],
+        _ => declared_identifier(&node.children[0]),
+    }
+}
+
 fn parse_initialized_identifier<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<VariableDeclaration<'input>> {
@@ -40,11 +56,13 @@ fn parse_initialized_identifier<'input>(
         if node.children.len() == 1 {
             return Ok(VariableDeclaration {
                 identifier: parse_identifier(&node.children[0])?,
+                dart_type: None,
                 expr: None,
             });
         } else {
             return Ok(VariableDeclaration {
                 identifier: parse_identifier(&node.children[0])?,
+                dart_type: None,
                 expr: Some(Box::new(parse_expression(&node.children[2])?)),
             });
         }
@@ -56,7 +74,20 @@ fn parse_initialized_identifier<'input>(
     );
 }
 
-pub fn parse_initialized_identifier_list<'input>(
+/// `Type a = 1, b;`のように、型の後に続く変数の宣言を読む
+/// childrenは宣言全体の子ノードで、型があれば各変数に設定する
+pub fn parse_typed_identifier_list<'input>(
+    children: &[NodeInternal<'input>],
+    node: &NodeInternal<'input>,
+) -> Result<Vec<VariableDeclaration<'input>>> {
+    let mut decl_list = parse_initialized_identifier_list(node)?;
+    for decl in &mut decl_list {
+        decl.dart_type = parse_declared_type(children)?;
+    }
+    Ok(decl_list)
+}
+
+fn parse_initialized_identifier_list<'input>(
     node: &NodeInternal<'input>,
 ) -> Result<Vec<VariableDeclaration<'input>>> {
     if node.rule_name == "InitializedIdentifierList" {
@@ -1,24 +1,47 @@
-use std::{fs::File, io::Write};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::mem;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 
 use crate::bytecode::{self, ByteCode};
 
+// marshalのTYPE_LONGは1桁15bitで表現する
+const LONG_DIGIT_BITS: u32 = 15;
+const LONG_DIGIT_MASK: u32 = (1 << LONG_DIGIT_BITS) - 1;
+
 #[allow(dead_code)]
+#[derive(Clone)]
 pub enum PyObject {
     Int(i32, bool),
+    Long {
+        digits: Vec<u16>, // 2^15進数の桁(下位から)
+        negative: bool,
+        add_ref: bool,
+    },
     Float(f64, bool),
-    String(Vec<u8>, bool), // 文字列ではなくバイト列に利用
+    Complex(f64, f64, bool), // 実部と虚部
+    String(Vec<u8>, bool),   // 文字列ではなくバイト列に利用
     Ascii(String, bool),
     AsciiShort(String, bool),
     Unicode(String, bool),
     None(bool),
     True(bool),
     False(bool),
+    Ellipsis(bool),
     SmallTuple {
         children: Vec<PyObject>,
         add_ref: bool,
     },
+    Tuple {
+        children: Vec<PyObject>,
+        add_ref: bool,
+    },
+    FrozenSet {
+        children: Vec<PyObject>,
+        add_ref: bool,
+    },
     Code {
         file_name: String,
         code_name: String,
@@ -31,31 +54,70 @@ pub enum PyObject {
         constant_list: Box<PyObject>,
         name_list: Box<PyObject>,
         local_list: Box<PyObject>,
+        first_line_number: u32,
+        line_table: Vec<u8>, // 命令のオフセットと行番号の増分の組(lnotab)
         add_ref: bool,
     },
 }
 
 impl PyObject {
     pub fn new_numeric(value: &str, add_ref: bool) -> Result<PyObject> {
+        // 桁区切りの_は無視する
+        let value = value.replace('_', "");
         if value.starts_with("0x") || value.starts_with("0X") {
             // 16進数の場合
-            let value = i32::from_str_radix(&value[2..], 16)
-                .with_context(|| format!("Failed to parse hex: {}", value))?;
-            Ok(PyObject::Int(value, add_ref))
+            PyObject::new_integer(&value[2..], 16, add_ref)
+                .with_context(|| format!("Failed to parse hex: {}", value))
+        } else if value.contains(['.', 'e', 'E']) {
+            // 小数の場合
+            let value = value
+                .parse::<f64>()
+                .with_context(|| format!("Failed to parse float: {}", value))?;
+            Ok(PyObject::Float(value, add_ref))
         } else {
-            match value.parse::<i32>() {
-                Ok(value) => {
-                    // 整数の場合
-                    Ok(PyObject::Int(value, add_ref))
-                }
-                Err(_) => {
-                    // 小数の場合
-                    let value = value
-                        .parse::<f64>()
-                        .with_context(|| format!("Failed to parse float: {}", value))?;
-                    Ok(PyObject::Float(value, add_ref))
-                }
+            // 整数の場合
+            PyObject::new_integer(&value, 10, add_ref)
+                .with_context(|| format!("Failed to parse integer: {}", value))
+        }
+    }
+
+    /// i32に収まる整数はTYPE_INT、それ以外はTYPE_LONGとして表現する
+    fn new_integer(value: &str, radix: u32, add_ref: bool) -> Result<PyObject> {
+        if let Ok(v) = i32::from_str_radix(value, radix) {
+            return Ok(PyObject::Int(v, add_ref));
+        }
+
+        // 2^15進数の桁列に変換する
+        let mut digits: Vec<u16> = vec![];
+        for c in value.chars() {
+            let d = match c.to_digit(radix) {
+                Some(d) => d,
+                None => bail!("Invalid digit: {}", c),
+            };
+            let mut carry = d;
+            for digit in digits.iter_mut() {
+                let v = (*digit as u32) * radix + carry;
+                *digit = (v & LONG_DIGIT_MASK) as u16;
+                carry = v >> LONG_DIGIT_BITS;
             }
+            while carry > 0 {
+                digits.push((carry & LONG_DIGIT_MASK) as u16);
+                carry >>= LONG_DIGIT_BITS;
+            }
+        }
+        Ok(PyObject::Long {
+            digits,
+            negative: false,
+            add_ref,
+        })
+    }
+
+    /// 要素数が256未満ならTYPE_SMALL_TUPLE、それ以外はTYPE_TUPLEとして表現する
+    pub fn new_tuple(children: Vec<PyObject>, add_ref: bool) -> PyObject {
+        if children.len() < 256 {
+            PyObject::SmallTuple { children, add_ref }
+        } else {
+            PyObject::Tuple { children, add_ref }
         }
     }
 
@@ -85,39 +147,260 @@ impl PyObject {
 }
 
 impl PyObject {
-    pub fn write(&self, file: &mut File) -> Result<()> {
-        let object_type = self.get_object_type();
-        file.write(&[object_type])?;
-        match &*self {
+    /// marshalの形式で書き出す
+    /// 2回以上現れる文字列やコードオブジェクトは、最初の1回だけFLAG_REFを付けて書き出し、
+    /// 以降はTYPE_REFで参照する
+    pub fn write(&self, file: &mut impl Write) -> Result<()> {
+        let mut counter = MarshalWriter::new(io::sink(), HashMap::new());
+        counter.write_object(self)?;
+        let mut writer = MarshalWriter::new(file, counter.counts);
+        writer.is_counting = false;
+        writer.write_object(self)
+    }
+
+    /// marshalの形式から読み込む
+    /// writeで書き出せるオブジェクトだけを扱う
+    pub fn read(input: &[u8]) -> Result<PyObject> {
+        let mut reader = MarshalReader {
+            input,
+            position: 0,
+            refs: vec![],
+        };
+        let object = reader.read_object()?;
+        ensure!(
+            reader.position == input.len(),
+            "{} bytes remain after marshal data",
+            input.len() - reader.position
+        );
+        Ok(object)
+    }
+
+    /// 文字列ならその値を返す
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PyObject::Ascii(v, _) | PyObject::AsciiShort(v, _) | PyObject::Unicode(v, _) => Some(v),
+            _ => None,
+        }
+    }
+
+    // 参照として共有できるオブジェクト
+    fn can_ref(&self) -> bool {
+        matches!(
+            self,
+            PyObject::Long { .. }
+                | PyObject::Float(..)
+                | PyObject::Complex(..)
+                | PyObject::String(..)
+                | PyObject::Ascii(..)
+                | PyObject::AsciiShort(..)
+                | PyObject::Unicode(..)
+                | PyObject::SmallTuple { .. }
+                | PyObject::Tuple { .. }
+                | PyObject::FrozenSet { .. }
+                | PyObject::Code { .. }
+        )
+    }
+
+    fn add_ref(&self) -> bool {
+        match *self {
+            PyObject::Int(_, r)
+            | PyObject::Float(_, r)
+            | PyObject::String(_, r)
+            | PyObject::Ascii(_, r)
+            | PyObject::AsciiShort(_, r)
+            | PyObject::Unicode(_, r)
+            | PyObject::None(r)
+            | PyObject::True(r)
+            | PyObject::False(r)
+            | PyObject::Ellipsis(r)
+            | PyObject::Complex(_, _, r) => r,
+            PyObject::Long { add_ref, .. }
+            | PyObject::SmallTuple { add_ref, .. }
+            | PyObject::Tuple { add_ref, .. }
+            | PyObject::FrozenSet { add_ref, .. }
+            | PyObject::Code { add_ref, .. } => add_ref,
+        }
+    }
+
+    fn get_object_type(&self) -> u8 {
+        match *self {
+            PyObject::Int(_, r) => 0x69 | ((r as u8) << 7),
+            PyObject::Long {
+                digits: _,
+                negative: _,
+                add_ref,
+            } => 0x6C | ((add_ref as u8) << 7),
+            PyObject::Float(_, r) => 0x67 | ((r as u8) << 7),
+            PyObject::Complex(_, _, r) => 0x79 | ((r as u8) << 7),
+            PyObject::String(_, r) => 0x73 | ((r as u8) << 7),
+            PyObject::Ascii(_, r) => 0x61 | ((r as u8) << 7),
+            PyObject::AsciiShort(_, r) => 0x7A | ((r as u8) << 7),
+            PyObject::Unicode(_, r) => 0x75 | ((r as u8) << 7),
+            PyObject::None(r) => 0x4E | ((r as u8) << 7),
+            PyObject::True(r) => 0x54 | ((r as u8) << 7),
+            PyObject::False(r) => 0x46 | ((r as u8) << 7),
+            PyObject::Ellipsis(r) => 0x2E | ((r as u8) << 7),
+            PyObject::SmallTuple {
+                children: _,
+                add_ref,
+            } => 0x29 | ((add_ref as u8) << 7),
+            PyObject::Tuple {
+                children: _,
+                add_ref,
+            } => 0x28 | ((add_ref as u8) << 7),
+            PyObject::FrozenSet {
+                children: _,
+                add_ref,
+            } => 0x3E | ((add_ref as u8) << 7),
+            PyObject::Code {
+                file_name: _,
+                code_name: _,
+                num_args: _,
+                num_pos_only_args: _,
+                num_kw_only_args: _,
+                num_locals: _,
+                stack_size: _,
+                operation_list: _,
+                constant_list: _,
+                name_list: _,
+                local_list: _,
+                first_line_number: _,
+                line_table: _,
+                add_ref,
+            } => 0x63 | ((add_ref as u8) << 7),
+        }
+    }
+}
+
+const FLAG_REF: u8 = 0x80;
+const TYPE_REF: u8 = 0x72;
+
+// marshalの書き出し
+// 1回目は出現回数を数えるだけで、2回目に参照表を作りながら書き出す
+struct MarshalWriter<W: Write> {
+    out: W,
+    counts: HashMap<PyObject, usize>,
+    refs: HashMap<PyObject, u32>,
+    // FLAG_REFを付けて書き出したオブジェクトの数
+    ref_len: u32,
+    is_counting: bool,
+}
+
+impl<W: Write> MarshalWriter<W> {
+    fn new(out: W, counts: HashMap<PyObject, usize>) -> Self {
+        MarshalWriter {
+            out,
+            counts,
+            refs: HashMap::new(),
+            ref_len: 0,
+            is_counting: true,
+        }
+    }
+
+    fn write_object(&mut self, object: &PyObject) -> Result<()> {
+        let can_ref = object.can_ref();
+        if can_ref && self.is_counting {
+            let count = self.counts.entry(object.clone()).or_insert(0);
+            *count += 1;
+            // 2回目以降は参照になるので中身は数えない
+            if *count > 1 {
+                return Ok(());
+            }
+        }
+        if can_ref && !self.is_counting {
+            if let Some(index) = self.refs.get(object) {
+                self.out.write_all(&[TYPE_REF])?;
+                self.out.write_all(&index.to_le_bytes())?;
+                return Ok(());
+            }
+        }
+
+        let is_shared = can_ref && !self.is_counting && self.counts.get(object) > Some(&1);
+        let flag = if object.add_ref() || is_shared {
+            // 参照の番号は中身より先に割り当てられる
+            if is_shared {
+                self.refs.insert(object.clone(), self.ref_len);
+            }
+            self.ref_len += 1;
+            FLAG_REF
+        } else {
+            0
+        };
+        self.out.write_all(&[object.get_object_type() | flag])?;
+        self.write_body(object)
+    }
+
+    fn write_body(&mut self, object: &PyObject) -> Result<()> {
+        match object {
             PyObject::Int(v, _) => {
-                file.write(&(v.to_le_bytes()))?;
+                self.out.write_all(&(v.to_le_bytes()))?;
+            }
+            PyObject::Long {
+                digits,
+                negative,
+                add_ref: _,
+            } => {
+                // 桁数の符号が値の符号を表す
+                let size = if *negative {
+                    -(digits.len() as i32)
+                } else {
+                    digits.len() as i32
+                };
+                self.out.write_all(&(size.to_le_bytes()))?;
+                for digit in digits {
+                    self.out.write_all(&(digit.to_le_bytes()))?;
+                }
             }
             PyObject::Float(v, _) => {
-                file.write(&(v.to_le_bytes()))?;
+                self.out.write_all(&(v.to_le_bytes()))?;
+            }
+            PyObject::Complex(real, imag, _) => {
+                self.out.write_all(&(real.to_le_bytes()))?;
+                self.out.write_all(&(imag.to_le_bytes()))?;
             }
             PyObject::String(v, _) => {
                 let str_len = v.len() as u32;
-                file.write(&str_len.to_le_bytes())?;
-                file.write(v)?;
+                self.out.write_all(&str_len.to_le_bytes())?;
+                self.out.write_all(v)?;
             }
             PyObject::Ascii(v, _) | PyObject::Unicode(v, _) => {
                 let str_len = v.len() as u32;
-                file.write(&str_len.to_le_bytes())?;
-                file.write(v.as_bytes())?;
+                self.out.write_all(&str_len.to_le_bytes())?;
+                self.out.write_all(v.as_bytes())?;
             }
             PyObject::AsciiShort(v, _) => {
+                ensure!(v.len() < 256, "Too long short string: {} bytes", v.len());
                 let str_len = v.len() as u8;
-                file.write(&[str_len])?;
-                file.write(v.as_bytes())?;
+                self.out.write_all(&[str_len])?;
+                self.out.write_all(v.as_bytes())?;
             }
             PyObject::SmallTuple {
                 children,
                 add_ref: _,
             } => {
+                ensure!(
+                    children.len() < 256,
+                    "Too many elements in small tuple: {}",
+                    children.len()
+                );
                 let tuple_len = children.len() as u8;
-                file.write(&[tuple_len])?;
+                self.out.write_all(&[tuple_len])?;
+                for child in children {
+                    self.write_object(child)?;
+                }
+            }
+            PyObject::Tuple {
+                children,
+                add_ref: _,
+            }
+            | PyObject::FrozenSet {
+                children,
+                add_ref: _,
+            } => {
+                let len = children.len() as u32;
+                self.out.write_all(&len.to_le_bytes())?;
                 for child in children {
-                    child.write(file)?;
+                    self.write_object(child)?;
                 }
             }
             PyObject::Code {
@@ -132,89 +415,535 @@ impl PyObject {
                 constant_list,
                 name_list,
                 local_list,
+                first_line_number,
+                line_table,
                 add_ref: _,
             } => {
-                file.write(&(num_args.to_le_bytes()))?; // ArgCount
-                file.write(&(num_pos_only_args.to_le_bytes()))?; // PosOnlyArgCount
-                file.write(&(num_kw_only_args.to_le_bytes()))?; // KwOnlyArgCount
-                file.write(&(num_locals.to_le_bytes()))?; // NumLocals
-                file.write(&(*stack_size as u32).to_le_bytes())?; // StackSize
-                file.write(&(64u32.to_le_bytes()))?; // Flags
+                self.out.write_all(&(num_args.to_le_bytes()))?; // ArgCount
+                self.out.write_all(&(num_pos_only_args.to_le_bytes()))?; // PosOnlyArgCount
+                self.out.write_all(&(num_kw_only_args.to_le_bytes()))?; // KwOnlyArgCount
+                self.out.write_all(&(num_locals.to_le_bytes()))?; // NumLocals
+                self.out.write_all(&(stack_size.to_le_bytes()))?; // StackSize
+                self.out.write_all(&(64u32.to_le_bytes()))?; // Flags
 
                 // コードをコンパイルして格納
-                let codes = bytecode::compile_code(&operation_list);
-                PyObject::new_bytes(codes, false).write(file)?;
+                let codes = bytecode::compile_code(operation_list);
+                self.write_object(&PyObject::new_bytes(codes, false))?;
 
                 // 定数一覧
-                constant_list.write(file)?;
+                self.write_object(constant_list)?;
 
                 // 名前一覧
-                name_list.write(file)?;
+                self.write_object(name_list)?;
 
                 // ローカル変数一覧
-                local_list.write(file)?;
+                self.write_object(local_list)?;
 
                 // 自由変数
-                PyObject::SmallTuple {
+                self.write_object(&PyObject::SmallTuple {
                     children: vec![],
                     add_ref: false,
-                }
-                .write(file)?;
+                })?;
 
                 // セル変数
-                PyObject::SmallTuple {
+                self.write_object(&PyObject::SmallTuple {
                     children: vec![],
                     add_ref: false,
-                }
-                .write(file)?;
+                })?;
 
                 // ファイル名
-                PyObject::new_string(file_name.to_string(), true).write(file)?;
+                self.write_object(&PyObject::new_string(file_name.to_string(), false))?;
 
                 // 名前
-                PyObject::new_string(code_name.to_string(), true).write(file)?;
+                self.write_object(&PyObject::new_string(code_name.to_string(), false))?;
 
-                // first line
-                file.write(&(1u32).to_le_bytes())?;
+                // 最初の行番号
+                self.out.write_all(&(first_line_number.to_le_bytes()))?;
 
-                // line table
-                // StackTraceに使われるが、仕様が不明なので0埋め
-                PyObject::new_bytes((0u32).to_le_bytes().to_vec(), true).write(file)?;
+                // 行番号表
+                self.write_object(&PyObject::new_bytes(line_table.clone(), false))?;
             }
-            PyObject::None(_) | PyObject::True(_) | PyObject::False(_) => (),
+            PyObject::None(_) | PyObject::True(_) | PyObject::False(_) | PyObject::Ellipsis(_) => {}
         };
         Ok(())
     }
+}
 
-    fn get_object_type(&self) -> u8 {
-        match *self {
-            PyObject::Int(_, r) => 0x69 | ((r as u8) << 7),
-            PyObject::Float(_, r) => 0x67 | ((r as u8) << 7),
-            PyObject::String(_, r) => 0x73 | ((r as u8) << 7),
-            PyObject::Ascii(_, r) => 0x61 | ((r as u8) << 7),
-            PyObject::AsciiShort(_, r) => 0x7A | ((r as u8) << 7),
-            PyObject::Unicode(_, r) => 0x75 | ((r as u8) << 7),
-            PyObject::None(r) => 0x4E | ((r as u8) << 7),
-            PyObject::True(r) => 0x54 | ((r as u8) << 7),
-            PyObject::False(r) => 0x46 | ((r as u8) << 7),
-            PyObject::SmallTuple {
-                children: _,
-                add_ref,
-            } => 0x29 | ((add_ref as u8) << 7),
+// marshalの読み込み
+struct MarshalReader<'a> {
+    input: &'a [u8],
+    position: usize,
+    // FLAG_REFの付いたオブジェクト(読み込み中のものはNone)
+    refs: Vec<Option<PyObject>>,
+}
+
+impl<'a> MarshalReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.position + len <= self.input.len(),
+            "unexpected end of marshal data at {}",
+            self.position
+        );
+        let bytes = &self.input[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into()?))
+    }
+
+    fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
+
+    fn read_string(&mut self, len: usize) -> Result<String> {
+        let position = self.position;
+        String::from_utf8(self.read_bytes(len)?.to_vec())
+            .with_context(|| format!("invalid utf-8 string at {}", position))
+    }
+
+    fn read_children(&mut self, len: usize) -> Result<Vec<PyObject>> {
+        (0..len).map(|_| self.read_object()).collect()
+    }
+
+    fn read_object(&mut self) -> Result<PyObject> {
+        let position = self.position;
+        let code = self.read_u8()?;
+        let add_ref = code & FLAG_REF != 0;
+        let object_type = code & !FLAG_REF;
+        if object_type == TYPE_REF {
+            let index = self.read_u32()? as usize;
+            match self.refs.get(index) {
+                Some(Some(object)) => return Ok(object.clone()),
+                _ => bail!("invalid reference {} at {}", index, position),
+            }
+        }
+
+        // 参照の番号は中身より先に割り当てられる
+        let ref_index = if add_ref {
+            self.refs.push(None);
+            Some(self.refs.len() - 1)
+        } else {
+            None
+        };
+        let object = match object_type {
+            0x69 => PyObject::Int(self.read_u32()? as i32, add_ref),
+            0x6C => {
+                let size = self.read_u32()? as i32;
+                let digits = (0..size.unsigned_abs())
+                    .map(|_| Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into()?)))
+                    .collect::<Result<Vec<u16>>>()?;
+                PyObject::Long {
+                    digits,
+                    negative: size < 0,
+                    add_ref,
+                }
+            }
+            0x67 => PyObject::Float(self.read_f64()?, add_ref),
+            0x79 => PyObject::Complex(self.read_f64()?, self.read_f64()?, add_ref),
+            0x73 => {
+                let len = self.read_u32()? as usize;
+                PyObject::String(self.read_bytes(len)?.to_vec(), add_ref)
+            }
+            0x61 => {
+                let len = self.read_u32()? as usize;
+                PyObject::Ascii(self.read_string(len)?, add_ref)
+            }
+            0x75 => {
+                let len = self.read_u32()? as usize;
+                PyObject::Unicode(self.read_string(len)?, add_ref)
+            }
+            0x7A => {
+                let len = self.read_u8()? as usize;
+                PyObject::AsciiShort(self.read_string(len)?, add_ref)
+            }
+            0x4E => PyObject::None(add_ref),
+            0x54 => PyObject::True(add_ref),
+            0x46 => PyObject::False(add_ref),
+            0x2E => PyObject::Ellipsis(add_ref),
+            0x29 => {
+                let len = self.read_u8()? as usize;
+                PyObject::SmallTuple {
+                    children: self.read_children(len)?,
+                    add_ref,
+                }
+            }
+            0x28 => {
+                let len = self.read_u32()? as usize;
+                PyObject::Tuple {
+                    children: self.read_children(len)?,
+                    add_ref,
+                }
+            }
+            0x3E => {
+                let len = self.read_u32()? as usize;
+                PyObject::FrozenSet {
+                    children: self.read_children(len)?,
+                    add_ref,
+                }
+            }
+            0x63 => self.read_code(add_ref)?,
+            _ => bail!(
+                "unsupported marshal type {:#04x} at {}",
+                object_type,
+                position
+            ),
+        };
+        if let Some(index) = ref_index {
+            self.refs[index] = Some(object.clone());
+        }
+        Ok(object)
+    }
+
+    fn read_code(&mut self, add_ref: bool) -> Result<PyObject> {
+        let num_args = self.read_u32()?;
+        let num_pos_only_args = self.read_u32()?;
+        let num_kw_only_args = self.read_u32()?;
+        let num_locals = self.read_u32()?;
+        let stack_size = self.read_u32()?;
+        let _flags = self.read_u32()?;
+
+        let operation_list = match self.read_object()? {
+            PyObject::String(codes, _) => bytecode::decode_code(&codes)?,
+            _ => bail!("code must be bytes"),
+        };
+        let constant_list = Box::new(self.read_object()?);
+        let name_list = Box::new(self.read_object()?);
+        let local_list = Box::new(self.read_object()?);
+
+        // 自由変数とセル変数は生成しないので空でなければならない
+        for kind in ["free", "cell"] {
+            match self.read_object()? {
+                PyObject::SmallTuple { children, .. } if children.is_empty() => (),
+                _ => bail!("{} variables are not supported", kind),
+            }
+        }
+
+        let file_name = self.read_object()?;
+        let code_name = self.read_object()?;
+        let (Some(file_name), Some(code_name)) = (file_name.as_str(), code_name.as_str()) else {
+            bail!("file name and code name must be strings");
+        };
+        let first_line_number = self.read_u32()?;
+        let line_table = match self.read_object()? {
+            PyObject::String(v, _) => v,
+            _ => bail!("line table must be bytes"),
+        };
+
+        Ok(PyObject::Code {
+            file_name: file_name.to_string(),
+            code_name: code_name.to_string(),
+            num_args,
+            num_pos_only_args,
+            num_kw_only_args,
+            num_locals,
+            stack_size,
+            operation_list,
+            constant_list,
+            name_list,
+            local_list,
+            first_line_number,
+            line_table,
+            add_ref,
+        })
+    }
+}
+
+// 定数表の重複を除くための比較
+// add_refは値に含めず、浮動小数点数はビット列で比べるので0.0と-0.0は区別される
+impl PartialEq for PyObject {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PyObject::Int(a, _), PyObject::Int(b, _)) => a == b,
+            (
+                PyObject::Long {
+                    digits: a,
+                    negative: a_negative,
+                    ..
+                },
+                PyObject::Long {
+                    digits: b,
+                    negative: b_negative,
+                    ..
+                },
+            ) => a == b && a_negative == b_negative,
+            (PyObject::Float(a, _), PyObject::Float(b, _)) => a.to_bits() == b.to_bits(),
+            (PyObject::Complex(a_real, a_imag, _), PyObject::Complex(b_real, b_imag, _)) => {
+                a_real.to_bits() == b_real.to_bits() && a_imag.to_bits() == b_imag.to_bits()
+            }
+            (PyObject::String(a, _), PyObject::String(b, _)) => a == b,
+            (PyObject::Ascii(a, _), PyObject::Ascii(b, _))
+            | (PyObject::AsciiShort(a, _), PyObject::AsciiShort(b, _))
+            | (PyObject::Unicode(a, _), PyObject::Unicode(b, _)) => a == b,
+            (PyObject::None(_), PyObject::None(_))
+            | (PyObject::True(_), PyObject::True(_))
+            | (PyObject::False(_), PyObject::False(_))
+            | (PyObject::Ellipsis(_), PyObject::Ellipsis(_)) => true,
+            (
+                PyObject::SmallTuple { children: a, .. },
+                PyObject::SmallTuple { children: b, .. },
+            )
+            | (PyObject::Tuple { children: a, .. }, PyObject::Tuple { children: b, .. })
+            | (PyObject::FrozenSet { children: a, .. }, PyObject::FrozenSet { children: b, .. }) => {
+                a == b
+            }
+            (a @ PyObject::Code { .. }, b @ PyObject::Code { .. }) => a.code_key() == b.code_key(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for PyObject {}
+
+impl Hash for PyObject {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            PyObject::Int(v, _) => v.hash(state),
+            PyObject::Long {
+                digits, negative, ..
+            } => {
+                digits.hash(state);
+                negative.hash(state);
+            }
+            PyObject::Float(v, _) => v.to_bits().hash(state),
+            PyObject::Complex(real, imag, _) => {
+                real.to_bits().hash(state);
+                imag.to_bits().hash(state);
+            }
+            PyObject::String(v, _) => v.hash(state),
+            PyObject::Ascii(v, _) | PyObject::AsciiShort(v, _) | PyObject::Unicode(v, _) => {
+                v.hash(state)
+            }
+            PyObject::None(_) | PyObject::True(_) | PyObject::False(_) | PyObject::Ellipsis(_) => {}
+            PyObject::SmallTuple { children, .. }
+            | PyObject::Tuple { children, .. }
+            | PyObject::FrozenSet { children, .. } => children.hash(state),
+            PyObject::Code { .. } => self.code_key().hash(state),
+        }
+    }
+}
+
+// コードオブジェクトのadd_ref以外のフィールド
+type CodeKey<'a> = (
+    (&'a String, &'a String),
+    (u32, u32, u32, u32, u32),
+    &'a Vec<ByteCode>,
+    (&'a PyObject, &'a PyObject, &'a PyObject),
+    (u32, &'a Vec<u8>),
+);
+
+impl PyObject {
+    fn code_key(&self) -> Option<CodeKey<'_>> {
+        match self {
             PyObject::Code {
-                file_name: _,
-                code_name: _,
-                num_args: _,
-                num_pos_only_args: _,
-                num_kw_only_args: _,
-                num_locals: _,
-                stack_size: _,
-                operation_list: _,
-                constant_list: _,
-                name_list: _,
-                local_list: _,
+                file_name,
+                code_name,
+                num_args,
+                num_pos_only_args,
+                num_kw_only_args,
+                num_locals,
+                stack_size,
+                operation_list,
+                constant_list,
+                name_list,
+                local_list,
+                first_line_number,
+                line_table,
+                add_ref: _,
+            } => Some((
+                (file_name, code_name),
+                (
+                    *num_args,
+                    *num_pos_only_args,
+                    *num_kw_only_args,
+                    *num_locals,
+                    *stack_size,
+                ),
+                operation_list,
+                (constant_list, name_list, local_list),
+                (*first_line_number, line_table),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executioncontext::intern_constant;
+
+    #[test]
+    fn intern_distinct_constants() {
+        let mut constant_list = vec![];
+        let values = [
+            PyObject::Int(1, false),
+            PyObject::Float(1.0, false),
+            PyObject::True(false),
+            PyObject::Float(0.0, false),
+            PyObject::Float(-0.0, false),
+        ];
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(intern_constant(&mut constant_list, value.clone()), i as u32);
+        }
+        // 同じ値は同じ位置を返す
+        assert_eq!(
+            intern_constant(&mut constant_list, PyObject::Int(1, true)),
+            0
+        );
+        assert_eq!(
+            intern_constant(&mut constant_list, PyObject::Float(-0.0, false)),
+            4
+        );
+        assert_eq!(constant_list.len(), values.len());
+    }
+
+    #[test]
+    fn write_back_reference() -> Result<()> {
+        let tuple = PyObject::SmallTuple {
+            children: vec![
+                PyObject::new_string("abc".to_string(), false),
+                PyObject::new_string("abc".to_string(), false),
+                PyObject::new_string("d".to_string(), false),
+            ],
+            add_ref: false,
+        };
+        let mut out = vec![];
+        tuple.write(&mut out)?;
+        assert_eq!(
+            out,
+            [
+                0x29, 3, // タプル
+                0xFA, 3, b'a', b'b', b'c', // FLAG_REF付きの文字列
+                0x72, 0, 0, 0, 0, // 参照
+                0x7A, 1, b'd',
+            ]
+        );
+        Ok(())
+    }
+
+    // 再現性のある疑似乱数(xorshift)
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn random_object(random: &mut Random, depth: u32) -> PyObject {
+        let strings = ["", "a", "abc", "名前", "x\ny"];
+        let kind = if depth == 0 {
+            random.next(10)
+        } else {
+            random.next(13)
+        };
+        let add_ref = random.next(4) == 0;
+        let string = strings[random.next(strings.len() as u64) as usize].to_string();
+        match kind {
+            0 => PyObject::Int(random.next(1000) as i32 - 500, add_ref),
+            1 => {
+                PyObject::new_numeric(&"9".repeat(random.next(40) as usize + 10), add_ref).unwrap()
+            }
+            2 => PyObject::Float(random.next(1000) as f64 / 8.0 - 60.0, add_ref),
+            3 => PyObject::Complex(random.next(10) as f64, -0.5, add_ref),
+            4 => PyObject::new_string(string, add_ref),
+            5 => PyObject::Ascii("s".repeat(random.next(300) as usize), add_ref),
+            6 => PyObject::new_bytes(string.into_bytes(), add_ref),
+            7 => PyObject::None(add_ref),
+            8 => {
+                [PyObject::True(add_ref), PyObject::False(add_ref)][random.next(2) as usize].clone()
+            }
+            9 => PyObject::Ellipsis(add_ref),
+            10 => {
+                let len = random.next(5) as usize;
+                let children = (0..len).map(|_| random_object(random, depth - 1)).collect();
+                PyObject::new_tuple(children, add_ref)
+            }
+            11 => PyObject::FrozenSet {
+                children: (0..random.next(4))
+                    .map(|_| random_object(random, 0))
+                    .collect(),
                 add_ref,
-            } => 0x63 | ((add_ref as u8) << 7),
+            },
+            _ => PyObject::Code {
+                file_name: "main.py".to_string(),
+                code_name: "f".to_string(),
+                num_args: random.next(3) as u32,
+                num_pos_only_args: 0,
+                num_kw_only_args: 0,
+                num_locals: 0,
+                stack_size: random.next(10) as u32,
+                operation_list: bytecode::decode_code(&[100, 0, 83, 0]).unwrap(),
+                constant_list: Box::new(random_object(random, depth - 1)),
+                name_list: Box::new(PyObject::new_tuple(vec![], false)),
+                local_list: Box::new(PyObject::new_tuple(vec![], false)),
+                first_line_number: random.next(100) as u32,
+                line_table: vec![2, 1],
+                add_ref,
+            },
+        }
+    }
+
+    #[test]
+    fn write_and_read_roundtrip() -> Result<()> {
+        let mut random = Random(0x2545F4914F6CDD1D);
+        for _ in 0..500 {
+            let object = random_object(&mut random, 3);
+            let mut out = vec![];
+            object.write(&mut out)?;
+            let read = PyObject::read(&out)?;
+            assert!(read == object);
+
+            // 読み込んだオブジェクトを書き出すと同じバイト列になる
+            let mut rewritten = vec![];
+            read.write(&mut rewritten)?;
+            assert_eq!(rewritten, out);
         }
+        Ok(())
+    }
+
+    #[test]
+    fn reject_broken_marshal() {
+        assert!(PyObject::read(&[0x72, 0, 0, 0, 0]).is_err());
+        assert!(PyObject::read(&[0x7A, 3, b'a']).is_err());
+        assert!(PyObject::read(&[0x4E, 0x4E]).is_err());
+        assert!(PyObject::read(&[0x3C, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn write_large_containers() -> Result<()> {
+        let children: Vec<PyObject> = (0..300).map(|v| PyObject::Int(v, false)).collect();
+        let tuple = PyObject::new_tuple(children.clone(), false);
+        assert!(matches!(tuple, PyObject::Tuple { .. }));
+        let mut out = vec![];
+        tuple.write(&mut out)?;
+        assert_eq!(out[..5], [0x28, 44, 1, 0, 0]);
+        assert_eq!(out.len(), 5 + 300 * 5);
+
+        let set = PyObject::FrozenSet {
+            children: vec![
+                PyObject::Complex(1.0, -2.0, false),
+                PyObject::Ellipsis(false),
+            ],
+            add_ref: false,
+        };
+        let mut out = vec![];
+        set.write(&mut out)?;
+        let mut expected = vec![0x3E, 2, 0, 0, 0, 0x79];
+        expected.extend(1.0f64.to_le_bytes());
+        expected.extend((-2.0f64).to_le_bytes());
+        expected.push(0x2E);
+        assert_eq!(out, expected);
+        Ok(())
     }
 }
@@ -0,0 +1,310 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+
+use crate::library::scan_top_level_names;
+use crate::parser::node::{DartType, Identifier, Member, NodeStatement};
+use crate::runtime;
+use crate::tokenizer::Position;
+
+/// elaphe/core.d.dartで宣言されている組み込みの名前
+const CORE_DECLARATION: &str = include_str!("../template/elaphe/core.d.dart");
+
+// クラスのスーパークラスの名前と、そのクラスで宣言されたインスタンス変数
+type ClassFields = (Option<String>, Vec<String>);
+
+/// コンパイル時の名前解決
+/// 参照された識別子がどこかで宣言されているかを確かめる
+pub struct Resolver {
+    // トップレベルで参照できる名前
+    names: RefCell<HashSet<String>>,
+    // 公開している名前が分からないライブラリをインポートしている
+    // この場合は未定義の名前を報告しない
+    is_open: Cell<bool>,
+    // このファイルで宣言された抽象クラス
+    abstract_classes: RefCell<HashSet<String>>,
+    // このファイルで宣言されたクラス
+    class_fields: RefCell<HashMap<String, ClassFields>>,
+    errors: RefCell<Vec<String>>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        let resolver = Resolver {
+            names: RefCell::new(HashSet::new()),
+            is_open: Cell::new(false),
+            abstract_classes: RefCell::new(HashSet::new()),
+            class_fields: RefCell::new(HashMap::new()),
+            errors: RefCell::new(vec![]),
+        };
+        resolver.declare_all(scan_top_level_names(CORE_DECLARATION).unwrap_or_default());
+        resolver.declare_all(
+            runtime::CORE_EXPORTS
+                .iter()
+                .map(|(dart_name, _)| *dart_name),
+        );
+        resolver
+    }
+
+    pub fn declare(&self, name: &str) {
+        self.names.borrow_mut().insert(name.to_string());
+    }
+
+    pub fn declare_all<I, S>(&self, names: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut table = self.names.borrow_mut();
+        for name in names {
+            table.insert(name.as_ref().to_string());
+        }
+    }
+
+    pub fn open(&self) {
+        self.is_open.set(true);
+    }
+
+    /// トップレベルの名前として解決できなければエラーとして記録する
+    pub fn resolve(&self, identifier: &Identifier) {
+        let name = identifier.value;
+        if self.is_open.get() || self.names.borrow().contains(name) {
+            return;
+        }
+        self.push_error(format!("undefined name '{}'", name), identifier.position);
+    }
+
+    /// 抽象クラスのインスタンスを作ろうとしていればエラーとして記録する
+    pub fn check_instantiation(&self, identifier: &Identifier) {
+        if self.abstract_classes.borrow().contains(identifier.value) {
+            self.push_error(
+                format!(
+                    "abstract class '{}' cannot be instantiated",
+                    identifier.value
+                ),
+                identifier.position,
+            );
+        }
+    }
+
+    /// 抽象クラスを登録し、抽象クラスでないクラスが
+    /// 継承した抽象メソッドをすべて実装しているか確かめる
+    /// super(...)を書かずに、引数の必要なスーパークラスのコンストラクタを呼んでいないかも確かめる
+    /// 同じファイルで宣言されたクラスの継承関係だけを調べる
+    pub fn check_classes<'a>(&self, class_list: &[&'a NodeStatement<'a>]) {
+        let mut class_map: HashMap<&str, ClassSummary> = HashMap::new();
+        // エラーの順序が変わらないよう、宣言順に調べる
+        let mut class_names: Vec<&str> = vec![];
+        for class in class_list {
+            if let Some(summary) = ClassSummary::new(class) {
+                if summary.is_abstract {
+                    self.abstract_classes
+                        .borrow_mut()
+                        .insert(summary.identifier.value.to_string());
+                }
+                self.class_fields.borrow_mut().insert(
+                    summary.identifier.value.to_string(),
+                    (
+                        summary.superclass.map(|v| v.to_string()),
+                        summary.fields.iter().map(|v| v.to_string()).collect(),
+                    ),
+                );
+                class_names.push(summary.identifier.value);
+                class_map.insert(summary.identifier.value, summary);
+            }
+        }
+
+        let summaries = || class_names.iter().filter_map(|v| class_map.get(v));
+        for summary in summaries().filter(|v| !v.calls_super) {
+            let Some(superclass) = summary.superclass.and_then(|v| class_map.get(v)) else {
+                continue;
+            };
+            if superclass.required_arguments > 0 {
+                self.push_error(
+                    format!(
+                        "the superclass '{}' of '{}' has no zero-argument constructor; call it with ': super(...)'",
+                        superclass.identifier.value, summary.identifier.value
+                    ),
+                    summary.identifier.position,
+                );
+            }
+        }
+
+        for summary in summaries().filter(|v| !v.is_abstract) {
+            let mut abstract_members: Vec<&str> = vec![];
+            let mut concrete_members: HashSet<&str> = HashSet::new();
+            let mut visited: HashSet<&str> = HashSet::new();
+            let mut current = Some(summary);
+            while let Some(class) = current {
+                if !visited.insert(class.identifier.value) {
+                    break;
+                }
+                abstract_members.extend(&class.abstract_members);
+                concrete_members.extend(&class.concrete_members);
+                concrete_members.extend(&class.fields);
+                current = class.superclass.and_then(|v| class_map.get(v));
+            }
+            let mut missing: Vec<&str> = vec![];
+            for member in abstract_members {
+                if !concrete_members.contains(member) && !missing.contains(&member) {
+                    missing.push(member);
+                }
+            }
+            for member in missing {
+                self.push_error(
+                    format!(
+                        "missing concrete implementation of '{}' in non-abstract class '{}'",
+                        member, summary.identifier.value
+                    ),
+                    summary.identifier.position,
+                );
+            }
+        }
+    }
+
+    /// クラスとそのスーパークラスで宣言されたインスタンス変数を返す
+    pub fn inherited_fields(&self, class_name: &str) -> Vec<String> {
+        let class_fields = self.class_fields.borrow();
+        let mut fields: Vec<String> = vec![];
+        let mut visited: HashSet<&str> = HashSet::new();
+        let mut current = Some(class_name);
+        while let Some(name) = current {
+            let Some((superclass, own_fields)) = class_fields.get(name) else {
+                break;
+            };
+            if !visited.insert(name) {
+                break;
+            }
+            fields.extend(own_fields.iter().cloned());
+            current = superclass.as_deref();
+        }
+        fields
+    }
+
+    // 識別子の行と列を付けて記録する
+    fn push_error(&self, message: String, position: Position) {
+        self.errors.borrow_mut().push(format!(
+            "{}:{}: {}",
+            position.line, position.column, message
+        ));
+    }
+
+    /// 記録されたエラーをまとめて返す
+    pub fn finish(&self) -> Result<()> {
+        let errors = self.errors.borrow();
+        if !errors.is_empty() {
+            bail!("{}", errors.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+// 抽象メソッドの実装を確かめるために必要なクラスの情報
+struct ClassSummary<'a> {
+    identifier: &'a Identifier<'a>,
+    is_abstract: bool,
+    superclass: Option<&'a str>,
+    abstract_members: Vec<&'a str>,
+    concrete_members: Vec<&'a str>,
+    fields: Vec<&'a str>,
+    // コンストラクタの必須の引数の数
+    required_arguments: usize,
+    // コンストラクタの初期化リストでsuper(...)を呼んでいる
+    calls_super: bool,
+}
+
+impl<'a> ClassSummary<'a> {
+    fn new(node: &'a NodeStatement<'a>) -> Option<Self> {
+        let NodeStatement::ClassDeclaration {
+            identifier,
+            member_list,
+            is_abstract,
+            superclass,
+            ..
+        } = node
+        else {
+            return None;
+        };
+        let mut summary = ClassSummary {
+            identifier,
+            is_abstract: *is_abstract,
+            superclass: match superclass {
+                Some(DartType::Named { type_name, .. }) => Some(type_name.identifier.value),
+                _ => None,
+            },
+            abstract_members: vec![],
+            concrete_members: vec![],
+            fields: vec![],
+            required_arguments: 0,
+            calls_super: false,
+        };
+        for member in member_list {
+            match member {
+                Member::AbstractMethod { signature, .. } => {
+                    summary.abstract_members.push(signature.name.value)
+                }
+                // クラスと同じ名前のメソッドはコンストラクタ
+                Member::MethodImpl { signature, .. } => {
+                    if signature.name.value == identifier.value {
+                        summary.required_arguments = signature.param.normal_list.len();
+                    } else {
+                        summary.concrete_members.push(signature.name.value)
+                    }
+                }
+                Member::ConstructorImpl {
+                    signature,
+                    super_arguments,
+                    ..
+                } => {
+                    summary.required_arguments = signature.param.normal_list.len();
+                    summary.calls_super = super_arguments.is_some();
+                }
+                Member::GetterImpl { signature, .. } => {
+                    summary.concrete_members.push(signature.name.value)
+                }
+                Member::VariableDecl { decl_list } => {
+                    summary
+                        .fields
+                        .extend(decl_list.iter().map(|v| v.identifier.value));
+                }
+            }
+        }
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::parser::node::{Identifier, IdentifierKind};
+    use crate::tokenizer::{tokenize, TokenKind};
+
+    #[test]
+    fn report_undefined_names() {
+        let tokens = tokenize("main() {\n  pritn(1);\n  print('é', x);\n}").unwrap();
+        let identifiers: Vec<Identifier> = tokens
+            .iter()
+            .filter(|v| v.kind == TokenKind::Identifier)
+            .map(|v| Identifier {
+                value: v.str,
+                kind: IdentifierKind::Normal,
+                position: v.position,
+            })
+            .collect();
+        let resolver = Resolver::new();
+        resolver.declare("main");
+        for identifier in &identifiers {
+            resolver.resolve(identifier);
+        }
+        assert_eq!(
+            resolver.finish().unwrap_err().to_string(),
+            "2:3: undefined name 'pritn'\n3:14: undefined name 'x'"
+        );
+
+        let resolver = Resolver::new();
+        resolver.open();
+        resolver.resolve(&identifiers[1]);
+        assert!(resolver.finish().is_ok());
+    }
+}
@@ -0,0 +1,235 @@
+// 生成されたバイトコードが実行時に利用するPythonのモジュール群
+// ソースをバイナリに埋め込み、ビルド時に出力先のディレクトリへ書き出す
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// 生成したコードから実行時ライブラリを参照するための名前
+pub const CORE_ALIAS: &str = "__elaphe__";
+
+pub const CORE_MODULE_NAME: &str = "elaphe_core";
+pub const CORE_SOURCE: &str = include_str!("runtime/core.py");
+
+/// `dart:`で始まるURIでインポートできるライブラリ
+/// (ライブラリ名, モジュール名, ソースコード)
+pub const DART_LIBRARIES: [(&str, &str, &str); 3] = [
+    (
+        "math",
+        "elaphe_dart_math",
+        include_str!("runtime/dart_math.py"),
+    ),
+    (
+        "convert",
+        "elaphe_dart_convert",
+        include_str!("runtime/dart_convert.py"),
+    ),
+    ("io", "elaphe_dart_io", include_str!("runtime/dart_io.py")),
+];
+
+/// 実行時ライブラリのモジュールを`directory`に書き出す
+/// 生成されたコードからは通常のモジュールとしてインポートされる
+pub fn write_runtime_modules(directory: &Path) -> Result<()> {
+    let modules = std::iter::once((CORE_MODULE_NAME, CORE_SOURCE)).chain(
+        DART_LIBRARIES
+            .iter()
+            .map(|(_, module, source)| (*module, *source)),
+    );
+    for (module, source) in modules {
+        let path = directory.join(format!("{}.py", module));
+        if fs::read_to_string(&path).is_ok_and(|v| v == source) {
+            continue;
+        }
+        // 並行するビルドが書きかけのファイルを読まないように、別名で書いてから置き換える
+        let temporary = directory.join(format!("{}.{}.tmp", module, uuid::Uuid::new_v4()));
+        fs::write(&temporary, source)
+            .with_context(|| format!("failed to write {}", temporary.display()))?;
+        fs::rename(&temporary, &path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// URIが`dart:xxx`ならライブラリ名を返す
+pub fn dart_library_name(uri: &str) -> Option<&str> {
+    uri.strip_prefix("dart:")
+}
+
+/// インポートされたdart:ライブラリの(モジュール名, ソースコード)を返す
+pub fn find_dart_library(name: &str) -> Option<(&'static str, &'static str)> {
+    DART_LIBRARIES
+        .iter()
+        .find(|(library, _, _)| *library == name)
+        .map(|(_, module, source)| (*module, *source))
+}
+
+/// 宣言されていない場合に実行時ライブラリから参照される名前
+/// (Dartでの名前, 実行時ライブラリでの名前)
+pub const CORE_EXPORTS: [(&str, &str); 17] = [
+    ("print", "print"),
+    ("dartStr", "dartStr"),
+    ("int", "Int"),
+    ("double", "Double"),
+    ("num", "Num"),
+    ("bool", "Bool"),
+    ("String", "String"),
+    ("Iterable", "Iterable"),
+    ("List", "List"),
+    ("Set", "Set"),
+    ("Map", "Map"),
+    ("StringBuffer", "StringBuffer"),
+    ("MapEntry", "MapEntry"),
+    ("StateError", "StateError"),
+    ("FormatException", "FormatException"),
+    ("override", "override"),
+    ("Invocation", "Invocation"),
+];
+
+/// 実行時ライブラリのシムがメンバーを提供するdart:coreの型
+pub const CORE_TYPES: [&str; 9] = [
+    "int", "double", "num", "bool", "String", "Iterable", "List", "Set", "Map",
+];
+
+/// Objectのメンバー。レシーバーの型によらず実行時ライブラリを経由する
+pub const OBJECT_MEMBERS: [&str; 3] = ["hashCode", "runtimeType", "toString"];
+
+/// dart:coreの型のメンバーのうち、要素などdart:coreの型とは限らない値を返すもの
+pub const UNTYPED_CORE_MEMBERS: [&str; 15] = [
+    "elementAt",
+    "first",
+    "firstWhere",
+    "fold",
+    "forEach",
+    "last",
+    "lastWhere",
+    "putIfAbsent",
+    "reduce",
+    "remove",
+    "removeAt",
+    "removeLast",
+    "runtimeType",
+    "single",
+    "update",
+];
+
+/// dart:coreの型が持つメンバー名
+/// 静的な型がdart:coreの型の値へのアクセスは、実行時ライブラリのgetprop, invokeを経由する
+pub const CORE_MEMBERS: [&str; 106] = [
+    "abs",
+    "add",
+    "addAll",
+    "addEntries",
+    "any",
+    "asMap",
+    "bitLength",
+    "cast",
+    "ceil",
+    "ceilToDouble",
+    "clamp",
+    "clear",
+    "codeUnitAt",
+    "codeUnits",
+    "compareTo",
+    "contains",
+    "containsAll",
+    "containsKey",
+    "containsValue",
+    "difference",
+    "elementAt",
+    "endsWith",
+    "entries",
+    "every",
+    "expand",
+    "fillRange",
+    "first",
+    "firstWhere",
+    "floor",
+    "floorToDouble",
+    "fold",
+    "followedBy",
+    "forEach",
+    "gcd",
+    "getRange",
+    "hashCode",
+    "indexOf",
+    "indexWhere",
+    "insert",
+    "insertAll",
+    "intersection",
+    "isEmpty",
+    "isEven",
+    "isFinite",
+    "isInfinite",
+    "isNaN",
+    "isNegative",
+    "isNotEmpty",
+    "isOdd",
+    "join",
+    "keys",
+    "last",
+    "lastIndexOf",
+    "lastWhere",
+    "length",
+    "map",
+    "modPow",
+    "padLeft",
+    "padRight",
+    "putIfAbsent",
+    "reduce",
+    "remainder",
+    "remove",
+    "removeAll",
+    "removeAt",
+    "removeLast",
+    "removeWhere",
+    "replaceAll",
+    "replaceFirst",
+    "retainWhere",
+    "reversed",
+    "round",
+    "roundToDouble",
+    "runes",
+    "runtimeType",
+    "shuffle",
+    "sign",
+    "single",
+    "skip",
+    "skipWhile",
+    "sort",
+    "split",
+    "startsWith",
+    "sublist",
+    "substring",
+    "take",
+    "takeWhile",
+    "toDouble",
+    "toInt",
+    "toList",
+    "toLowerCase",
+    "toRadixString",
+    "toSet",
+    "toString",
+    "toStringAsFixed",
+    "toUpperCase",
+    "trim",
+    "trimLeft",
+    "trimRight",
+    "truncate",
+    "truncateToDouble",
+    "union",
+    "update",
+    "updateAll",
+    "values",
+    "where",
+];
+
+/// dart:ライブラリのモジュールが`__all__`で公開している名前を返す
+pub fn dart_library_exports(source: &str) -> Vec<&str> {
+    let Some(start) = source.find("__all__ = [") else {
+        return vec![];
+    };
+    let rest = &source[start..];
+    let end = rest.find(']').unwrap_or(rest.len());
+    rest[..end].split('"').skip(1).step_by(2).collect()
+}
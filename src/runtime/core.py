@@ -0,0 +1,1114 @@
+# Elapheの実行時ライブラリ
+# コンパイラが生成したコードから __elaphe__ として参照される
+import builtins
+import functools
+from abc import ABCMeta, abstractmethod
+import importlib
+import itertools
+import math
+import random as _random
+import re
+import types
+
+_INT64_MASK = (1 << 64) - 1
+_INT64_SIGN = 1 << 63
+
+
+def _is_int(value):
+    return isinstance(value, int) and not isinstance(value, bool)
+
+
+def _to_int64(value):
+    value &= _INT64_MASK
+    if value >= _INT64_SIGN:
+        value -= 1 << 64
+    return value
+
+
+# ----- 数値演算 (Dartの意味論) -----
+
+
+def div(a, b):
+    """a / b: 結果は常にdouble"""
+    try:
+        return a / b
+    except ZeroDivisionError:
+        a = float(a)
+        if a == 0.0 or math.isnan(a):
+            return math.nan
+        return math.copysign(math.inf, a) * math.copysign(1.0, float(b))
+
+
+def truncdiv(a, b):
+    """a ~/ b: 0方向に切り捨てた整数"""
+    if _is_int(a) and _is_int(b):
+        if b == 0:
+            raise ZeroDivisionError("IntegerDivisionByZeroException")
+        q = abs(a) // abs(b)
+        return q if (a < 0) == (b < 0) else -q
+    q = div(a, b)
+    if math.isnan(q) or math.isinf(q):
+        raise ValueError("Unsupported operation: Result of truncating division is " + str(q))
+    return int(q)
+
+
+def mod(a, b):
+    """a % b: 結果は常に0以上"""
+    if _is_int(a) and _is_int(b):
+        if b == 0:
+            raise ZeroDivisionError("IntegerDivisionByZeroException")
+        return a % abs(b)
+    r = remainder(a, b)
+    if r < 0:
+        r += abs(b)
+    return r
+
+
+def remainder(a, b):
+    """a.remainder(b): 結果の符号はaと同じ"""
+    if _is_int(a) and _is_int(b):
+        return a - truncdiv(a, b) * b
+    if b == 0 or math.isnan(a) or math.isnan(b) or math.isinf(a):
+        return math.nan
+    return math.fmod(a, b)
+
+
+def ushr(a, b):
+    """a >>> b: 64bit整数としての論理右シフト"""
+    if b < 0:
+        raise ValueError("Invalid argument(s): " + str(b))
+    if b >= 64:
+        return 0
+    return _to_int64((a & _INT64_MASK) >> b)
+
+
+# ----- 文字列化 (DartのtoString) -----
+
+# 循環参照を検出するため、文字列化している途中のコレクション
+_to_string_visiting = []
+
+
+def _double_str(value):
+    if math.isnan(value):
+        return "NaN"
+    if math.isinf(value):
+        return "Infinity" if value > 0 else "-Infinity"
+    if value == 0.0:
+        return "-0.0" if math.copysign(1.0, value) < 0 else "0.0"
+
+    # reprは最短の10進表記を返すので、桁と指数だけを取り出してDartの書式に直す
+    mantissa, _, exponent = repr(abs(value)).partition("e")
+    int_part, _, frac_part = mantissa.partition(".")
+    all_digits = int_part + frac_part
+    digits = all_digits.lstrip("0")
+    # 有効数字の先頭から数えた小数点の位置
+    point = len(int_part) - (len(all_digits) - len(digits))
+    point += int(exponent) if exponent else 0
+    digits = digits.rstrip("0")
+
+    sign = "-" if value < 0 else ""
+    if len(digits) <= point <= 21:
+        return sign + digits + "0" * (point - len(digits)) + ".0"
+    if 0 < point <= 21:
+        return sign + digits[:point] + "." + digits[point:]
+    if -6 < point <= 0:
+        return sign + "0." + "0" * -point + digits
+    e = point - 1
+    result = digits[0]
+    if len(digits) > 1:
+        result += "." + digits[1:]
+    return sign + result + "e" + ("+" if e > 0 else "-") + str(abs(e))
+
+
+def _collection_str(value, start, end, items):
+    if any(v is value for v in _to_string_visiting):
+        return start + "..." + end
+    _to_string_visiting.append(value)
+    try:
+        return start + ", ".join(items()) + end
+    finally:
+        _to_string_visiting.pop()
+
+
+# runtimeTypeで得られるPythonの型に対応するDartの型名
+_TYPE_NAMES = {
+    bool: "bool",
+    float: "double",
+    str: "String",
+    type(None): "Null",
+    list: "List<dynamic>",
+    dict: "Map<dynamic, dynamic>",
+    set: "Set<dynamic>",
+}
+
+
+def dartStr(value):
+    """Dartのv.toString()と同じ文字列を返す"""
+    if value is None:
+        return "null"
+    if value is True:
+        return "true"
+    if value is False:
+        return "false"
+    if isinstance(value, str):
+        return value
+    if isinstance(value, float):
+        return _double_str(value)
+    if isinstance(value, int):
+        return str(value)
+    if isinstance(value, (list, tuple)):
+        return _collection_str(value, "[", "]", lambda: (dartStr(v) for v in value))
+    if isinstance(value, dict):
+        return _collection_str(
+            value,
+            "{",
+            "}",
+            lambda: (dartStr(k) + ": " + dartStr(v) for k, v in value.items()),
+        )
+    if isinstance(value, (set, frozenset)):
+        return _collection_str(value, "{", "}", lambda: (dartStr(v) for v in value))
+    if isinstance(value, _LazyIterable):
+        return _collection_str(value, "(", ")", lambda: (dartStr(v) for v in value))
+
+    if isinstance(value, type):
+        return _TYPE_NAMES.get(value, value.__name__)
+
+    to_string = getattr(value, "toString", None)
+    if callable(to_string):
+        return to_string()
+    cls = type(value)
+    if cls.__str__ is object.__str__ and cls.__repr__ is object.__repr__:
+        return "Instance of '" + cls.__name__ + "'"
+    return str(value)
+
+
+def print(*objects, sep=" ", end="\n", file=None, flush=False):
+    """Dartの書式で値を出力するprint"""
+    builtins.print(*(dartStr(v) for v in objects), sep=sep, end=end, file=file, flush=flush)
+
+
+# ----- dart:coreのシム -----
+# コンパイラはruntime.rsのCORE_MEMBERSに含まれる名前へのアクセスだけを
+# getprop / invoke 経由にするので、ここにメンバーを追加したら向こうにも追加すること
+
+
+class StateError(Exception):
+    pass
+
+
+class FormatException(Exception):
+    pass
+
+
+def override(function):
+    """@overrideはデコレータとしては何もしない"""
+    return function
+
+
+class Invocation:
+    """noSuchMethodに渡される呼び出しの情報 (memberNameは文字列)"""
+
+    def __init__(self, memberName, positionalArguments, namedArguments):
+        self.memberName = memberName
+        self.positionalArguments = list(positionalArguments)
+        self.namedArguments = dict(namedArguments)
+        self.isMethod = True
+        self.isGetter = False
+        self.isSetter = False
+        self.isAccessor = False
+
+
+# Objectのメンバーと特殊メソッドはnoSuchMethodに渡さない
+_OBJECT_MEMBER_NAMES = ("toString", "hashCode", "runtimeType", "noSuchMethod")
+
+
+def noSuchMethodGetattr(no_such_method):
+    """noSuchMethodから__getattr__を作る
+    Pythonでは属性の取得とメソッド呼び出しを区別できないので、
+    見つからないメンバーは呼び出されたときにnoSuchMethodへ渡す"""
+
+    def __getattr__(self, name):
+        if name.startswith("__") or name in _OBJECT_MEMBER_NAMES:
+            raise AttributeError(name)
+
+        def method(*args, **kwargs):
+            return no_such_method(self, Invocation(name, args, kwargs))
+
+        return method
+
+    return __getattr__
+
+
+def _no_element():
+    raise StateError("Bad state: No element")
+
+
+class _LazyIterable:
+    """DartのIterable: 要素は走査されるたびに計算される"""
+
+    def __init__(self, factory):
+        self._factory = factory
+
+    def __iter__(self):
+        return iter(self._factory())
+
+
+class MapEntry:
+    def __init__(self, key, value):
+        self.key = key
+        self.value = value
+
+    def toString(self):
+        return "MapEntry(" + dartStr(self.key) + ": " + dartStr(self.value) + ")"
+
+
+def _lazy(factory):
+    return _LazyIterable(factory)
+
+
+def _first(iterable):
+    for v in iterable:
+        return v
+    _no_element()
+
+
+def _last(iterable):
+    found = False
+    result = None
+    for v in iterable:
+        found = True
+        result = v
+    if not found:
+        _no_element()
+    return result
+
+
+def _single(iterable):
+    it = iter(iterable)
+    for v in it:
+        for _ in it:
+            raise StateError("Bad state: Too many elements")
+        return v
+    _no_element()
+
+
+def _length(iterable):
+    try:
+        return len(iterable)
+    except TypeError:
+        return sum(1 for _ in iterable)
+
+
+def _is_empty(iterable):
+    for _ in iterable:
+        return False
+    return True
+
+
+def _first_where(iterable, test, orElse=None):
+    for v in iterable:
+        if test(v):
+            return v
+    if orElse is not None:
+        return orElse()
+    _no_element()
+
+
+def _last_where(iterable, test, orElse=None):
+    return _first_where(_reversed(list(iterable)), test, orElse)
+
+
+def _reversed(sequence):
+    return _lazy(lambda: builtins.reversed(sequence))
+
+
+def _reduce(iterable, combine):
+    it = iter(iterable)
+    for value in it:
+        for v in it:
+            value = combine(value, v)
+        return value
+    _no_element()
+
+
+def _fold(iterable, initial, combine):
+    value = initial
+    for v in iterable:
+        value = combine(value, v)
+    return value
+
+
+def _element_at(iterable, index):
+    if index >= 0:
+        for i, v in enumerate(iterable):
+            if i == index:
+                return v
+    raise IndexError("RangeError (index): Index out of range: " + str(index))
+
+
+def _join(iterable, separator=""):
+    return separator.join(dartStr(v) for v in iterable)
+
+
+def _for_each(iterable, action):
+    for v in iterable:
+        action(v)
+
+
+def _skip_while(iterable, test):
+    return _lazy(lambda: itertools.dropwhile(test, iterable))
+
+
+def _take_while(iterable, test):
+    return _lazy(lambda: itertools.takewhile(test, iterable))
+
+
+_ITERABLE_GETTERS = {
+    "length": _length,
+    "isEmpty": _is_empty,
+    "isNotEmpty": lambda it: not _is_empty(it),
+    "first": _first,
+    "last": _last,
+    "single": _single,
+}
+
+_ITERABLE_METHODS = {
+    "where": lambda it, test: _lazy(lambda: builtins.filter(test, it)),
+    "map": lambda it, f: _lazy(lambda: builtins.map(f, it)),
+    "expand": lambda it, f: _lazy(lambda: itertools.chain.from_iterable(builtins.map(f, it))),
+    "skip": lambda it, count: _lazy(lambda: itertools.islice(it, count, None)),
+    "take": lambda it, count: _lazy(lambda: itertools.islice(it, count)),
+    "skipWhile": _skip_while,
+    "takeWhile": _take_while,
+    "followedBy": lambda it, other: _lazy(lambda: itertools.chain(it, other)),
+    "cast": lambda it: it,
+    "toList": lambda it, growable=True: list(it),
+    "toSet": lambda it: set(it),
+    "forEach": _for_each,
+    "any": lambda it, test: builtins.any(test(v) for v in it),
+    "every": lambda it, test: builtins.all(test(v) for v in it),
+    "contains": lambda it, element: element in it,
+    "fold": _fold,
+    "reduce": _reduce,
+    "join": _join,
+    "elementAt": _element_at,
+    "firstWhere": _first_where,
+    "lastWhere": _last_where,
+    "toString": dartStr,
+}
+
+
+def _list_remove(lst, value):
+    try:
+        lst.remove(value)
+        return True
+    except ValueError:
+        return False
+
+
+def _list_index_of(lst, element, start=0):
+    try:
+        return lst.index(element, start)
+    except ValueError:
+        return -1
+
+
+def _list_last_index_of(lst, element, start=None):
+    end = len(lst) - 1 if start is None else start
+    for i in range(end, -1, -1):
+        if lst[i] == element:
+            return i
+    return -1
+
+
+def _list_index_where(lst, test, start=0):
+    for i in range(start, len(lst)):
+        if test(lst[i]):
+            return i
+    return -1
+
+
+def _list_sort(lst, compare=None):
+    if compare is None:
+        lst.sort()
+    else:
+        lst.sort(key=functools.cmp_to_key(compare))
+
+
+def _list_remove_where(lst, test):
+    lst[:] = [v for v in lst if not test(v)]
+
+
+def _list_retain_where(lst, test):
+    lst[:] = [v for v in lst if test(v)]
+
+
+def _list_remove_last(lst):
+    if not lst:
+        raise IndexError("RangeError: Value not in range: -1")
+    return lst.pop()
+
+
+def _list_fill_range(lst, start, end, value=None):
+    for i in range(start, end):
+        lst[i] = value
+
+
+_LIST_GETTERS = dict(
+    _ITERABLE_GETTERS,
+    length=len,
+    isEmpty=lambda lst: len(lst) == 0,
+    isNotEmpty=lambda lst: len(lst) != 0,
+    first=lambda lst: lst[0] if lst else _no_element(),
+    last=lambda lst: lst[-1] if lst else _no_element(),
+    reversed=_reversed,
+)
+
+_LIST_METHODS = dict(
+    _ITERABLE_METHODS,
+    add=lambda lst, value: lst.append(value),
+    addAll=lambda lst, iterable: lst.extend(iterable),
+    insert=lambda lst, index, element: lst.insert(index, element),
+    insertAll=lambda lst, index, iterable: lst.__setitem__(slice(index, index), list(iterable)),
+    remove=_list_remove,
+    removeAt=lambda lst, index: lst.pop(index),
+    removeLast=_list_remove_last,
+    removeWhere=_list_remove_where,
+    retainWhere=_list_retain_where,
+    clear=lambda lst: lst.clear(),
+    indexOf=_list_index_of,
+    lastIndexOf=_list_last_index_of,
+    indexWhere=_list_index_where,
+    sort=_list_sort,
+    shuffle=lambda lst, random=None: (random or _random).shuffle(lst),
+    sublist=lambda lst, start, end=None: lst[start:end],
+    getRange=lambda lst, start, end: _lazy(lambda: lst[start:end]),
+    fillRange=_list_fill_range,
+    asMap=lambda lst: dict(enumerate(lst)),
+)
+
+
+def _set_add(s, value):
+    if value in s:
+        return False
+    s.add(value)
+    return True
+
+
+def _set_remove(s, value):
+    if value in s:
+        s.remove(value)
+        return True
+    return False
+
+
+def _set_remove_where(s, test):
+    for v in [v for v in s if test(v)]:
+        s.remove(v)
+
+
+def _set_retain_where(s, test):
+    for v in [v for v in s if not test(v)]:
+        s.remove(v)
+
+
+_SET_GETTERS = dict(
+    _ITERABLE_GETTERS,
+    length=len,
+    isEmpty=lambda s: len(s) == 0,
+    isNotEmpty=lambda s: len(s) != 0,
+)
+
+_SET_METHODS = dict(
+    _ITERABLE_METHODS,
+    add=_set_add,
+    addAll=lambda s, iterable: s.update(iterable),
+    remove=_set_remove,
+    removeAll=lambda s, iterable: s.difference_update(iterable),
+    removeWhere=_set_remove_where,
+    retainWhere=_set_retain_where,
+    containsAll=lambda s, other: builtins.all(v in s for v in other),
+    union=lambda s, other: s | set(other),
+    intersection=lambda s, other: s & set(other),
+    difference=lambda s, other: s - set(other),
+    clear=lambda s: s.clear(),
+)
+
+
+def _map_put_if_absent(m, key, if_absent):
+    if key not in m:
+        m[key] = if_absent()
+    return m[key]
+
+
+def _map_update(m, key, update, ifAbsent=None):
+    if key in m:
+        m[key] = update(m[key])
+    elif ifAbsent is not None:
+        m[key] = ifAbsent()
+    else:
+        raise ValueError("Invalid argument (key): Key not in map.: " + dartStr(key))
+    return m[key]
+
+
+def _map_update_all(m, update):
+    for k in m:
+        m[k] = update(k, m[k])
+
+
+def _map_for_each(m, action):
+    for k, v in list(m.items()):
+        action(k, v)
+
+
+def _map_map(m, convert):
+    result = {}
+    for k, v in m.items():
+        entry = convert(k, v)
+        result[entry.key] = entry.value
+    return result
+
+
+def _map_remove_where(m, test):
+    for k in [k for k, v in m.items() if test(k, v)]:
+        del m[k]
+
+
+def _map_add_entries(m, entries):
+    for entry in entries:
+        m[entry.key] = entry.value
+
+
+_MAP_GETTERS = {
+    "length": len,
+    "isEmpty": lambda m: len(m) == 0,
+    "isNotEmpty": lambda m: len(m) != 0,
+    "keys": lambda m: _lazy(m.keys),
+    "values": lambda m: _lazy(m.values),
+    "entries": lambda m: _lazy(lambda: (MapEntry(k, v) for k, v in m.items())),
+}
+
+_MAP_METHODS = {
+    "containsKey": lambda m, key: key in m,
+    "containsValue": lambda m, value: value in m.values(),
+    "remove": lambda m, key: m.pop(key, None),
+    "putIfAbsent": _map_put_if_absent,
+    "update": _map_update,
+    "updateAll": _map_update_all,
+    "forEach": _map_for_each,
+    "addAll": lambda m, other: m.update(other),
+    "addEntries": _map_add_entries,
+    "removeWhere": _map_remove_where,
+    "map": _map_map,
+    "clear": lambda m: m.clear(),
+    "cast": lambda m: m,
+    "toString": dartStr,
+}
+
+
+def _string_index_of(s, pattern, start=0):
+    return s.find(pattern, start)
+
+
+def _string_last_index_of(s, pattern, start=None):
+    if start is None:
+        return s.rfind(pattern)
+    return s.rfind(pattern, 0, start + len(pattern))
+
+
+def _string_split(s, pattern):
+    if pattern == "":
+        return list(s)
+    if s == "":
+        return [""]
+    return s.split(pattern)
+
+
+def _string_replace_first(s, old, new, start=0):
+    return s[:start] + s[start:].replace(old, new, 1)
+
+
+def _string_compare_to(s, other):
+    return (s > other) - (s < other)
+
+
+_STRING_GETTERS = {
+    "length": len,
+    "isEmpty": lambda s: len(s) == 0,
+    "isNotEmpty": lambda s: len(s) != 0,
+    "codeUnits": lambda s: [ord(c) for c in s],
+    "runes": lambda s: _lazy(lambda: (ord(c) for c in s)),
+}
+
+_STRING_METHODS = {
+    "substring": lambda s, start, end=None: s[start:end],
+    "contains": lambda s, other, start=0: other in s[start:],
+    "startsWith": lambda s, pattern, index=0: s.startswith(pattern, index),
+    "endsWith": lambda s, other: s.endswith(other),
+    "indexOf": _string_index_of,
+    "lastIndexOf": _string_last_index_of,
+    "toUpperCase": lambda s: s.upper(),
+    "toLowerCase": lambda s: s.lower(),
+    "trim": lambda s: s.strip(),
+    "trimLeft": lambda s: s.lstrip(),
+    "trimRight": lambda s: s.rstrip(),
+    "split": _string_split,
+    "replaceAll": lambda s, old, new: s.replace(old, new),
+    "replaceFirst": _string_replace_first,
+    "padLeft": lambda s, width, padding=" ": padding * (width - len(s)) + s,
+    "padRight": lambda s, width, padding=" ": s + padding * (width - len(s)),
+    "codeUnitAt": lambda s, index: ord(s[index]),
+    "compareTo": _string_compare_to,
+    "toString": lambda s: s,
+}
+
+
+def _num_round(value):
+    # Dartのroundは0から遠い方へ丸める
+    if isinstance(value, int):
+        return value
+    if value < 0:
+        return -_num_round(-value)
+    result = math.floor(value)
+    if value - result >= 0.5:
+        result += 1
+    return result
+
+
+def _num_clamp(value, lower, upper):
+    if value < lower:
+        return lower
+    if value > upper:
+        return upper
+    return value
+
+
+def _num_compare_to(value, other):
+    return (value > other) - (value < other)
+
+
+def _int_to_radix_string(value, radix):
+    if not 2 <= radix <= 36:
+        raise ValueError("Invalid argument(s): radix " + str(radix))
+    if value == 0:
+        return "0"
+    chars = "0123456789abcdefghijklmnopqrstuvwxyz"
+    sign = "-" if value < 0 else ""
+    value = abs(value)
+    result = ""
+    while value > 0:
+        value, d = divmod(value, radix)
+        result = chars[d] + result
+    return sign + result
+
+
+def _num_to_string_as_fixed(value, digits):
+    value = float(value)
+    if math.isnan(value) or math.isinf(value):
+        return _double_str(value)
+    return format(value, "." + str(digits) + "f")
+
+
+def _num_sign(value):
+    if isinstance(value, float):
+        if value == 0 or math.isnan(value):
+            return value
+        return math.copysign(1.0, value)
+    return (value > 0) - (value < 0)
+
+
+_NUM_GETTERS = {
+    "isNaN": lambda n: isinstance(n, float) and math.isnan(n),
+    "isInfinite": lambda n: isinstance(n, float) and math.isinf(n),
+    "isFinite": lambda n: not isinstance(n, float) or math.isfinite(n),
+    "isNegative": lambda n: n < 0 or (isinstance(n, float) and math.copysign(1.0, n) < 0),
+    "isEven": lambda n: n % 2 == 0,
+    "isOdd": lambda n: n % 2 == 1,
+    "sign": _num_sign,
+    "bitLength": lambda n: n.bit_length() if n >= 0 else (~n).bit_length(),
+}
+
+_NUM_METHODS = {
+    "abs": abs,
+    "ceil": math.ceil,
+    "floor": math.floor,
+    "round": _num_round,
+    "truncate": math.trunc,
+    "ceilToDouble": lambda n: float(math.ceil(n)),
+    "floorToDouble": lambda n: float(math.floor(n)),
+    "roundToDouble": lambda n: float(_num_round(n)),
+    "truncateToDouble": lambda n: float(math.trunc(n)),
+    "toInt": math.trunc,
+    "toDouble": float,
+    "clamp": _num_clamp,
+    "compareTo": _num_compare_to,
+    "remainder": remainder,
+    "gcd": math.gcd,
+    "modPow": pow,
+    "toRadixString": _int_to_radix_string,
+    "toStringAsFixed": _num_to_string_as_fixed,
+    "toString": dartStr,
+}
+
+_OBJECT_GETTERS = {}
+
+_OBJECT_METHODS = {
+    "toString": dartStr,
+}
+
+_ITERATOR_TYPES = (
+    _LazyIterable,
+    range,
+    types.GeneratorType,
+    type({}.keys()),
+    type({}.values()),
+    type({}.items()),
+)
+
+
+def _members(obj):
+    if isinstance(obj, str):
+        return _STRING_GETTERS, _STRING_METHODS
+    if isinstance(obj, bool) or obj is None:
+        return _OBJECT_GETTERS, _OBJECT_METHODS
+    if isinstance(obj, (int, float)):
+        return _NUM_GETTERS, _NUM_METHODS
+    if isinstance(obj, (list, tuple)):
+        return _LIST_GETTERS, _LIST_METHODS
+    if isinstance(obj, dict):
+        return _MAP_GETTERS, _MAP_METHODS
+    if isinstance(obj, (set, frozenset)):
+        return _SET_GETTERS, _SET_METHODS
+    if isinstance(obj, _ITERATOR_TYPES):
+        return _ITERABLE_GETTERS, _ITERABLE_METHODS
+    return None
+
+
+def getprop(obj, name):
+    """obj.name: Dartのコア型ならシムのメンバーを返す"""
+    if name == "hashCode":
+        # クラスで定義したhashCodeはそのまま返す (hash()は-1を-2にする)
+        return obj.hashCode if hasattr(obj, name) else hash(obj)
+    if name == "runtimeType":
+        return type(obj)
+    members = _members(obj)
+    if members is not None:
+        getters, methods = members
+        if name in getters:
+            return getters[name](obj)
+        if name in methods:
+            return functools.partial(methods[name], obj)
+    return getattr(obj, name)
+
+
+def invoke(obj, name, *args, **kwargs):
+    """obj.name(args): Dartのコア型ならシムのメソッドを呼ぶ"""
+    members = _members(obj)
+    if members is not None:
+        getters, methods = members
+        if name in methods:
+            return methods[name](obj, *args, **kwargs)
+        if name in getters:
+            return getters[name](obj)(*args, **kwargs)
+    elif name == "toString" and not hasattr(obj, name):
+        return dartStr(obj)
+    return getattr(obj, name)(*args, **kwargs)
+
+
+# ----- dart:coreの型 -----
+# `is`による型テストと、int.parseなどの静的メンバーを提供する
+
+
+class _CoreType(type):
+    def __instancecheck__(cls, obj):
+        return cls._is_instance(obj)
+
+
+_INT_PATTERN = re.compile(r"[+-]?(0[xX][0-9a-fA-F]+|[0-9]+)")
+_DOUBLE_PATTERN = re.compile(r"[+-]?([0-9]+\.?[0-9]*|\.[0-9]+)([eE][+-]?[0-9]+)?")
+
+
+class Int(metaclass=_CoreType):
+    # Pythonのint(...)として呼ばれた場合
+    def __new__(cls, *args, **kwargs):
+        return int(*args, **kwargs)
+
+    @staticmethod
+    def _is_instance(obj):
+        return _is_int(obj)
+
+    @staticmethod
+    def parse(source, radix=None):
+        result = Int.tryParse(source, radix)
+        if result is None:
+            raise FormatException("FormatException: Invalid radix-" + str(radix or 10) + " number: " + source)
+        return result
+
+    @staticmethod
+    def tryParse(source, radix=None):
+        source = source.strip()
+        try:
+            if radix is not None:
+                return int(source, radix)
+            if _INT_PATTERN.fullmatch(source):
+                return int(source, 0) if "x" in source or "X" in source else int(source)
+        except ValueError:
+            pass
+        return None
+
+
+class Double(metaclass=_CoreType):
+    nan = math.nan
+    infinity = math.inf
+    negativeInfinity = -math.inf
+
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, float)
+
+    @staticmethod
+    def parse(source):
+        result = Double.tryParse(source)
+        if result is None:
+            raise FormatException("FormatException: Invalid double: " + source)
+        return result
+
+    @staticmethod
+    def tryParse(source):
+        source = source.strip()
+        unsigned = source.lstrip("+-")
+        if unsigned in ("Infinity", "NaN") and len(source) - len(unsigned) <= 1:
+            return float(source.replace("Infinity", "inf"))
+        if _DOUBLE_PATTERN.fullmatch(source):
+            return float(source)
+        if _INT_PATTERN.fullmatch(source):
+            return float(Int.parse(source))
+        return None
+
+
+class Num(metaclass=_CoreType):
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, (int, float)) and not isinstance(obj, bool)
+
+    @staticmethod
+    def parse(source):
+        result = Num.tryParse(source)
+        if result is None:
+            raise FormatException("FormatException: " + source)
+        return result
+
+    @staticmethod
+    def tryParse(source):
+        result = Int.tryParse(source)
+        return result if result is not None else Double.tryParse(source)
+
+
+class Bool(metaclass=_CoreType):
+    def __new__(cls, *args, **kwargs):
+        return bool(*args, **kwargs)
+
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, bool)
+
+
+class String(metaclass=_CoreType):
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, str)
+
+    @staticmethod
+    def fromCharCode(char_code):
+        return chr(char_code)
+
+    @staticmethod
+    def fromCharCodes(char_codes, start=0, end=None):
+        return "".join(chr(c) for c in list(char_codes)[start:end])
+
+
+class Iterable(metaclass=_CoreType):
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, (list, tuple, set, frozenset) + _ITERATOR_TYPES)
+
+    @staticmethod
+    def generate(count, generator=None):
+        if generator is None:
+            return _lazy(lambda: range(count))
+        return _lazy(lambda: builtins.map(generator, range(count)))
+
+    @staticmethod
+    def empty():
+        return _lazy(tuple)
+
+
+class List(metaclass=_CoreType):
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, (list, tuple))
+
+    @staticmethod
+    def filled(length, fill, growable=False):
+        return [fill] * length
+
+    @staticmethod
+    def generate(length, generator, growable=True):
+        return [generator(i) for i in range(length)]
+
+    @staticmethod
+    def empty(growable=False):
+        return []
+
+    @staticmethod
+    def of(elements, growable=True):
+        return list(elements)
+
+    @staticmethod
+    def from_(elements, growable=True):
+        return list(elements)
+
+
+class Set(metaclass=_CoreType):
+    def __new__(cls):
+        return set()
+
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, (set, frozenset))
+
+    @staticmethod
+    def of(elements):
+        return set(elements)
+
+    @staticmethod
+    def from_(elements):
+        return set(elements)
+
+
+class Map(metaclass=_CoreType):
+    def __new__(cls):
+        return {}
+
+    @staticmethod
+    def _is_instance(obj):
+        return isinstance(obj, dict)
+
+    @staticmethod
+    def of(other):
+        return dict(other)
+
+    @staticmethod
+    def from_(other):
+        return dict(other)
+
+    @staticmethod
+    def fromIterables(keys, values):
+        return dict(zip(keys, values))
+
+    @staticmethod
+    def fromEntries(entries):
+        return {e.key: e.value for e in entries}
+
+
+# `from`はPythonの予約語なので、属性としてだけ登録する
+setattr(List, "from", List.from_)
+setattr(Set, "from", Set.from_)
+setattr(Map, "from", Map.from_)
+
+
+class StringBuffer:
+    def __init__(self, content=""):
+        self._parts = [dartStr(content)]
+
+    @property
+    def length(self):
+        return sum(len(p) for p in self._parts)
+
+    @property
+    def isEmpty(self):
+        return self.length == 0
+
+    @property
+    def isNotEmpty(self):
+        return self.length != 0
+
+    def write(self, obj):
+        self._parts.append(dartStr(obj))
+
+    def writeln(self, obj=""):
+        self._parts.append(dartStr(obj) + "\n")
+
+    def writeAll(self, objects, separator=""):
+        self._parts.append(_join(objects, separator))
+
+    def writeCharCode(self, char_code):
+        self._parts.append(chr(char_code))
+
+    def clear(self):
+        self._parts = []
+
+    def toString(self):
+        return "".join(self._parts)
+
+
+# ----- インポート -----
+
+
+def _public_names(module):
+    names = getattr(module, "__all__", None)
+    if names is None:
+        names = [n for n in dir(module) if not n.startswith("_")]
+    return names
+
+
+def import_hiding(namespace, module, hide):
+    """import "x" hide a, b; の名前をnamespaceに追加する"""
+    for name in _public_names(module):
+        if name not in hide:
+            namespace[name] = getattr(module, name)
+
+
+def import_prefixed(module, show, hide):
+    """import "x" as p show a; のpとして使う名前空間を返す"""
+    names = _public_names(module) if show is None else show
+    return types.SimpleNamespace(
+        **{name: getattr(module, name) for name in names if name not in hide}
+    )
+
+
+class _DeferredLibrary:
+    """import "x" deferred as p; のp
+
+    loadLibrary()が呼ばれるまでモジュールを読み込まない
+    """
+
+    def __init__(self, module_name, show, hide):
+        self.__dict__["_module_name"] = module_name
+        self.__dict__["_show"] = show
+        self.__dict__["_hide"] = hide
+        self.__dict__["_library"] = None
+
+    def loadLibrary(self):
+        if self._library is None:
+            module = importlib.import_module(self._module_name)
+            if self._show is not None or self._hide:
+                module = import_prefixed(module, self._show, self._hide)
+            self.__dict__["_library"] = module
+
+    def __getattr__(self, name):
+        if name.startswith("__"):
+            raise AttributeError(name)
+        if self._library is None:
+            raise StateError(
+                "Deferred library %s was not loaded. Call loadLibrary() first."
+                % self._module_name
+            )
+        return getattr(self._library, name)
+
+    def __setattr__(self, name, value):
+        raise StateError("Cannot assign to a member of a deferred library.")
+
+
+def deferred_library(module_name, show, hide):
+    return _DeferredLibrary(module_name, show, hide)
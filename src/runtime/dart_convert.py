@@ -0,0 +1,134 @@
+# dart:convert のシム
+import base64 as _base64
+import json as _json
+
+__all__ = [
+    "base64",
+    "base64Decode",
+    "base64Encode",
+    "base64Url",
+    "json",
+    "jsonDecode",
+    "jsonEncode",
+    "utf8",
+    "JsonEncoder",
+    "JsonDecoder",
+]
+
+
+def _default(to_encodable):
+    def default(obj):
+        if to_encodable is not None:
+            return to_encodable(obj)
+        to_json = getattr(obj, "toJson", None)
+        if callable(to_json):
+            return to_json()
+        raise TypeError("Converting object to an encodable object failed: " + repr(obj))
+
+    return default
+
+
+class JsonEncoder:
+    def __init__(self, toEncodable=None, indent=None):
+        self._to_encodable = toEncodable
+        self._indent = indent
+
+    @staticmethod
+    def withIndent(indent, toEncodable=None):
+        return JsonEncoder(toEncodable, indent)
+
+    def convert(self, obj):
+        if self._indent is None:
+            separators = (",", ":")
+        else:
+            separators = (",", ": ")
+        return _json.dumps(
+            obj,
+            ensure_ascii=False,
+            allow_nan=False,
+            indent=self._indent,
+            separators=separators,
+            default=_default(self._to_encodable),
+        )
+
+
+class JsonDecoder:
+    def __init__(self, reviver=None):
+        self._reviver = reviver
+
+    def convert(self, source):
+        result = _json.loads(source)
+        if self._reviver is None:
+            return result
+        return self._revive(None, result)
+
+    def _revive(self, key, value):
+        if isinstance(value, dict):
+            for k in value:
+                value[k] = self._revive(k, value[k])
+        elif isinstance(value, list):
+            for i in range(len(value)):
+                value[i] = self._revive(i, value[i])
+        return self._reviver(key, value)
+
+
+class JsonCodec:
+    def __init__(self, reviver=None, toEncodable=None):
+        self._reviver = reviver
+        self._to_encodable = toEncodable
+
+    def encode(self, value, toEncodable=None):
+        return JsonEncoder(toEncodable or self._to_encodable).convert(value)
+
+    def decode(self, source, reviver=None):
+        return JsonDecoder(reviver or self._reviver).convert(source)
+
+
+json = JsonCodec()
+
+
+def jsonEncode(obj, toEncodable=None):
+    return json.encode(obj, toEncodable=toEncodable)
+
+
+def jsonDecode(source, reviver=None):
+    return json.decode(source, reviver=reviver)
+
+
+class Utf8Codec:
+    def encode(self, string):
+        return list(string.encode("utf-8"))
+
+    def decode(self, codeUnits, allowMalformed=False):
+        errors = "replace" if allowMalformed else "strict"
+        return bytes(codeUnits).decode("utf-8", errors)
+
+
+utf8 = Utf8Codec()
+
+
+class Base64Codec:
+    def __init__(self, url_safe=False):
+        self._url_safe = url_safe
+
+    def encode(self, bytes_):
+        if self._url_safe:
+            return _base64.urlsafe_b64encode(bytes(bytes_)).decode("ascii")
+        return _base64.b64encode(bytes(bytes_)).decode("ascii")
+
+    def decode(self, encoded):
+        if self._url_safe:
+            return list(_base64.urlsafe_b64decode(encoded))
+        return list(_base64.b64decode(encoded))
+
+
+base64 = Base64Codec()
+base64Url = Base64Codec(url_safe=True)
+
+
+def base64Encode(bytes_):
+    return base64.encode(bytes_)
+
+
+def base64Decode(source):
+    return base64.decode(source)
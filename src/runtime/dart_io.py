@@ -0,0 +1,226 @@
+# dart:io のシム (同期APIのみ)
+import os as _os
+import pathlib as _pathlib
+import shutil as _shutil
+import sys as _sys
+import tempfile as _tempfile
+
+from elaphe_core import dartStr as _dart_str
+
+__all__ = [
+    "Directory",
+    "File",
+    "FileMode",
+    "FileSystemEntity",
+    "FileSystemException",
+    "Platform",
+    "exit",
+    "stderr",
+    "stdin",
+    "stdout",
+]
+
+
+class FileSystemException(Exception):
+    pass
+
+
+class FileMode:
+    read = "r"
+    write = "w"
+    append = "a"
+    writeOnly = "w"
+    writeOnlyAppend = "a"
+
+
+def _wrap_os_error(f):
+    def wrapper(*args, **kwargs):
+        try:
+            return f(*args, **kwargs)
+        except OSError as e:
+            raise FileSystemException(str(e)) from e
+
+    wrapper.__name__ = f.__name__
+    return wrapper
+
+
+class FileSystemEntity:
+    def __init__(self, path):
+        self.path = str(path)
+
+    @property
+    def absolute(self):
+        return type(self)(_os.path.abspath(self.path))
+
+    @property
+    def parent(self):
+        return Directory(_os.path.dirname(_os.path.abspath(self.path)))
+
+    @property
+    def uri(self):
+        return _pathlib.Path(_os.path.abspath(self.path)).as_uri()
+
+    def existsSync(self):
+        return _os.path.exists(self.path)
+
+    def toString(self):
+        return type(self).__name__ + ": '" + self.path + "'"
+
+
+class File(FileSystemEntity):
+    def existsSync(self):
+        return _os.path.isfile(self.path)
+
+    @_wrap_os_error
+    def createSync(self, recursive=False, exclusive=False):
+        if recursive:
+            _os.makedirs(_os.path.dirname(_os.path.abspath(self.path)), exist_ok=True)
+        with open(self.path, "x" if exclusive else "a"):
+            pass
+
+    @_wrap_os_error
+    def readAsStringSync(self, encoding=None):
+        with open(self.path, encoding="utf-8", newline="") as f:
+            return f.read()
+
+    @_wrap_os_error
+    def readAsLinesSync(self, encoding=None):
+        return self.readAsStringSync().splitlines()
+
+    @_wrap_os_error
+    def readAsBytesSync(self):
+        with open(self.path, "rb") as f:
+            return list(f.read())
+
+    @_wrap_os_error
+    def writeAsStringSync(self, contents, mode=FileMode.write, encoding=None, flush=False):
+        with open(self.path, mode, encoding="utf-8", newline="") as f:
+            f.write(contents)
+
+    @_wrap_os_error
+    def writeAsBytesSync(self, bytes_, mode=FileMode.write, flush=False):
+        with open(self.path, mode + "b") as f:
+            f.write(bytes(bytes_))
+
+    @_wrap_os_error
+    def lengthSync(self):
+        return _os.path.getsize(self.path)
+
+    @_wrap_os_error
+    def deleteSync(self, recursive=False):
+        _os.remove(self.path)
+
+    @_wrap_os_error
+    def copySync(self, newPath):
+        _shutil.copyfile(self.path, newPath)
+        return File(newPath)
+
+    @_wrap_os_error
+    def renameSync(self, newPath):
+        _os.replace(self.path, newPath)
+        return File(newPath)
+
+
+class _DirectoryType(type):
+    @property
+    def current(cls):
+        return Directory(_os.getcwd())
+
+    @property
+    def systemTemp(cls):
+        return Directory(_tempfile.gettempdir())
+
+
+class Directory(FileSystemEntity, metaclass=_DirectoryType):
+    def existsSync(self):
+        return _os.path.isdir(self.path)
+
+    @_wrap_os_error
+    def createSync(self, recursive=False):
+        if recursive:
+            _os.makedirs(self.path, exist_ok=True)
+        elif not _os.path.isdir(self.path):
+            _os.mkdir(self.path)
+
+    @_wrap_os_error
+    def deleteSync(self, recursive=False):
+        if recursive:
+            _shutil.rmtree(self.path)
+        else:
+            _os.rmdir(self.path)
+
+    @_wrap_os_error
+    def listSync(self, recursive=False, followLinks=True):
+        result = []
+        for root, dirs, files in _os.walk(self.path, followlinks=followLinks):
+            result += [Directory(_os.path.join(root, d)) for d in sorted(dirs)]
+            result += [File(_os.path.join(root, f)) for f in sorted(files)]
+            if not recursive:
+                break
+        return result
+
+
+class _Stdout:
+    def __init__(self, stream):
+        self._stream = stream
+
+    def _target(self):
+        return _sys.stdout if self._stream == "stdout" else _sys.stderr
+
+    def write(self, obj):
+        self._target().write(_dart_str(obj))
+
+    def writeln(self, obj=""):
+        self._target().write(_dart_str(obj) + "\n")
+
+    def writeAll(self, objects, sep=""):
+        self._target().write(sep.join(_dart_str(o) for o in objects))
+
+    def writeCharCode(self, charCode):
+        self._target().write(chr(charCode))
+
+    def flush(self):
+        self._target().flush()
+
+
+stdout = _Stdout("stdout")
+stderr = _Stdout("stderr")
+
+
+class _Stdin:
+    def readLineSync(self, encoding=None, retainNewlines=False):
+        line = _sys.stdin.readline()
+        if line == "":
+            return None
+        return line if retainNewlines else line.rstrip("\r\n")
+
+
+stdin = _Stdin()
+
+
+def _operating_system():
+    if _sys.platform.startswith("linux"):
+        return "linux"
+    if _sys.platform == "darwin":
+        return "macos"
+    if _sys.platform in ("win32", "cygwin"):
+        return "windows"
+    return _sys.platform
+
+
+class Platform:
+    operatingSystem = _operating_system()
+    isLinux = operatingSystem == "linux"
+    isMacOS = operatingSystem == "macos"
+    isWindows = operatingSystem == "windows"
+    pathSeparator = _os.sep
+    numberOfProcessors = _os.cpu_count()
+    environment = dict(_os.environ)
+    executable = _sys.executable
+    version = _sys.version
+    localHostname = _os.uname().nodename if hasattr(_os, "uname") else ""
+
+
+def exit(code):
+    _sys.stdout.flush()
+    _sys.exit(code)
@@ -0,0 +1,120 @@
+# dart:math のシム
+import math as _math
+import random as _random
+
+__all__ = [
+    "e",
+    "ln10",
+    "ln2",
+    "log10e",
+    "log2e",
+    "pi",
+    "sqrt1_2",
+    "sqrt2",
+    "acos",
+    "asin",
+    "atan",
+    "atan2",
+    "cos",
+    "exp",
+    "log",
+    "max",
+    "min",
+    "pow",
+    "sin",
+    "sqrt",
+    "tan",
+    "Random",
+]
+
+e = _math.e
+ln10 = _math.log(10)
+ln2 = _math.log(2)
+log10e = 1 / _math.log(10)
+log2e = 1 / _math.log(2)
+pi = _math.pi
+sqrt1_2 = _math.sqrt(0.5)
+sqrt2 = _math.sqrt(2)
+
+
+def _nan_if_invalid(f):
+    # Dartでは定義域外の引数でも例外にならずNaNを返す
+    def wrapper(*args):
+        try:
+            return f(*args)
+        except ValueError:
+            return _math.nan
+
+    wrapper.__name__ = f.__name__
+    return wrapper
+
+
+acos = _nan_if_invalid(_math.acos)
+asin = _nan_if_invalid(_math.asin)
+atan = _math.atan
+atan2 = _math.atan2
+cos = _math.cos
+exp = _math.exp
+sin = _math.sin
+sqrt = _nan_if_invalid(_math.sqrt)
+tan = _math.tan
+
+
+def log(x):
+    if x == 0:
+        return -_math.inf
+    try:
+        return _math.log(x)
+    except ValueError:
+        return _math.nan
+
+
+def _is_int(value):
+    return isinstance(value, int) and not isinstance(value, bool)
+
+
+def max(a, b):
+    if isinstance(a, float) and _math.isnan(a):
+        return a
+    if isinstance(b, float) and _math.isnan(b):
+        return b
+    return b if b > a else a
+
+
+def min(a, b):
+    if isinstance(a, float) and _math.isnan(a):
+        return a
+    if isinstance(b, float) and _math.isnan(b):
+        return b
+    return b if b < a else a
+
+
+def pow(x, exponent):
+    if _is_int(x) and _is_int(exponent) and exponent >= 0:
+        return x**exponent
+    try:
+        return _math.pow(x, exponent)
+    except (ValueError, OverflowError):
+        return _math.nan if x < 0 else _math.inf
+
+
+class Random:
+    def __init__(self, seed=None):
+        self._random = _random.Random(seed)
+
+    @staticmethod
+    def secure():
+        random = Random()
+        random._random = _random.SystemRandom()
+        return random
+
+    def nextInt(self, max):
+        if max <= 0:
+            raise ValueError("RangeError: max must be in range 0 < max ≤ 2^32, was " + str(max))
+        return self._random.randrange(max)
+
+    def nextDouble(self):
+        return self._random.random()
+
+    def nextBool(self):
+        return self._random.random() < 0.5
@@ -0,0 +1,84 @@
+// CPython 3.9の_Py_KeyedHashと同じSipHash-2-4
+// https://github.com/python/cpython/blob/3.9/Python/pyhash.c
+// (SipHash-1-3に変わったのは3.11から)
+
+const C_ROUNDS: usize = 2;
+const D_ROUNDS: usize = 4;
+
+struct State {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+}
+
+impl State {
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        for _ in 0..C_ROUNDS {
+            self.round();
+        }
+        self.v0 ^= m;
+    }
+}
+
+pub fn siphash(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut state = State {
+        v0: k0 ^ 0x736f6d6570736575,
+        v1: k1 ^ 0x646f72616e646f6d,
+        v2: k0 ^ 0x6c7967656e657261,
+        v3: k1 ^ 0x7465646279746573,
+    };
+
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        state.compress(u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    // 残りのバイトと、最上位のバイトに長さを入れる
+    let mut last = (data.len() as u64) << 56;
+    for (i, &v) in chunks.remainder().iter().enumerate() {
+        last |= (v as u64) << (8 * i);
+    }
+    state.compress(last);
+
+    state.v2 ^= 0xff;
+    for _ in 0..D_ROUNDS {
+        state.round();
+    }
+    state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_as_cpython() {
+        // _imp.source_hash(importlib.util._RAW_MAGIC_NUMBER, data)の値
+        let key = 0x0A0D0D61;
+        let cases: [(&[u8], [u8; 8]); 3] = [
+            (b"", [0xac, 0x0b, 0xb7, 0xc8, 0x3e, 0x5b, 0x1c, 0x5f]),
+            (b"abc", [0x36, 0x1f, 0x00, 0x27, 0xdb, 0xc6, 0xdb, 0x99]),
+            (
+                b"hello world, this is a test of the hash",
+                [0x5e, 0x8b, 0x98, 0x77, 0x5d, 0xac, 0xa5, 0x49],
+            ),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(siphash(key, 0, data).to_le_bytes(), expected);
+        }
+    }
+}
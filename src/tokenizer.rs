@@ -2,6 +2,7 @@ use anyhow::{bail, Result};
 
 use dart_parser_generator::grammar::END;
 use regex::Regex;
+use serde::Serialize;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum TokenKind {
@@ -24,6 +25,52 @@ pub enum TokenKind {
 pub struct Token<'input> {
     pub kind: TokenKind,
     pub str: &'input str,
+    // strの先頭の位置
+    pub position: Position,
+}
+
+/// ソースコード上の位置
+/// 行と列は1始まりで、列はバイトではなく文字で数える
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// この位置から始まるtextの直後の位置
+    pub fn after(self, text: &str) -> Position {
+        match text.rfind('\n') {
+            Some(i) => Position {
+                line: self.line + text.matches('\n').count(),
+                column: text[i + 1..].chars().count() + 1,
+            },
+            None => Position {
+                line: self.line,
+                column: self.column + text.chars().count(),
+            },
+        }
+    }
+}
+
+// トークンの位置を先頭から順に求める
+struct PositionTracker<'input> {
+    input: &'input str,
+    index: usize,
+    position: Position,
+}
+
+impl PositionTracker<'_> {
+    fn at(&mut self, index: usize) -> Position {
+        // トークンは前から順に作るので、前回の位置から数えればよい
+        if index < self.index {
+            self.index = 0;
+            self.position = Position { line: 1, column: 1 };
+        }
+        self.position = self.position.after(&self.input[self.index..index]);
+        self.index = index;
+        self.position
+    }
 }
 
 impl Token<'_> {
@@ -83,10 +130,11 @@ pub const OTHER_IDENTIFIER: [&'static str; 8] = [
     "async", "hide", "of", "on", "show", "sync", "await", "yield",
 ];
 
-const SYMBOLS: [&'static str; 49] = [
-    "<<=", ">>=", "??=", "~/=", "??", "&&", "||", "==", "!=", "<<", ">>", ">=", "<=", "*=", "/=",
-    "%=", "+=", "-=", "&=", "^=", "|=", "=>", "~/", "++", "--", "?", ":", ">", ";", "=", "{", "}",
-    "<", "!", "~", "|", "^", "&", "+", "-", "*", "/", "%", "(", ")", ",", ".", "[", "]",
+const SYMBOLS: [&'static str; 52] = [
+    ">>>=", ">>>", "<<=", ">>=", "??=", "~/=", "??", "&&", "||", "==", "!=", "<<", ">>", ">=",
+    "<=", "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=", "=>", "~/", "++", "--", "?", ":", ">",
+    ";", "=", "{", "}", "<", "!", "~", "|", "^", "&", "+", "-", "*", "/", "%", "(", ")", ",", ".",
+    "[", "]", "@",
 ];
 
 enum StringInterpolationKind {
@@ -99,6 +147,11 @@ enum StringInterpolationKind {
 pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut current_index = 0;
+    let mut tracker = PositionTracker {
+        input,
+        index: 0,
+        position: Position { line: 1, column: 1 },
+    };
 
     let regex_whitespace = Regex::new(r"^[\t\n\r ]+").unwrap();
     let regex_single_comment = Regex::new(r"^//[^\n]*").unwrap();
@@ -141,7 +194,8 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
     let regex_multi_line_string_dq_mid_end =
         Regex::new(r#"^\}(((\\\\)|(\\""")|(\\\$)|(\\)|(\$[a-zA-Z_])|[^\$]))*?""""#).unwrap();
 
-    let regex_number = Regex::new(r"^((0(x|X)[a-fA-F0-9]+)|((([0-9]+(\.[0-9]+)?((e|E)(\+|-)?[0-9]+)?)|(\.[0-9]+((e|E)(\+|-)?[0-9]+)?))))").unwrap();
+    // 数字の間には桁区切りの_を入れられる
+    let regex_number = Regex::new(r"^((0(x|X)[a-fA-F0-9]+(_+[a-fA-F0-9]+)*)|((([0-9]+(_+[0-9]+)*(\.[0-9]+(_+[0-9]+)*)?((e|E)(\+|-)?[0-9]+(_+[0-9]+)*)?)|(\.[0-9]+(_+[0-9]+)*((e|E)(\+|-)?[0-9]+(_+[0-9]+)*)?))))").unwrap();
     let regex_identifier_or_keyword = Regex::new(r"^[a-zA-Z_\$][0-9a-zA-Z_\$]*").unwrap();
 
     let mut string_interpolation_stack: Vec<StringInterpolationKind> = Vec::new();
@@ -184,6 +238,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidMid,
                             str: &input[current_index + 1..current_index + string.end() - 2],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         continue 'tokenize;
@@ -196,6 +251,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidEnd,
                             str: &input[current_index + 1..current_index + string.end() - 1],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         string_interpolation_stack.pop();
@@ -210,6 +266,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidMid,
                             str: &input[current_index + 1..current_index + string.end() - 2],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         continue 'tokenize;
@@ -222,6 +279,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidEnd,
                             str: &input[current_index + 1..current_index + string.end() - 1],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         string_interpolation_stack.pop();
@@ -236,6 +294,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidMid,
                             str: &input[current_index + 1..current_index + string.end() - 2],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         continue 'tokenize;
@@ -247,6 +306,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidEnd,
                             str: &input[current_index + 1..current_index + string.end() - 3],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         string_interpolation_stack.pop();
@@ -261,6 +321,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidMid,
                             str: &input[current_index + 1..current_index + string.end() - 2],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         continue 'tokenize;
@@ -272,6 +333,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                         tokens.push(Token {
                             kind: TokenKind::StringMidEnd,
                             str: &input[current_index + 1..current_index + string.end() - 3],
+                            position: tracker.at(current_index + 1),
                         });
                         current_index += string.end();
                         string_interpolation_stack.pop();
@@ -289,6 +351,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginEnd,
                     str: &input[current_index + 4 + first_spaces..current_index + string.end() - 3],
+                    position: tracker.at(current_index + 4 + first_spaces),
                 });
                 current_index += string.end();
                 continue 'tokenize;
@@ -301,6 +364,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginEnd,
                     str: &input[current_index + 2..current_index + string.end() - 1],
+                    position: tracker.at(current_index + 2),
                 });
                 current_index += string.end();
                 continue 'tokenize;
@@ -314,6 +378,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginEnd,
                     str: &input[current_index + 3 + first_spaces..current_index + string.end() - 3],
+                    position: tracker.at(current_index + 3 + first_spaces),
                 });
                 current_index += string.end();
                 continue 'tokenize;
@@ -327,6 +392,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginEnd,
                     str: &input[current_index + 3 + first_spaces..current_index + string.end() - 3],
+                    position: tracker.at(current_index + 3 + first_spaces),
                 });
                 current_index += string.end();
                 continue 'tokenize;
@@ -340,6 +406,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginMid,
                     str: &input[current_index + 3 + first_spaces..current_index + string.end() - 2],
+                    position: tracker.at(current_index + 3 + first_spaces),
                 });
                 current_index += string.end();
                 string_interpolation_stack.push(StringInterpolationKind::TripleSingleQuote);
@@ -354,6 +421,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginMid,
                     str: &input[current_index + 3 + first_spaces..current_index + string.end() - 2],
+                    position: tracker.at(current_index + 3 + first_spaces),
                 });
                 current_index += string.end();
                 string_interpolation_stack.push(StringInterpolationKind::TripleDoubleQuote);
@@ -367,6 +435,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginEnd,
                     str: &input[current_index + 1..current_index + string.end() - 1],
+                    position: tracker.at(current_index + 1),
                 });
                 current_index += string.end();
                 continue 'tokenize;
@@ -379,6 +448,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginEnd,
                     str: &input[current_index + 1..current_index + string.end() - 1],
+                    position: tracker.at(current_index + 1),
                 });
                 current_index += string.end();
                 continue 'tokenize;
@@ -391,6 +461,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginMid,
                     str: &input[current_index + 1..current_index + string.end() - 2],
+                    position: tracker.at(current_index + 1),
                 });
                 current_index += string.end();
                 string_interpolation_stack.push(StringInterpolationKind::SingleQuote);
@@ -404,6 +475,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::StringBeginMid,
                     str: &input[current_index + 1..current_index + string.end() - 2],
+                    position: tracker.at(current_index + 1),
                 });
                 current_index += string.end();
                 string_interpolation_stack.push(StringInterpolationKind::DoubleQuote);
@@ -417,6 +489,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::Number,
                     str: &input[current_index..current_index + number.end()],
+                    position: tracker.at(current_index),
                 });
                 current_index += number.end();
                 continue 'tokenize;
@@ -432,6 +505,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                     tokens.push(Token {
                         kind: TokenKind::Boolean,
                         str: identifier_or_keyword,
+                        position: tracker.at(current_index),
                     });
                     current_index += identifier_or_keyword.len();
                     continue 'tokenize;
@@ -440,6 +514,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                     tokens.push(Token {
                         kind: TokenKind::Null,
                         str: identifier_or_keyword,
+                        position: tracker.at(current_index),
                     });
                     current_index += identifier_or_keyword.len();
                     continue 'tokenize;
@@ -448,6 +523,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                     tokens.push(Token {
                         kind: TokenKind::Keyword,
                         str: identifier_or_keyword,
+                        position: tracker.at(current_index),
                     });
                     current_index += identifier_or_keyword.len();
                     continue 'tokenize;
@@ -456,6 +532,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                     tokens.push(Token {
                         kind: TokenKind::BuiltInIdentifier,
                         str: identifier_or_keyword,
+                        position: tracker.at(current_index),
                     });
                     current_index += identifier_or_keyword.len();
                     continue 'tokenize;
@@ -464,6 +541,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                     tokens.push(Token {
                         kind: TokenKind::OtherIdentifier,
                         str: identifier_or_keyword,
+                        position: tracker.at(current_index),
                     });
                     current_index += identifier_or_keyword.len();
                     continue 'tokenize;
@@ -471,6 +549,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::Identifier,
                     str: identifier_or_keyword,
+                    position: tracker.at(current_index),
                 });
                 current_index += identifier_or_keyword.len();
                 continue 'tokenize;
@@ -483,6 +562,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
                 tokens.push(Token {
                     kind: TokenKind::Symbol,
                     str: symbol,
+                    position: tracker.at(current_index),
                 });
                 current_index += symbol.len();
                 continue 'tokenize;
@@ -494,6 +574,7 @@ pub fn tokenize<'input>(input: &'input str) -> Result<Vec<Token<'input>>> {
     tokens.push(Token {
         kind: TokenKind::EOF,
         str: "",
+        position: tracker.at(input.len()),
     });
     Ok(tokens)
 }
@@ -528,7 +609,7 @@ fn count_first_spaces(input: &str) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use crate::tokenizer::{tokenize, Token, TokenKind};
+    use crate::tokenizer::{tokenize, Position, Token, TokenKind};
 
     #[test]
     fn lexer() {
@@ -540,42 +621,67 @@ mod tests {
                 Token {
                     kind: TokenKind::Number,
                     str: "1",
+                    position: Position { line: 1, column: 1 },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "+",
+                    position: Position { line: 1, column: 3 },
                 },
                 Token {
                     kind: TokenKind::Number,
                     str: "2.3",
+                    position: Position { line: 1, column: 5 },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "*",
+                    position: Position { line: 1, column: 8 },
                 },
                 Token {
                     kind: TokenKind::Number,
                     str: ".9e+3",
+                    position: Position { line: 1, column: 9 },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "/",
+                    position: Position {
+                        line: 1,
+                        column: 14
+                    },
                 },
                 Token {
                     kind: TokenKind::Number,
                     str: "10.2e-20",
+                    position: Position {
+                        line: 1,
+                        column: 15
+                    },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "+",
+                    position: Position {
+                        line: 1,
+                        column: 24
+                    },
                 },
                 Token {
                     kind: TokenKind::Number,
                     str: "0x2A",
+                    position: Position {
+                        line: 1,
+                        column: 26
+                    },
                 },
                 Token {
                     kind: TokenKind::EOF,
-                    str: ""
+                    str: "",
+                    position: Position {
+                        line: 1,
+                        column: 30
+                    },
                 }
             ]
         );
@@ -588,34 +694,63 @@ mod tests {
                 Token {
                     kind: TokenKind::StringBeginEnd,
                     str: "hoge ho123.4",
+                    position: Position { line: 1, column: 2 },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "+",
+                    position: Position {
+                        line: 1,
+                        column: 16
+                    },
                 },
                 Token {
                     kind: TokenKind::Boolean,
                     str: "true",
+                    position: Position {
+                        line: 1,
+                        column: 18
+                    },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "+",
+                    position: Position {
+                        line: 1,
+                        column: 23
+                    },
                 },
                 Token {
                     kind: TokenKind::Boolean,
                     str: "false",
+                    position: Position {
+                        line: 1,
+                        column: 25
+                    },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "+",
+                    position: Position {
+                        line: 1,
+                        column: 31
+                    },
                 },
                 Token {
                     kind: TokenKind::Null,
                     str: "null",
+                    position: Position {
+                        line: 1,
+                        column: 32
+                    },
                 },
                 Token {
                     kind: TokenKind::EOF,
-                    str: ""
+                    str: "",
+                    position: Position {
+                        line: 1,
+                        column: 36
+                    },
                 }
             ]
         );
@@ -628,39 +763,76 @@ mod tests {
                 Token {
                     kind: TokenKind::Keyword,
                     str: "var",
+                    position: Position { line: 1, column: 1 },
                 },
                 Token {
                     kind: TokenKind::Identifier,
                     str: "truely",
+                    position: Position { line: 1, column: 5 },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: "=",
+                    position: Position {
+                        line: 1,
+                        column: 12
+                    },
                 },
                 Token {
                     kind: TokenKind::Boolean,
                     str: "true",
+                    position: Position {
+                        line: 1,
+                        column: 14
+                    },
                 },
                 Token {
                     kind: TokenKind::Symbol,
                     str: ";",
+                    position: Position {
+                        line: 1,
+                        column: 18
+                    },
                 },
                 Token {
                     kind: TokenKind::BuiltInIdentifier,
                     str: "as",
+                    position: Position {
+                        line: 1,
+                        column: 20
+                    },
                 },
                 Token {
                     kind: TokenKind::Keyword,
                     str: "finally",
+                    position: Position {
+                        line: 1,
+                        column: 23
+                    },
                 },
                 Token {
                     kind: TokenKind::EOF,
-                    str: ""
+                    str: "",
+                    position: Position {
+                        line: 1,
+                        column: 30
+                    },
                 }
             ]
         );
     }
 
+    #[test]
+    fn lexer_digit_separator() {
+        let result = tokenize("1_000_000 0xFF_FF 1__0.2_5e1_0 _1").unwrap();
+        let result_str = result.iter().map(|t| t.str).collect::<Vec<&str>>();
+        assert_eq!(
+            result_str,
+            vec!["1_000_000", "0xFF_FF", "1__0.2_5e1_0", "_1", ""]
+        );
+        assert_eq!(result[3].kind, TokenKind::Identifier);
+    }
+
     #[test]
     fn lexer_string() {
         let result = tokenize(r#"'abcd' + "efgh""#).unwrap();
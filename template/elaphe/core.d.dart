@@ -1,6 +1,40 @@
 /// create slice object
 external dynamic sl([int? start, int? end, int? step]);
 
+/// convert a value to String in the same format as Dart's toString()
+external String dartStr(Object? value);
+
+// dart:core members supported by the runtime library.
+// Dart analyzers already know these types, so they are listed here only for
+// reference. Members not listed below are looked up on the Python object.
+//
+// String: length, isEmpty, isNotEmpty, codeUnits, runes, substring, contains,
+//   startsWith, endsWith, indexOf, lastIndexOf, toUpperCase, toLowerCase,
+//   trim, trimLeft, trimRight, split, replaceAll, replaceFirst, padLeft,
+//   padRight, codeUnitAt, compareTo, String.fromCharCode(s)
+// Iterable: length, isEmpty, isNotEmpty, first, last, single, where, map,
+//   expand, skip, take, skipWhile, takeWhile, followedBy, cast, toList, toSet,
+//   forEach, any, every, contains, fold, reduce, join, elementAt, firstWhere,
+//   lastWhere, Iterable.generate, Iterable.empty
+// List: Iterable members, reversed, add, addAll, insert, insertAll, remove,
+//   removeAt, removeLast, removeWhere, retainWhere, clear, indexOf,
+//   lastIndexOf, indexWhere, sort, shuffle, sublist, getRange, fillRange,
+//   asMap, List.filled, List.generate, List.empty, List.of, List.from
+// Set: Iterable members, add, addAll, remove, removeAll, removeWhere,
+//   retainWhere, containsAll, union, intersection, difference, clear,
+//   Set.of, Set.from
+// Map: length, isEmpty, isNotEmpty, keys, values, entries, containsKey,
+//   containsValue, remove, putIfAbsent, update, updateAll, forEach, addAll,
+//   addEntries, removeWhere, map, clear, cast, Map.of, Map.from,
+//   Map.fromIterables, Map.fromEntries
+// num: isNaN, isInfinite, isFinite, isNegative, isEven, isOdd, sign,
+//   bitLength, abs, ceil, floor, round, truncate, ceilToDouble,
+//   floorToDouble, roundToDouble, truncateToDouble, toInt, toDouble, clamp,
+//   compareTo, remainder, gcd, modPow, toRadixString, toStringAsFixed,
+//   int.parse, int.tryParse, double.parse, double.tryParse, num.parse,
+//   num.tryParse
+// StringBuffer, MapEntry, StateError, FormatException
+
 // built-in functions in python 3.9
 external abs(x);
 external bool all(iterable);
@@ -49,3 +83,52 @@ external sum(iterable, {int start});
 // external super([type, object_or_type]);
 external vars([object]);
 external zip(iter1, [iter2, iter3, iter4, iter5]);
+
+// built-in classes in python 3.9
+class bytearray {}
+class bytes {}
+class classmethod {}
+class complex {}
+class dict {}
+class float {}
+class frozenset {}
+class list {}
+class object {}
+class property {}
+class range {}
+class slice {}
+class staticmethod {}
+class str {}
+class tuple {}
+class type {}
+
+// built-in exceptions in python 3.9
+class BaseException {}
+class Exception {}
+class ArithmeticError {}
+class AssertionError {}
+class AttributeError {}
+class EOFError {}
+class FileExistsError {}
+class FileNotFoundError {}
+class ImportError {}
+class IndexError {}
+class IOError {}
+class KeyError {}
+class KeyboardInterrupt {}
+class LookupError {}
+class MemoryError {}
+class ModuleNotFoundError {}
+class NameError {}
+class NotImplementedError {}
+class OSError {}
+class OverflowError {}
+class RecursionError {}
+class RuntimeError {}
+class StopIteration {}
+class SystemExit {}
+class TypeError {}
+class UnicodeDecodeError {}
+class UnicodeEncodeError {}
+class ValueError {}
+class ZeroDivisionError {}
@@ -1,5 +1,7 @@
+use std::cell::RefCell;
 use std::path::Path;
 use std::process::Command;
+use std::time::SystemTime;
 use std::{fs, str};
 
 use anyhow::{bail, Context, Result};
@@ -67,6 +69,63 @@ fn calc_hex() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn calc_big_int() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(&output, "main() { print(0xFFFFFFFF); }")?;
+    exec_py_and_assert(&output, "4294967295\n")?;
+    elaphe::build_from_code_single(&output, "main() { print(9007199254740993); }")?;
+    exec_py_and_assert(&output, "9007199254740993\n")?;
+    elaphe::build_from_code_single(&output, "main() { print(1 << 40); }")?;
+    exec_py_and_assert(&output, "1099511627776\n")?;
+    elaphe::build_from_code_single(&output, "main() { print(1_000_000 + 0xFF_FF); }")?;
+    exec_py_and_assert(&output, "1065535\n")?;
+    elaphe::build_from_code_single(
+        &output,
+        "main() { print(-123456789012345678901234567890 + 0x7FFF_FFFF_FFFF_FFFF); }",
+    )?;
+    exec_py_and_assert(&output, "-123456789003122306864379792083\n")?;
+    elaphe::build_from_code_single(&output, "main() { print(1_0.2_5e1_0); }")?;
+    exec_py_and_assert(&output, "102500000000.0\n")?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn calc_dart_numeric() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let options = elaphe::BuildOptions {
+        numeric_semantics: elaphe::NumericSemantics::Dart,
+        ..Default::default()
+    };
+    elaphe::build_from_code_single_with_options(
+        &output,
+        "main() { var a = -7; var b = 2; print(a ~/ b); print(a % b); print(a.remainder(b)); }",
+        &options,
+    )?;
+    exec_py_and_assert(&output, "-3\n1\n-1\n")?;
+    elaphe::build_from_code_single_with_options(
+        &output,
+        "main() { var a = 7; print(a % (0 - 2)); print((0 - 7.5) ~/ 2); print(a / 0); print(-a / 0); print(0 / 0); }",
+        &options,
+    )?;
+    exec_py_and_assert(&output, "1\n-3\nInfinity\n-Infinity\nNaN\n")?;
+    elaphe::build_from_code_single_with_options(
+        &output,
+        "main() { var a = -16; a ~/= 3; print(a); a %= 3; print(a); print(-1 >>> 60); }",
+        &options,
+    )?;
+    exec_py_and_assert(&output, "-5\n1\n15\n")?;
+    // >>> はモードに関係なく使える
+    elaphe::build_from_code_single(&output, "main() { var a = -8; a >>>= 61; print(a); }")?;
+    exec_py_and_assert(&output, "7\n")?;
+    // 既定ではPythonの演算子のまま
+    elaphe::build_from_code_single(&output, "main() { print(-7 ~/ 2); }")?;
+    exec_py_and_assert(&output, "-4\n")?;
+    clean(&output);
+    Ok(())
+}
+
 #[test]
 fn calc_boolean() -> Result<()> {
     let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
@@ -144,6 +203,45 @@ fn string_interpolation() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn dart_to_string() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        "main() { print(null); print(1.0); print(1e21); print(0.000001); }",
+    )?;
+    exec_py_and_assert(&output, "null\n1.0\n1e+21\n0.000001\n")?;
+    elaphe::build_from_code_single(
+        &output,
+        "main() { print([1, null, 'a', 2.5]); print({'a': [true], 'b': {1}}); }",
+    )?;
+    exec_py_and_assert(&output, "[1, null, a, 2.5]\n{a: [true], b: {1}}\n")?;
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        class Point {
+            String toString() {
+              return "Point!";
+            }
+        }
+        class Empty {}
+
+        main() {
+            var p = Point();
+            print(p);
+            print("${p} ${[p]} ${null} ${-0.0}");
+            print(Empty());
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "Point!\nPoint! [Point!] null -0.0\nInstance of 'Empty'\n",
+    )?;
+    clean(&output);
+    Ok(())
+}
+
 #[test]
 fn string_escape() -> Result<()> {
     let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
@@ -164,17 +262,17 @@ fn string_escape() -> Result<()> {
 fn compare_op() -> Result<()> {
     let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
     elaphe::build_from_code_single(&output, "main() { print(1 == 2); }")?;
-    exec_py_and_assert(&output, "False\n")?;
+    exec_py_and_assert(&output, "false\n")?;
     elaphe::build_from_code_single(&output, "main() { print(1 != 2); }")?;
-    exec_py_and_assert(&output, "True\n")?;
+    exec_py_and_assert(&output, "true\n")?;
     elaphe::build_from_code_single(&output, "main() { print(1 >= 2); }")?;
-    exec_py_and_assert(&output, "False\n")?;
+    exec_py_and_assert(&output, "false\n")?;
     elaphe::build_from_code_single(&output, "main() { print(1.3 < 2.1); }")?;
-    exec_py_and_assert(&output, "True\n")?;
+    exec_py_and_assert(&output, "true\n")?;
     elaphe::build_from_code_single(&output, "main() { print(1 > 2); }")?;
-    exec_py_and_assert(&output, "False\n")?;
+    exec_py_and_assert(&output, "false\n")?;
     elaphe::build_from_code_single(&output, "main() { print(1 <= 2); }")?;
-    exec_py_and_assert(&output, "True\n")?;
+    exec_py_and_assert(&output, "true\n")?;
     clean(&output);
     Ok(())
 }
@@ -216,7 +314,7 @@ fn unary_op() -> Result<()> {
     elaphe::build_from_code_single(&output, "main() { print(~2); }")?;
     exec_py_and_assert(&output, "-3\n")?;
     elaphe::build_from_code_single(&output, "main() { print(!(1!=2)); }")?;
-    exec_py_and_assert(&output, "False\n")?;
+    exec_py_and_assert(&output, "false\n")?;
     clean(&output);
     Ok(())
 }
@@ -499,9 +597,9 @@ fn conditional_expression() -> Result<()> {
 fn logical_expression() -> Result<()> {
     let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
     elaphe::build_from_code_single(&output, "main() { var x = 1; print(x == 1 && x == 2); }")?;
-    exec_py_and_assert(&output, "False\n")?;
+    exec_py_and_assert(&output, "false\n")?;
     elaphe::build_from_code_single(&output, "main() { var x = 2; print(x == 1 || x == 2); }")?;
-    exec_py_and_assert(&output, "True\n")?;
+    exec_py_and_assert(&output, "true\n")?;
     elaphe::build_from_code_single(&output, "main() { var x = null; print(x ?? 10); }")?;
     exec_py_and_assert(&output, "10\n")?;
     elaphe::build_from_code_single(&output, "main() { var x = 1; print(x ?? 10); }")?;
@@ -678,6 +776,45 @@ fn try_statement() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn catch_binding() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        main() {
+            try {
+              throw ValueError("typed");
+            } on ValueError catch (e) {
+              print(e);
+            }
+            try {
+              throw KeyError("untyped");
+            } catch (e) {
+              print(e);
+            }
+            try {
+              throw ValueError("trace");
+            } on ValueError catch (e, s) {
+              print(e);
+              print(s.tb_lineno > 0);
+            }
+            try {
+              throw ValueError("unmatched");
+            } on KeyError catch (e, s) {
+              print("KeyError");
+            } catch (e, s) {
+              print(e);
+              print(s.tb_lineno > 0);
+            }
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "typed\n'untyped'\ntrace\ntrue\nunmatched\ntrue\n")?;
+    clean(&output);
+    Ok(())
+}
+
 #[test]
 fn collection_literal() -> Result<()> {
     let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
@@ -686,7 +823,7 @@ fn collection_literal() -> Result<()> {
     elaphe::build_from_code_single(&output, "main() { print({1,2,3}); }")?;
     exec_py_and_assert(&output, "{1, 2, 3}\n")?;
     elaphe::build_from_code_single(&output, "main() { print({'a':1, 'b':2}); }")?;
-    exec_py_and_assert(&output, "{'a': 1, 'b': 2}\n")?;
+    exec_py_and_assert(&output, "{a: 1, b: 2}\n")?;
     clean(&output);
     Ok(())
 }
@@ -904,9 +1041,9 @@ fn slice() -> Result<()> {
 fn type_as_is() -> Result<()> {
     let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
     elaphe::build_from_code_single(&output, "main() { print(3 is int); }")?;
-    exec_py_and_assert(&output, "True\n")?;
+    exec_py_and_assert(&output, "true\n")?;
     elaphe::build_from_code_single(&output, "main() { print(2 is! int); }")?;
-    exec_py_and_assert(&output, "False\n")?;
+    exec_py_and_assert(&output, "false\n")?;
     elaphe::build_from_code_single(&output, "main() { var x = 1; print(x as int); }")?;
     exec_py_and_assert(&output, "1\n")?;
     elaphe::build_from_code_single(&output, "main() { var as = 1; print(as); }")?;
@@ -914,3 +1051,1370 @@ fn type_as_is() -> Result<()> {
     clean(&output);
     Ok(())
 }
+
+#[test]
+fn dart_core_library() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        main() {
+            String s = "Hello, world";
+            print(s.length);
+            print(s.substring(7));
+            print(s.split(", "));
+            print(s.toUpperCase().startsWith("HELLO"));
+            print(s.padLeft(14, "*"));
+            print("x" is String);
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "12\nworld\n[Hello, world]\ntrue\n**Hello, world\ntrue\n",
+    )?;
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        bool isOdd(int x) {
+            return x % 2 == 1;
+        }
+        int square(int x) {
+            return x * x;
+        }
+        main() {
+            var list = [3, 1, 4, 1, 5];
+            list.add(9);
+            print(list.length);
+            var odd = list.where(isOdd);
+            print(odd);
+            list.add(7);
+            print(odd.map(square).toList());
+            print(list.first);
+            print(list.isEmpty);
+            print(list.indexOf(4));
+            print(list.contains(2));
+            print(list.join("-"));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "6\n(3, 1, 1, 5, 9)\n[9, 1, 1, 25, 81, 49]\n3\nfalse\n2\nfalse\n3-1-4-1-5-9-7\n",
+    )?;
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        int two() {
+            return 2;
+        }
+        main() {
+            var map = {"a": 1};
+            print(map.containsKey("a"));
+            map.putIfAbsent("b", two);
+            print(map.keys);
+            print(map.length);
+            var set = {1, 2};
+            print(set.add(2));
+            print(int.parse("42") + 1);
+            print(double.tryParse("x"));
+            print(3.7.round());
+            print(2.5.toStringAsFixed(2));
+            var buffer = StringBuffer();
+            buffer.write("a");
+            buffer.write(1);
+            buffer.writeln(null);
+            print(buffer.toString().trim());
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "true\n(a, b)\n2\nfalse\n43\nnull\n4\n2.50\na1null\n",
+    )?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn dart_core_members_by_static_type() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        class Bag {
+            List items = [];
+
+            void put(String name) {
+                items.add(name.toUpperCase());
+            }
+        }
+
+        int count(List xs) => xs.length;
+
+        main() {
+            // Pythonの組み込み関数としても呼べる
+            print(int("42") + 1);
+            print(bool(1));
+
+            // 型の分からないPythonのオブジェクトはそのままメソッドを呼ぶ
+            var m = dict(a: 1, b: 2);
+            print(list(m.keys()));
+            m.update({"c": 3});
+            print(len(m));
+            var s = str("a  b");
+            print(s.split());
+
+            var bag = Bag();
+            bag.put("x");
+            bag.put("y");
+            print(bag.items.join(","));
+            print(count([1, 2, 3]));
+            for (String w in ["p", "q"]) {
+                print(w.padLeft(2, "-"));
+            }
+            print(m.toString());
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "43\ntrue\n[a, b]\n3\n[a, b]\nX,Y\n3\n-p\n-q\n{a: 1, b: 2, c: 3}\n",
+    )?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn import_dart_libraries() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'dart:math';
+        import 'dart:convert' as convert;
+
+        main() {
+            print(sqrt(16));
+            print(max(3, 7));
+            print(pow(2, 10));
+            print(pi > 3.14);
+            var random = Random(42);
+            var n = random.nextInt(10);
+            print(n >= 0 && n < 10);
+            var encoded = convert.jsonEncode({"a": [1, 2.5, null, true]});
+            print(encoded);
+            print(convert.jsonDecode(encoded)["a"]);
+            print(convert.base64Encode(convert.utf8.encode("elaphe")));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "4.0\n7\n1024\ntrue\ntrue\n{\"a\":[1,2.5,null,true]}\n[1, 2.5, null, true]\nZWxhcGhl\n",
+    )?;
+    let text_file = format!("{}.txt", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        &format!(
+            r#"
+            import 'dart:io';
+
+            main() {{
+                var file = File("{}");
+                print(file.existsSync());
+                file.writeAsStringSync("line1\nline2");
+                print(file.readAsLinesSync());
+                stdout.write(1.0);
+                stdout.writeln(null);
+                file.deleteSync();
+                print(file.existsSync());
+            }}
+            "#,
+            text_file
+        ),
+    )?;
+    exec_py_and_assert(&output, "false\n[line1, line2]\n1.0null\nfalse\n")?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn import_combinators() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'dart:math' show sqrt, pi;
+
+        main() {
+            Map names = globals();
+            print(sqrt(4));
+            print(pi > 3);
+            print(names.containsKey("max"));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "2.0\ntrue\nfalse\n")?;
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'dart:math' hide sqrt, max;
+
+        main() {
+            Map names = globals();
+            print(min(1, 2));
+            print(names.containsKey("sqrt"));
+            print(names.containsKey("max"));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "1\nfalse\nfalse\n")?;
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'dart:math' as math show pi, sqrt hide sqrt;
+
+        main() {
+            print(math.pi > 3);
+            print(hasattr(math, "sqrt"));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "true\nfalse\n")?;
+    assert!(elaphe::build_from_code_single(
+        &output,
+        "import 'dart:math' show undefinedName; main() {}"
+    )
+    .is_err());
+
+    // メタデータの中の名前はライブラリが宣言した名前ではない
+    let library = format!("lib_{}.dart", Uuid::new_v4().simple());
+    fs::write(
+        &library,
+        "var app;\n\n@app.route('/x')\nindex() {\n  return 'index';\n}\n",
+    )?;
+    let error = elaphe::build_from_code_single(
+        &output,
+        &format!("import '{}' show route; main() {{}}", library),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!("'route' is not declared in {}", library)
+    );
+    for file in [&output, &library] {
+        clean(file);
+    }
+    Ok(())
+}
+
+#[test]
+fn library_parts_and_exports() -> Result<()> {
+    let id = Uuid::new_v4().simple().to_string();
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let part = format!("part_{}.dart", id);
+    let library = format!("lib_{}.dart", id);
+    let barrel = format!("barrel_{}.dart", id);
+    fs::write(
+        &part,
+        r#"
+        part of app;
+
+        int twice(int x) {
+            return x * 2;
+        }
+        "#,
+    )?;
+    fs::write(
+        &library,
+        r#"
+        int add(int a, int b) {
+            return a + b;
+        }
+
+        int sub(int a, int b) {
+            return a - b;
+        }
+        "#,
+    )?;
+    fs::write(
+        &barrel,
+        format!(
+            r#"
+            library barrel;
+
+            export 'dart:math' show pi;
+            export '{}' show add;
+            "#,
+            library
+        ),
+    )?;
+
+    elaphe::build_from_code_single(
+        &output,
+        &format!(
+            r#"
+            library app;
+
+            import '{}';
+
+            part '{}';
+
+            main() {{
+                Map names = globals();
+                print(twice(add(1, 2)));
+                print(pi > 3);
+                print(names.containsKey("sub"));
+            }}
+            "#,
+            barrel, part
+        ),
+    )?;
+    exec_py_and_assert(&output, "6\ntrue\nfalse\n")?;
+
+    // partファイルは単体でコンパイルできない
+    assert!(elaphe::build_from_code_single(&output, "part of app; main() {}").is_err());
+    // part ofの名前が一致しない
+    assert!(elaphe::build_from_code_single(
+        &output,
+        &format!("library other; part '{}'; main() {{}}", part)
+    )
+    .is_err());
+    // partファイルの中のエラーはそのファイルの行と列で報告する
+    fs::write(&part, "part of app;\n\nint bad() => '€' + missing;\n")?;
+    let error = elaphe::build_from_code_single(
+        &output,
+        &format!("library app;\npart '{}';\nmain() {{ print('€€€'); }}", part),
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "3:20: undefined name 'missing'");
+
+    for file in [&output, &part, &library, &barrel] {
+        clean(file);
+    }
+    for file in [&library, &barrel] {
+        clean(Path::new(file).with_extension("pyc").to_str().unwrap());
+    }
+    Ok(())
+}
+
+#[test]
+fn deferred_import() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'elaphe/sys.d.dart' as sys;
+        import 'dart:math' deferred as math show sqrt;
+
+        main() {
+            Map modules = sys.modules;
+            print(modules.containsKey("elaphe_dart_math"));
+            try {
+                math.sqrt(4);
+            } on StateError {
+                print("not loaded");
+            }
+            math.loadLibrary();
+            print(modules.containsKey("elaphe_dart_math"));
+            print(math.sqrt(4));
+            print(hasattr(math, "pi"));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "false\nnot loaded\ntrue\n2.0\nfalse\n")?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn conditional_import() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'elaphe/math.d.dart'
+            if (python.no_such_module_elaphe) 'elaphe/no_such_module_elaphe.d.dart' as m;
+        import 'elaphe/no_such_module_elaphe.d.dart'
+            if (python.no_such_module_elaphe) 'elaphe/no_such_module_elaphe.d.dart'
+            if (python.json.decoder) 'elaphe/json.d.dart' as js;
+        import 'elaphe/math.d.dart' if (dart.library.io) 'dart:io' as io;
+
+        main() {
+            print(m.sqrt(4));
+            print(js.dumps([1, 2]));
+            print(hasattr(io, "exit"));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "2.0\n[1, 2]\ntrue\n")?;
+    assert!(elaphe::build_from_code_single(
+        &output,
+        "import 'elaphe/math.d.dart' if (unknown.test) 'elaphe/json.d.dart'; main() {}"
+    )
+    .is_err());
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn try_control_flow() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        int returnInTry() {
+            try {
+                return 1;
+            } finally {
+                print("finally 1");
+            }
+        }
+
+        int nestedFinally() {
+            try {
+                try {
+                    return 2;
+                } finally {
+                    print("inner");
+                }
+            } finally {
+                print("outer");
+            }
+        }
+
+        int returnInFinally() {
+            try {
+                return 3;
+            } finally {
+                return 4;
+            }
+        }
+
+        int breakAndContinue() {
+            var n = 0;
+            for (var i = 0; i < 4; i++) {
+                try {
+                    if (i == 1) {
+                        continue;
+                    }
+                    if (i == 3) {
+                        break;
+                    }
+                    n += 10;
+                } finally {
+                    n += 1;
+                }
+            }
+            return n;
+        }
+
+        int returnInForIn() {
+            for (var x in [1, 2, 3]) {
+                try {
+                    if (x == 2) {
+                        return x * 10;
+                    }
+                } finally {
+                    print("x = $x");
+                }
+            }
+            return 0;
+        }
+
+        int returnInCatch() {
+            try {
+                try {
+                    throw StateError("error");
+                } on StateError catch (e) {
+                    return 5;
+                }
+            } finally {
+                print("finally 5");
+            }
+        }
+
+        int labeledBreak() {
+            var count = 0;
+            outer:
+            for (var i = 0; i < 3; i++) {
+                for (var j = 0; j < 3; j++) {
+                    try {
+                        if (j == 1) {
+                            break outer;
+                        }
+                    } finally {
+                        count++;
+                    }
+                }
+            }
+            return count;
+        }
+
+        int returnInSwitch(int x) {
+            for (var y in [x]) {
+                switch (y) {
+                    case 1:
+                        try {
+                            return 6;
+                        } finally {
+                            print("switch");
+                        }
+                }
+            }
+            return 0;
+        }
+
+        void rethrowInCatch() {
+            try {
+                try {
+                    throw StateError("error");
+                } catch (e) {
+                    rethrow;
+                }
+            } on StateError {
+                print("rethrown");
+            }
+        }
+
+        main() {
+            print(returnInTry());
+            print(nestedFinally());
+            print(returnInFinally());
+            print(breakAndContinue());
+            print(returnInForIn());
+            print(returnInCatch());
+            print(labeledBreak());
+            print(returnInSwitch(1));
+            rethrowInCatch();
+
+            // ブロックスタックが壊れていれば繰り返すうちに失敗する
+            var sum = 0;
+            for (var i = 0; i < 100; i++) {
+                sum += returnInCatch() + labeledBreak() + breakAndContinue();
+            }
+            print(sum);
+        }
+        "#,
+    )?;
+    let expected = [
+        "finally 1",
+        "1",
+        "inner",
+        "outer",
+        "2",
+        "4",
+        "24",
+        "x = 1",
+        "x = 2",
+        "20",
+        "finally 5",
+        "5",
+        "2",
+        "switch",
+        "6",
+        "rethrown",
+    ]
+    .join("\n");
+    let repeated = "finally 5\n".repeat(100);
+    exec_py_and_assert(&output, &format!("{}\n{}3100\n", expected, repeated))?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn block_scope() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        main() {
+            var x = 1;
+            {
+                var x = 2;
+                print(x);
+                x = 3;
+            }
+            print(x);
+
+            if (true) {
+                var y = "a";
+                print(y);
+            } else {
+                var y = "b";
+                print(y);
+            }
+
+            for (var i = 0; i < 2; i++) {
+                var x;
+                print(x);
+                x = i;
+            }
+            for (var i in [5, 6]) {
+                print(i);
+            }
+            print(x);
+
+            try {
+                throw StateError("error");
+            } catch (e) {
+                var x = 4;
+                print(x);
+            }
+            print(x);
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "2\n1\na\nnull\nnull\n5\n6\n1\n4\n1\n")?;
+    assert!(elaphe::build_from_code_single(&output, "main() { var x = 1; var x = 2; }").is_err());
+    assert!(elaphe::build_from_code_single(&output, "main() { try {} catch (e, e) {} }").is_err());
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn name_resolution() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'dart:math' show sqrt;
+
+        external dynamic breakpoint();
+        external String __name__;
+
+        main() {
+            print(twice(sqrt(4)));
+            print(counter);
+            print(__name__);
+            print(range(2).stop);
+            try {
+                throw KeyError("k");
+            } on KeyError {
+                print("KeyError");
+            }
+        }
+
+        twice(v) => v * 2;
+        var counter = 1;
+        "#,
+    )?;
+    exec_py_and_assert(&output, "4.0\n1\n__main__\n2\nKeyError\n")?;
+
+    let error =
+        elaphe::build_from_code_single(&output, "main() {\n  var x = 1;\n  pritn(x);\n  y = x;\n}")
+            .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "3:3: undefined name 'pritn'\n4:3: undefined name 'y'"
+    );
+    assert!(
+        elaphe::build_from_code_single(&output, "main() { { var x = 1; } print(x); }").is_err()
+    );
+    assert!(elaphe::build_from_code_single(
+        &output,
+        "import 'dart:math' show pi; main() { print(sqrt(pi)); }"
+    )
+    .is_err());
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn metadata_decorator() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        import 'elaphe/functools.d.dart' as functools;
+
+        var registry = [];
+
+        register(cls) {
+            registry.add(cls);
+            return cls;
+        }
+
+        @functools.lru_cache(maxsize: 16)
+        int fib(int n) => n < 2 ? n : fib(n - 1) + fib(n - 2);
+
+        @register
+        class Greeter {
+            String name = "elaphe";
+
+            @override
+            String toString() => "Greeter(" + name + ")";
+
+            @staticmethod
+            int add(int a, int b) => a + b;
+        }
+
+        void main() {
+            print(fib(30));
+            print(fib.cache_info().hits);
+            print(registry.length);
+            print(Greeter());
+            print(Greeter.add(1, 2));
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "832040\n28\n1\nGreeter(elaphe)\n3\n")?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn abstract_class() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        abstract class Shape {
+            String label = "shape";
+            double area();
+            String describe() => label + ": " + area().toString();
+        }
+
+        class Rect extends Shape {
+            double w = 2.0;
+            double h = 3.0;
+            double area() => w * h;
+        }
+
+        class Square extends Rect {
+            Square(double s) {
+                w = s;
+                h = s;
+                label = "square";
+            }
+        }
+
+        main() {
+            print(Rect().describe());
+            print(Square(4.0).describe());
+            print(Square(1.0) is Shape);
+            var f = Shape;
+            try {
+                f();
+            } on TypeError {
+                print("TypeError");
+            }
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "shape: 6.0\nsquare: 16.0\ntrue\nTypeError\n")?;
+
+    let error = elaphe::build_from_code_single(
+        &output,
+        "abstract class A {\n  f();\n}\nclass B extends A {}\nmain() {\n  A();\n}",
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "4:7: missing concrete implementation of 'f' in non-abstract class 'B'\n6:3: abstract class 'A' cannot be instantiated"
+    );
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn super_constructor_initializer() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        abstract class Shape {
+            String name;
+            Shape(String name, {String unit = "m"}) {
+                this.name = name + " (" + unit + ")";
+            }
+            double area();
+        }
+
+        class Sq extends Shape {
+            double side = 1.0;
+            Sq(double side) : super("square") {
+                this.side = side;
+            }
+            double area() => side * side;
+        }
+
+        class Circle extends Shape {
+            double r;
+            Circle(double r) : super("circle", unit: "cm");
+            double area() => 3.0;
+        }
+
+        main() {
+            var s = Sq(2.0);
+            print(s.name);
+            print(s.area());
+            print(Circle(1.0).name);
+        }
+        "#,
+    )?;
+    exec_py_and_assert(&output, "square (m)\n4.0\ncircle (cm)\n")?;
+
+    // 引数の必要なスーパークラスのコンストラクタを暗黙に呼ぶことはできない
+    let error = elaphe::build_from_code_single(
+        &output,
+        "class A {\n  A(int x) {}\n}\nclass B extends A {\n  B() {}\n}\nmain() {\n  B();\n}",
+    )
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "4:7: the superclass 'A' of 'B' has no zero-argument constructor; call it with ': super(...)'"
+    );
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn object_members() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    elaphe::build_from_code_single(
+        &output,
+        r#"
+        class Point {
+            int x;
+            int y;
+            Point(int x, int y) {
+                this.x = x;
+                this.y = y;
+            }
+
+            String toString() => "Point($x, $y)";
+
+            int get hashCode => x * 31 + y;
+
+            bool operator ==(Object other) => other is Point && other.x == x && other.y == y;
+        }
+
+        class Adder {
+            int base = 10;
+            int call(int v) => base + v;
+        }
+
+        class Ghost {
+            dynamic noSuchMethod(Invocation invocation) {
+                return invocation.memberName + invocation.positionalArguments.toString();
+            }
+        }
+
+        class Id {
+            int v = 1;
+            bool operator ==(Object other) => other is Id && other.v == v;
+        }
+
+        class Minus {
+            int get hashCode => -1;
+        }
+
+        main() {
+            var p = Point(1, 2);
+            print([p, Point(3, 4)]);
+            print(p == Point(1, 2));
+            print(p != Point(1, 2));
+            print(p.hashCode);
+            print({p, Point(1, 2)}.length);
+            print(Adder()(5));
+            print(Ghost().fly(1, 2));
+            print(p.runtimeType);
+            print(1.5.runtimeType);
+            print({Id(), Id()}.length);
+            // hash()を通さずにゲッターの値を返す
+            print(Minus().hashCode);
+            var ghost = Ghost();
+            print(ghost.hashCode == ghost.hashCode);
+        }
+        "#,
+    )?;
+    exec_py_and_assert(
+        &output,
+        "[Point(1, 2), Point(3, 4)]\ntrue\nfalse\n33\n1\n15\nfly[1, 2]\nPoint\ndouble\n2\n-1\ntrue\n",
+    )?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn optimization_levels() -> Result<()> {
+    let source = r#"
+        f(a, b) {
+            var x = 1 + 2 * 3 - -4;
+            var s = "ab" + "cd";
+            if (!(a && b) || x > 10) {
+                return s;
+            }
+            while (true) {
+                if (a) break;
+            }
+            return x * 0.5;
+        }
+
+        main() {
+            print(f(true, false));
+            print(f(true, true));
+            for (var i in [1, 2]) {
+                try {
+                    if (i == 1) continue;
+                    print(i);
+                } finally {
+                    print("finally");
+                }
+            }
+        }
+        "#;
+    for opt_level in [
+        elaphe::OptLevel::O0,
+        elaphe::OptLevel::O1,
+        elaphe::OptLevel::O2,
+    ] {
+        let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+        let options = elaphe::BuildOptions {
+            opt_level,
+            ..Default::default()
+        };
+        elaphe::build_from_code_single_with_options(&output, source, &options)?;
+        exec_py_and_assert(&output, "abcd\nabcd\nfinally\n2\nfinally\n")?;
+        clean(&output);
+    }
+    Ok(())
+}
+
+#[test]
+fn deduplicate_constants_and_names() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let source = r#"
+        main() {
+            var x = 1;
+            print(x + 1);
+            print(1.0);
+            print(true);
+            print("abc");
+            print("abc");
+            x = x + 1;
+            print(x);
+        }
+        "#;
+    elaphe::build_from_code_single(&output, source)?;
+    exec_py_and_assert(&output, "2\n1.0\ntrue\nabc\nabc\n2\n")?;
+
+    // 定数表と名前表に同じ値が2回現れないこと
+    let check = format!(
+        r#"python -c "
+import marshal
+with open('{}', 'rb') as f:
+    f.read(16)
+    module = marshal.load(f)
+main = next(c for c in module.co_consts if getattr(c, 'co_name', '') == 'main')
+print(main.co_consts)
+print(main.co_names)
+""#,
+        output
+    );
+    let result = Command::new("bash").args(&["-c", &check]).output()?;
+    assert_eq!(
+        "(1, 1.0, True, 'abc', None)\n('__elaphe__', 'print')\n",
+        str::from_utf8(&result.stdout)?
+    );
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn many_constants_and_names() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    // 256個を超える定数、名前、ローカル変数はEXTENDED_ARGで参照する
+    let globals: String = (0..300).map(|i| format!("var g{} = {};\n", i, i)).collect();
+    let locals: String = (0..300)
+        .map(|i| format!("var v{} = {};\n", i, i + 1000))
+        .collect();
+    let source = format!(
+        r#"
+        {}
+        main() {{
+            {}
+            print(g299);
+            print(v299);
+            var total = 0;
+            for (var i = 0; i < 3; i++) {{
+                try {{
+                    total += v0 + v299;
+                }} finally {{
+                    total += 1;
+                }}
+            }}
+            print(total);
+        }}
+        "#,
+        globals, locals
+    );
+    elaphe::build_from_code_single(&output, &source)?;
+    exec_py_and_assert(&output, "299\n1299\n6900\n")?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn constant_sets() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let source = r#"
+        f(x) {
+            return {1, 2, 3}.contains(x);
+        }
+
+        main() {
+            print(f(2));
+            print(f(5));
+            print({"a", "b"}.contains("b"));
+            var s = const {1, 2, 3};
+            print(s.contains(3));
+            print(s.length);
+            var t = {1, 2};
+            t.add(3);
+            print(t);
+            print({true, 1}.contains(true));
+        }
+        "#;
+    elaphe::build_from_code_single(&output, source)?;
+    exec_py_and_assert(&output, "true\nfalse\ntrue\ntrue\n3\n{1, 2, 3}\ntrue\n")?;
+    clean(&output);
+    Ok(())
+}
+
+#[test]
+fn disassemble_output() -> Result<()> {
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let source = r#"
+        f(a) {
+            var x = 0;
+            while (x < a) {
+                x += 1;
+            }
+            return {1, 2}.contains(x);
+        }
+
+        main() {
+            print(f(2));
+        }
+        "#;
+    elaphe::build_from_code_single(&output, source)?;
+    let result = elaphe::disassemble_pyc(&fs::read(&output)?)?;
+    clean(&output);
+
+    let f = result
+        .split("\n\n")
+        .find(|v| v.starts_with("Disassembly of f:"))
+        .context("f is not disassembled")?;
+    let expected = "Disassembly of f:
+  1           0 LOAD_CONST               0 (0)
+              2 STORE_FAST               1 (x)
+        >>    4 LOAD_FAST                1 (x)
+              6 LOAD_FAST                0 (a)
+              8 COMPARE_OP               0 (<)
+             10 POP_JUMP_IF_FALSE       26 (to 26)
+             12 LOAD_FAST                1 (x)
+             14 LOAD_CONST               1 (1)
+             16 INPLACE_ADD
+             18 DUP_TOP
+             20 STORE_FAST               1 (x)
+             22 POP_TOP
+             24 JUMP_ABSOLUTE            4 (to 4)
+        >>   26 LOAD_FAST                1 (x)
+             28 LOAD_CONST               2 (frozenset({1, 2}))
+             30 CONTAINS_OP              0 (in)
+             32 RETURN_VALUE";
+    assert_eq!(expected, f);
+    assert!(elaphe::disassemble_pyc(&[0; 8]).is_err());
+    Ok(())
+}
+
+#[test]
+fn emit_intermediate_representations() -> Result<()> {
+    let source = r#"
+        f(a) {
+            while (a > 0) {
+                a -= 1;
+            }
+            return a;
+        }
+
+        main() {
+            print(f(2));
+        }
+        "#;
+    let options = elaphe::BuildOptions::default();
+
+    let tokens = elaphe::emit_from_code(source, elaphe::Emit::Tokens, &options)?;
+    assert!(tokens.starts_with("Identifier \"f\"\nSymbol \"(\"\n"));
+
+    let cst = elaphe::emit_from_code(source, elaphe::Emit::Cst, &options)?;
+    assert!(cst.starts_with("LibraryDeclaration\n"));
+    assert!(cst.contains("\n              Symbol \"(\"\n"));
+
+    let ast = elaphe::emit_from_code(source, elaphe::Emit::Ast, &options)?;
+    assert!(ast.starts_with("LibraryDeclaration {\n"));
+    assert!(ast.contains("FunctionDeclaration {"));
+
+    let ast_json = elaphe::emit_from_code(source, elaphe::Emit::AstJson, &options)?;
+    let value: serde_json::Value = serde_json::from_str(&ast_json)?;
+    assert_eq!(
+        value["top_level_declaration_list"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+
+    let bytecode = elaphe::emit_from_code(source, elaphe::Emit::Bytecode, &options)?;
+    let f = bytecode
+        .split_once("main:")
+        .context("main is not emitted")?
+        .0;
+    assert_eq!(
+        f,
+        "f:
+  L10:
+    LOAD_FAST            0
+    LOAD_CONST           0
+    COMPARE_OP           4
+    POP_JUMP_IF_FALSE    L3
+    LOAD_FAST            0
+    LOAD_CONST           1
+    INPLACE_SUBTRACT
+    DUP_TOP
+    STORE_FAST           0
+    POP_TOP
+    JUMP_ABSOLUTE        L10
+  L3:
+    LOAD_FAST            0
+    RETURN_VALUE
+"
+    );
+    assert!(bytecode.ends_with("    RETURN_VALUE\n"));
+
+    // ビルドと同じコンパイルで中間表現を出力し、コンパイルに失敗しても構文木は残る
+    let id = Uuid::new_v4().simple().to_string();
+    let source_file = format!("emit_{}.dart", id);
+    let output = format!("emit_{}.pyc", id);
+    fs::write(&source_file, "main() {\n  print(undefinedName);\n}\n")?;
+    let listing = RefCell::new(String::new());
+    let result = elaphe::build_from_file_with_emit(
+        &output,
+        &source_file,
+        SystemTime::now(),
+        &options,
+        elaphe::Emit::Ast,
+        &listing,
+    );
+    assert!(result.is_err());
+    assert!(listing.borrow().starts_with("LibraryDeclaration {\n"));
+
+    fs::write(&source_file, source)?;
+    let listing = RefCell::new(String::new());
+    elaphe::build_from_file_with_emit(
+        &output,
+        &source_file,
+        SystemTime::now(),
+        &options,
+        elaphe::Emit::Bytecode,
+        &listing,
+    )?;
+    assert_eq!(listing.into_inner(), bytecode);
+    exec_py_and_assert(&output, "0\n")?;
+    clean(&output);
+    fs::remove_file(&source_file)?;
+
+    Ok(())
+}
+
+#[test]
+fn pyc_invalidation_modes() -> Result<()> {
+    let id = Uuid::new_v4().simple().to_string();
+    let source_file = format!("source_{}.dart", id);
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let source = r#"
+        class A {
+            int x = 1;
+        }
+
+        main() {
+            var a = A();
+            print({1: "one", 2: "two"}[a.x]);
+        }
+        "#;
+    fs::write(&source_file, source)?;
+    let build = |mode| -> Result<Vec<u8>> {
+        let options = elaphe::BuildOptions {
+            invalidation_mode: mode,
+            ..Default::default()
+        };
+        elaphe::build_from_file(&output, &source_file, SystemTime::now(), true, &options)?;
+        Ok(fs::read(&output)?)
+    };
+
+    // 同じソースからは同じ.pycになる
+    let checked = build(elaphe::InvalidationMode::CheckedHash)?;
+    exec_py_and_assert(&output, "one\n")?;
+    assert_eq!(checked, build(elaphe::InvalidationMode::CheckedHash)?);
+    assert_eq!(checked[4..8], [3, 0, 0, 0]);
+
+    // Pythonが計算するハッシュと一致する
+    let py_command = format!(
+        "import _imp, importlib.util; print(_imp.source_hash(importlib.util._RAW_MAGIC_NUMBER, open('{}', 'rb').read()).hex())",
+        source_file
+    );
+    let py_output = Command::new("python").args(["-c", &py_command]).output()?;
+    let expected = str::from_utf8(&py_output.stdout)?.trim();
+    let actual: String = checked[8..16]
+        .iter()
+        .map(|v| format!("{:02x}", v))
+        .collect();
+    assert_eq!(expected, actual);
+
+    let unchecked = build(elaphe::InvalidationMode::UncheckedHash)?;
+    assert_eq!(unchecked[4..8], [1, 0, 0, 0]);
+    assert_eq!(unchecked[8..], checked[8..]);
+
+    // ソースの更新日時と大きさを書く
+    let timestamp = build(elaphe::InvalidationMode::Timestamp)?;
+    let mtime = fs::metadata(&source_file)?
+        .modified()?
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs() as u32;
+    assert_eq!(timestamp[4..8], [0, 0, 0, 0]);
+    assert_eq!(timestamp[8..12], mtime.to_le_bytes());
+    assert_eq!(timestamp[12..16], (source.len() as u32).to_le_bytes());
+    exec_py_and_assert(&output, "one\n")?;
+
+    clean(&output);
+    clean(&source_file);
+    Ok(())
+}
+
+#[test]
+fn incremental_build_cache() -> Result<()> {
+    let id = Uuid::new_v4().simple().to_string();
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let a = format!("a_{}.dart", id);
+    let b = format!("b_{}.dart", id);
+    let c = format!("c_{}.dart", id);
+    let c_part = format!("c_part_{}.dart", id);
+    let modules = [&a, &b, &c].map(|v| v.replace(".dart", ".pyc"));
+    fs::write(
+        &a,
+        format!("import '{}';\nint fa() {{ return fb() + 1; }}\n", b),
+    )?;
+    fs::write(&b, "int fb() { return 1; }\n")?;
+    fs::write(&c, format!("library c;\npart '{}';\n", c_part))?;
+    fs::write(&c_part, "part of c;\nint fc() { return 10; }\n")?;
+    let source = format!(
+        "import '{}';\nimport '{}';\nmain() {{ print(fa() + fc()); }}\n",
+        a, c
+    );
+    let build = || -> Result<Vec<SystemTime>> {
+        elaphe::build_from_code_single(&output, &source)?;
+        modules
+            .iter()
+            .map(|v| Ok(fs::metadata(v)?.modified()?))
+            .collect()
+    };
+
+    let first = build()?;
+    exec_py_and_assert(&output, "12\n")?;
+
+    // 何も変わっていなければビルドし直さない
+    assert_eq!(first, build()?);
+
+    // 変更したモジュールとそれをインポートするモジュールだけをビルドし直す
+    fs::write(&b, "int fb() { return 5; }\n")?;
+    let second = build()?;
+    exec_py_and_assert(&output, "16\n")?;
+    assert_ne!(first[0], second[0]);
+    assert_ne!(first[1], second[1]);
+    assert_eq!(first[2], second[2]);
+
+    // partのファイルの変更でもビルドし直す
+    fs::write(&c_part, "part of c;\nint fc() { return 20; }\n")?;
+    let third = build()?;
+    exec_py_and_assert(&output, "26\n")?;
+    assert_eq!(second[..2], third[..2]);
+    assert_ne!(second[2], third[2]);
+
+    clean(&output);
+    for file in modules.iter().chain([&a, &b, &c, &c_part]) {
+        clean(file);
+    }
+    Ok(())
+}
+
+#[test]
+fn parallel_module_build() -> Result<()> {
+    let id = Uuid::new_v4().simple().to_string();
+    let output = format!("{}.pyc", Uuid::new_v4().hyphenated().to_string());
+    let files: Vec<String> = (0..6).map(|i| format!("m{}_{}.dart", i, id)).collect();
+    // m0 → m1 → ... → m4の連鎖と、それとは独立したm5
+    for (i, file) in files.iter().enumerate().take(5) {
+        let import = match files.get(i + 1) {
+            Some(next) if i < 4 => format!("import '{}';", next),
+            _ => String::new(),
+        };
+        let next = if i < 4 {
+            format!("f{}()", i + 1)
+        } else {
+            "0".to_string()
+        };
+        fs::write(
+            file,
+            format!("{}\nint f{}() {{ return {} + {}; }}\n", import, i, next, i),
+        )?;
+    }
+    fs::write(&files[5], "int f5() { return 100; }\n")?;
+    let source = format!(
+        "import '{}';\nimport '{}';\nmain() {{ print(f0() + f5()); }}\n",
+        files[0], files[5]
+    );
+    elaphe::build_from_code_single(&output, &source)?;
+    exec_py_and_assert(&output, "110\n")?;
+    let pyc_files: Vec<String> = files.iter().map(|v| v.replace(".dart", ".pyc")).collect();
+    assert!(pyc_files.iter().all(|v| Path::new(v).exists()));
+
+    // ビルドに失敗したモジュールがあればエラーになる
+    fs::write(&files[3], "int f3() { return ; ; }\n")?;
+    assert!(elaphe::build_from_code_single(&output, &source).is_err());
+
+    clean(&output);
+    for file in files.iter().chain(&pyc_files) {
+        clean(file);
+    }
+    Ok(())
+}
+
+#[test]
+fn runtime_modules_next_to_output() -> Result<()> {
+    // 実行時ライブラリは.pycに埋め込まず、出力先に書き出したモジュールをインポートする
+    let directory = std::env::temp_dir().join(Uuid::new_v4().simple().to_string());
+    fs::create_dir(&directory)?;
+    let output = directory.join("main.pyc");
+    let output = output.to_str().unwrap();
+    elaphe::build_from_code_single(
+        output,
+        r#"
+        import 'dart:math';
+
+        main() {
+            print("hello");
+            print([3, 1, 2].reduce(max));
+        }
+        "#,
+    )?;
+    assert!(fs::metadata(output)?.len() < 1024);
+    assert!(directory.join("elaphe_core.py").exists());
+    assert!(directory.join("elaphe_dart_math.py").exists());
+    exec_py_and_assert(output, "hello\n3\n")?;
+
+    fs::remove_dir_all(&directory).ok();
+    Ok(())
+}